@@ -0,0 +1,531 @@
+//! Backbone extraction: keep the edges of a projected graph which are
+//! statistically significant under some null model rather than using one
+//! global strength cutoff.
+
+use crate::{
+  connection_strength::{ConnectionStrength, ConnectionStrengthValue},
+  curveball::{self, CurveballOptions},
+  dataset::Dataset,
+  progress_bar::get_bar,
+  projected_graph::ProjectedGraph,
+  stats::{beta_binomial_sf, binomial_sf, normal_sf},
+  ItemType,
+};
+use anyhow::{anyhow, Result};
+use indicatif::ProgressIterator;
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// How edge significance is assessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BackboneMethod {
+  /// Disparity filter (Serrano et al.): is the edge a larger share of an
+  /// endpoint's strength than expected if the strength were split uniformly
+  /// at random among its edges?
+  Disparity,
+  /// Stochastic degree sequence model: is the number of shared items larger
+  /// than expected if the bipartite network were drawn with each edge
+  /// independent and degrees matching in expectation (Chung-Lu, with edge
+  /// probabilities capped at 1)?
+  Sdsm,
+  /// Fixed degree sequence model: like SDSM, but the expectation is taken
+  /// over sampled bipartite networks with exactly the same degrees.
+  Fdsm,
+  /// Pólya urn filter (Marcaccioli and Livan): is the edge weight larger
+  /// than expected under a reinforced urn process with the same strengths?
+  PolyaUrn,
+}
+
+impl BackboneMethod {
+  #[must_use]
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Disparity => "disparity",
+      Self::Sdsm => "sdsm",
+      Self::Fdsm => "fdsm",
+      Self::PolyaUrn => "polya-urn",
+    }
+  }
+
+  /// Whether the method uses the edge strengths as weights rather than
+  /// counting shared items in the dataset.
+  #[must_use]
+  pub fn uses_weights(self) -> bool {
+    matches!(self, Self::Disparity | Self::PolyaUrn)
+  }
+
+  /// Errors if the method weighs edges by strength but `T` isn't a count:
+  /// the Pólya urn draws whole balls and the disparity filter splits a
+  /// nonnegative strength between edges.
+  pub fn check_strength<T: ConnectionStrength>(
+    self,
+    connection_strength: &T,
+  ) -> Result<()> {
+    if self.uses_weights() && !T::Value::IS_COUNT {
+      return Err(anyhow!(
+        "the {} backbone needs a count connection strength, not {:?}",
+        self.as_str(),
+        connection_strength
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+impl FromStr for BackboneMethod {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let out = match s {
+      "disparity" => Self::Disparity,
+      "sdsm" => Self::Sdsm,
+      "fdsm" => Self::Fdsm,
+      "polya-urn" => Self::PolyaUrn,
+      _ => return Err(format!("Unrecognized backbone method: {}", s)),
+    };
+
+    Ok(out)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackboneOptions {
+  /// Number of sampled networks for FDSM.
+  pub fdsm_samples: usize,
+  /// How FDSM networks are sampled.
+  pub curveball: CurveballOptions,
+  pub seed: u64,
+  /// Reinforcement parameter of the Pólya urn (0 is the binomial limit).
+  pub polya_reinforcement: f64,
+}
+
+impl Default for BackboneOptions {
+  fn default() -> Self {
+    Self {
+      fdsm_samples: 100,
+      curveball: CurveballOptions::default(),
+      seed: 0,
+      polya_reinforcement: 1.,
+    }
+  }
+}
+
+/// Sorted indexes of the items of the other type each item is connected to.
+fn neighbor_lists(item_type: ItemType, dataset: &Dataset) -> Vec<Vec<usize>> {
+  dataset.contribution_idxs()[item_type]
+    .iter()
+    .map(|contrib_idxs| {
+      let mut out: Vec<_> = contrib_idxs
+        .iter()
+        .map(|&i| dataset.contributions()[i].idx[item_type.other()])
+        .collect();
+      out.sort_unstable();
+      out
+    })
+    .collect()
+}
+
+fn num_common(l: &[usize], r: &[usize]) -> usize {
+  let (mut i, mut j, mut count) = (0, 0, 0);
+  while i < l.len() && j < r.len() {
+    match l[i].cmp(&r[j]) {
+      std::cmp::Ordering::Less => i += 1,
+      std::cmp::Ordering::Greater => j += 1,
+      std::cmp::Ordering::Equal => {
+        count += 1;
+        i += 1;
+        j += 1;
+      }
+    }
+  }
+  count
+}
+
+fn co_occurrences<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  neighbors: &[Vec<usize>],
+) -> Vec<usize> {
+  projected
+    .edges()
+    .par_iter()
+    .map(|e| {
+      let [a, b] = e.node_idxs;
+      num_common(&neighbors[a], &neighbors[b])
+    })
+    .collect()
+}
+
+fn weights<T: ConnectionStrength>(projected: &ProjectedGraph<T>) -> Vec<f64> {
+  projected
+    .edges()
+    .iter()
+    .map(|e| e.strength.clone().to_float())
+    .collect()
+}
+
+fn node_strengths<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  weights: &[f64],
+) -> Vec<f64> {
+  projected
+    .edge_idxs()
+    .iter()
+    .map(|idxs| idxs.iter().map(|&i| weights[i]).sum())
+    .collect()
+}
+
+fn disparity<T: ConnectionStrength>(projected: &ProjectedGraph<T>) -> Vec<f64> {
+  let weights = weights(projected);
+  let strengths = node_strengths(projected, &weights);
+
+  let p = |node: usize, w: f64| {
+    let degree = projected.edge_idxs()[node].len();
+    if degree <= 1 {
+      // a single edge always carries all the strength
+      1.
+    } else {
+      (1. - w / strengths[node]).max(0.).powi(degree as i32 - 1)
+    }
+  };
+
+  projected
+    .edges()
+    .iter()
+    .zip(&weights)
+    .map(|(e, &w)| {
+      let [a, b] = e.node_idxs;
+      p(a, w).min(p(b, w))
+    })
+    .collect()
+}
+
+/// Items of the other type grouped by degree, so the SDSM moments only have
+/// to visit the degrees high enough for an edge probability to be capped.
+struct DegreeBuckets {
+  num_edges: f64,
+  /// (degree, number of items with it), ascending
+  degrees: Vec<(f64, f64)>,
+  /// prefix sums of number * degree^2 and number * degree^4
+  sum_sqr: Vec<f64>,
+  sum_quad: Vec<f64>,
+}
+
+impl DegreeBuckets {
+  fn new(item_type: ItemType, dataset: &Dataset) -> Self {
+    let mut counts = BTreeMap::new();
+    for idxs in dataset.contribution_idxs()[item_type].iter() {
+      *counts.entry(idxs.len()).or_insert(0usize) += 1;
+    }
+    let degrees: Vec<_> = counts
+      .into_iter()
+      .map(|(degree, count)| (degree as f64, count as f64))
+      .collect();
+    let prefix_sums = |pow: i32| {
+      Some(0.)
+        .into_iter()
+        .chain(degrees.iter().scan(0., move |total, &(degree, count)| {
+          *total += count * degree.powi(pow);
+          Some(*total)
+        }))
+        .collect()
+    };
+
+    Self {
+      num_edges: dataset.contributions().len() as f64,
+      sum_sqr: prefix_sums(2),
+      sum_quad: prefix_sums(4),
+      degrees,
+    }
+  }
+
+  /// Mean and variance of the number of items shared by items with degrees
+  /// `a` and `b` when each edge (a, k) is present with probability
+  /// min(a * d_k / E, 1).
+  fn moments(&self, a: f64, b: f64) -> (f64, f64) {
+    let e = self.num_edges;
+    // below this degree neither probability is capped, so the sums are
+    // c * sum d^2 and c^2 * sum d^4
+    let split = self.degrees.partition_point(|&(d, _)| d * a.max(b) <= e);
+    let c = a * b / e.powi(2);
+    let mut mean = c * self.sum_sqr[split];
+    let mut sum_p_sqr = c.powi(2) * self.sum_quad[split];
+    for &(d, count) in &self.degrees[split..] {
+      let p = (a * d / e).min(1.) * (b * d / e).min(1.);
+      mean += count * p;
+      sum_p_sqr += count * p.powi(2);
+    }
+
+    (mean, (mean - sum_p_sqr).max(0.))
+  }
+}
+
+fn sdsm<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  item_type: ItemType,
+  dataset: &Dataset,
+) -> Vec<f64> {
+  let neighbors = neighbor_lists(item_type, dataset);
+  let observed = co_occurrences(projected, &neighbors);
+  let buckets = DegreeBuckets::new(item_type.other(), dataset);
+
+  projected
+    .edges()
+    .par_iter()
+    .zip(observed)
+    .map(|(e, observed)| {
+      let [a, b] = e.node_idxs.map(|idx| neighbors[idx].len() as f64);
+      let (mean, var) = buckets.moments(a, b);
+      if var == 0. {
+        if observed as f64 > mean {
+          0.
+        } else {
+          1.
+        }
+      } else {
+        // continuity corrected
+        normal_sf((observed as f64 - 0.5 - mean) / var.sqrt())
+      }
+    })
+    .collect()
+}
+
+fn fdsm<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  item_type: ItemType,
+  dataset: &Dataset,
+  options: &BackboneOptions,
+) -> Vec<f64> {
+  let observed = co_occurrences(projected, &neighbor_lists(item_type, dataset));
+  let mut num_exceeding = vec![0usize; observed.len()];
+
+  let mut rng = StdRng::seed_from_u64(options.seed);
+  let mut chain = curveball::Chain::new(dataset, &options.curveball);
+  let bar = get_bar(Some(options.fdsm_samples as u64), 1);
+  for _ in (0..options.fdsm_samples).progress_with(bar) {
+    let sample = chain.sample(&mut rng);
+    let sampled =
+      co_occurrences(projected, &neighbor_lists(item_type, &sample));
+    for ((count, sampled), observed) in
+      num_exceeding.iter_mut().zip(sampled).zip(&observed)
+    {
+      if sampled >= *observed {
+        *count += 1;
+      }
+    }
+  }
+
+  num_exceeding
+    .into_iter()
+    .map(|count| (count + 1) as f64 / (options.fdsm_samples + 1) as f64)
+    .collect()
+}
+
+fn polya_urn<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  options: &BackboneOptions,
+) -> Vec<f64> {
+  // the urn draws whole balls, so weights are rounded
+  let weights: Vec<_> =
+    weights(projected).into_iter().map(f64::round).collect();
+  let strengths = node_strengths(projected, &weights);
+  let total: f64 = strengths.iter().sum();
+  let a = options.polya_reinforcement;
+
+  let p = |w: f64, draws: f64, other: f64| {
+    if a == 0. {
+      // no reinforcement is the binomial limit
+      binomial_sf(w, draws, other / total)
+    } else {
+      beta_binomial_sf(w, draws, other / a, (total - other) / a)
+    }
+  };
+
+  projected
+    .edges()
+    .iter()
+    .zip(&weights)
+    .map(|(e, &w)| {
+      let [s_a, s_b] = e.node_idxs.map(|idx| strengths[idx]);
+      if s_a == total || s_b == total {
+        return 1.;
+      }
+      p(w, s_a, s_b).min(p(w, s_b, s_a))
+    })
+    .collect()
+}
+
+/// p-value of every edge of `projected` (same order as `edges()`) under
+/// `method`. Disparity and Pólya urn use the edge strengths as weights (so
+/// they need a count connection strength) while SDSM and FDSM use the number
+/// of shared items in `dataset`, so `projected` should contain every edge
+/// that might be kept. Disparity and Pólya urn test both endpoints and report
+/// the smaller p-value.
+pub fn edge_p_values<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  connection_strength: &T,
+  item_type: ItemType,
+  dataset: &Dataset,
+  method: BackboneMethod,
+  options: &BackboneOptions,
+) -> Result<Vec<f64>> {
+  method.check_strength(connection_strength)?;
+
+  Ok(match method {
+    BackboneMethod::Disparity => disparity(projected),
+    BackboneMethod::Sdsm => sdsm(projected, item_type, dataset),
+    BackboneMethod::Fdsm => fdsm(projected, item_type, dataset, options),
+    BackboneMethod::PolyaUrn => polya_urn(projected, options),
+  })
+}
+
+/// Keep the edges with p-value below `alpha`.
+#[must_use]
+pub fn backbone<T: ConnectionStrength>(
+  projected: &ProjectedGraph<T>,
+  num_items: usize,
+  p_values: &[f64],
+  alpha: f64,
+) -> ProjectedGraph<T> {
+  projected.filter_edges_by(num_items, |i, _| p_values[i] < alpha)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::{Jaccard, NumCommonNodes, TotalNumEvents},
+    dataset::{self, Contribution, Lens},
+    UserRepoPair,
+  };
+  use proptest::prelude::*;
+
+  #[test]
+  fn disparity_star() {
+    // user 0 shares repo 0 with users 1..4 and many repos with user 1
+    let mut contributions = Vec::new();
+    let mut add = |user, repo| {
+      contributions.push(Contribution {
+        idx: UserRepoPair { user, repo },
+        num: 1,
+      })
+    };
+    for user in 0..4 {
+      add(user, 0);
+    }
+    for repo in 1..20 {
+      add(0, repo);
+      add(1, repo);
+    }
+    let dataset =
+      Dataset::new(UserRepoPair { user: 4, repo: 20 }, contributions);
+
+    let projected = ProjectedGraph::from_dataset(
+      ItemType::User,
+      &NumCommonNodes,
+      &Default::default(),
+      &dataset,
+    );
+    let p_values = edge_p_values(
+      &projected,
+      &NumCommonNodes,
+      ItemType::User,
+      &dataset,
+      BackboneMethod::Disparity,
+      &Default::default(),
+    )
+    .unwrap();
+    let kept = backbone(&projected, 4, &p_values, 0.05);
+
+    assert_eq!(kept.edges().len(), 1);
+    assert_eq!(kept.edges()[0].node_idxs, [0, 1]);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_p_values(
+        dataset in dataset::small_strategy(),
+      ) {
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let projected = ProjectedGraph::from_dataset(
+            item_type,
+            &TotalNumEvents,
+            &Default::default(),
+            &dataset,
+          );
+          let options = BackboneOptions {
+            fdsm_samples: 5,
+            ..Default::default()
+          };
+          for method in [
+            BackboneMethod::Disparity,
+            BackboneMethod::Sdsm,
+            BackboneMethod::Fdsm,
+            BackboneMethod::PolyaUrn,
+          ] {
+            let p_values = edge_p_values(
+              &projected,
+              &TotalNumEvents,
+              item_type,
+              &dataset,
+              method,
+              &options,
+            )
+            .unwrap();
+            prop_assert_eq!(p_values.len(), projected.edges().len());
+            for &p in &p_values {
+              prop_assert!((0. ..=1.).contains(&p), "{:?} {}", method, p);
+            }
+            let num_items = dataset.lens()[item_type];
+            prop_assert_eq!(
+              backbone(&projected, num_items, &p_values, 1.1).edges().len(),
+              projected.edges().len()
+            );
+          }
+
+          let buckets = DegreeBuckets::new(item_type.other(), &dataset);
+          let e = dataset.contributions().len() as f64;
+          for (a, b) in [(1., 1.), (2., 5.), (e, 1.), (e, e)] {
+            let ps: Vec<_> = dataset.contribution_idxs()[item_type.other()]
+              .iter()
+              .map(|idxs| {
+                let d = idxs.len() as f64;
+                (a * d / e).min(1.) * (b * d / e).min(1.)
+              })
+              .collect();
+            let mean: f64 = ps.iter().sum();
+            let var: f64 = ps.iter().map(|p| p * (1. - p)).sum();
+            let (actual_mean, actual_var) = buckets.moments(a, b);
+            prop_assert!((actual_mean - mean).abs() < 1e-9);
+            prop_assert!((actual_var - var).abs() < 1e-9);
+          }
+
+          // fractional strengths can only be tested by shared items
+          let projected = ProjectedGraph::from_dataset(
+            item_type,
+            &Jaccard,
+            &Default::default(),
+            &dataset,
+          );
+          for method in [
+            BackboneMethod::Disparity,
+            BackboneMethod::Sdsm,
+            BackboneMethod::Fdsm,
+            BackboneMethod::PolyaUrn,
+          ] {
+            let p_values = edge_p_values(
+              &projected,
+              &Jaccard,
+              item_type,
+              &dataset,
+              method,
+              &options,
+            );
+            prop_assert_eq!(p_values.is_ok(), !method.uses_weights());
+          }
+        }
+      }
+  }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use github_net::{
+  output_data::csv_reader,
+  power_law::{fit_distribution, save_fits, FitOptions},
+};
+use rand::prelude::*;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+  name = "fit_distribution",
+  about = "fit power laws and alternatives to distributions saved as csv"
+)]
+struct Opt {
+  /// Csv with a column of values and (optionally) a column with the count of
+  /// each value, like the degree, contribution, strength and component size
+  /// distributions from process_data.
+  #[structopt(parse(from_os_str))]
+  path: PathBuf,
+
+  /// Columns to fit separately (for example user_size,repo_size). Defaults
+  /// to the first column.
+  #[structopt(long, use_delimiter = true)]
+  columns: Vec<String>,
+
+  /// Column with the number of times each value occurs. Defaults to "count"
+  /// if there is such a column, otherwise every row is one value.
+  #[structopt(long)]
+  count_column: Option<String>,
+
+  /// Multiply values by this before rounding to integers (for fractional
+  /// strengths).
+  #[structopt(long, default_value = "1")]
+  scale: f64,
+
+  /// Smallest number of values in the fitted tail.
+  #[structopt(long, default_value = "10")]
+  min_tail: usize,
+
+  /// Synthetic data sets for the goodness of fit p-value.
+  #[structopt(long, default_value = "100")]
+  num_bootstrap: usize,
+
+  /// Likelihood ratio p-values below this favor one of the distributions.
+  #[structopt(long, default_value = "0.1")]
+  significance: f64,
+
+  #[structopt(long, default_value = "0")]
+  seed: u64,
+
+  /// Also save the fits to this csv.
+  #[structopt(short, long, parse(from_os_str))]
+  output: Option<PathBuf>,
+}
+
+pub fn main() -> Result<()> {
+  let opt = Opt::from_args();
+
+  let mut reader = csv_reader(&opt.path)?;
+  let headers = reader.headers()?.clone();
+  let column_idx = |name: &str| {
+    headers
+      .iter()
+      .position(|header| header == name)
+      .ok_or_else(|| anyhow!("no column {} in {:?}", name, opt.path))
+  };
+  let columns = if opt.columns.is_empty() {
+    vec![headers
+      .get(0)
+      .ok_or_else(|| anyhow!("no columns in {:?}", opt.path))?
+      .to_owned()]
+  } else {
+    opt.columns.clone()
+  };
+  let column_idxs = columns
+    .iter()
+    .map(|name| column_idx(name))
+    .collect::<Result<Vec<_>>>()?;
+  let count_idx = match &opt.count_column {
+    Some(name) => Some(column_idx(name)?),
+    None => headers.iter().position(|header| header == "count"),
+  };
+
+  let mut values = vec![Vec::new(); columns.len()];
+  for record in reader.records() {
+    let record = record?;
+    let count = match count_idx {
+      Some(idx) => record[idx].parse::<usize>()?,
+      None => 1,
+    };
+    for (column_values, &idx) in values.iter_mut().zip(&column_idxs) {
+      let value = (record[idx].parse::<f64>()? * opt.scale).round();
+      if value.is_nan() || value < 0. {
+        return Err(anyhow!("{} is not a nonnegative value", &record[idx]));
+      }
+      column_values.extend(std::iter::repeat_n(value as usize, count));
+    }
+  }
+
+  let options = FitOptions {
+    min_tail: opt.min_tail,
+    num_bootstrap: opt.num_bootstrap,
+    significance: opt.significance,
+  };
+  let mut rng = StdRng::seed_from_u64(opt.seed);
+  let mut fits = Vec::new();
+  for (name, column_values) in columns.into_iter().zip(values) {
+    let fit = match fit_distribution(&column_values, &options, &mut rng) {
+      Some(fit) => fit,
+      None => {
+        println!("{}: fewer than {} positive values", name, opt.min_tail);
+        continue;
+      }
+    };
+
+    let power_law = &fit.power_law;
+    println!(
+      "{}: alpha {} xmin {} ({} of {} values in the tail) ks {}",
+      name,
+      power_law.alpha,
+      power_law.xmin,
+      power_law.num_tail,
+      power_law.num_total,
+      power_law.ks
+    );
+    if let Some(p_value) = fit.p_value {
+      println!("  goodness of fit p-value {}", p_value);
+    }
+    for (alternative, ratio) in &fit.alternatives {
+      println!(
+        "  vs {} ({}): log likelihood ratio {} p-value {} favors {}",
+        alternative.as_str(),
+        alternative.params(),
+        ratio.ratio,
+        ratio.p_value,
+        ratio.favored(alternative, opt.significance)
+      );
+    }
+
+    fits.push((name, fit));
+  }
+
+  if let Some(output) = &opt.output {
+    save_fits(output, &fits, opt.significance)?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,244 @@
+use anyhow::Result;
+use github_net::{
+  connection_strength::*,
+  dataset::{Dataset, DatasetInfo, Lens},
+  link_prediction::{
+    candidates, random_holdout, strength_scores, topology_scores, Candidates,
+    LinkPredictionCsvEntry, PairKind, TOPOLOGY_SCORERS,
+  },
+  monte_carlo::{MonteCarloNull, MonteCarloOptions, MonteCarloZ},
+  output_data::csv_writer,
+  ItemType, UserRepoPair,
+};
+use rand::prelude::*;
+use std::{fs, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+  name = "link_prediction",
+  about = "hold out contributions and evaluate how well different scores predict them"
+)]
+struct Opt {
+  /// Which pairs to predict: user-repo and/or repo-repo.
+  #[structopt(
+    long,
+    use_delimiter = true,
+    default_value = "user-repo,repo-repo"
+  )]
+  pair_kinds: Vec<PairKind>,
+
+  /// Fraction of contributions to hold out.
+  #[structopt(long, default_value = "0.1")]
+  holdout_fraction: f64,
+
+  /// Maximum number of held out pairs to score.
+  #[structopt(long, default_value = "1000")]
+  max_positives: usize,
+
+  /// Number of random pairs which aren't held out to score per held out
+  /// pair.
+  #[structopt(long, default_value = "10")]
+  negatives_per_positive: usize,
+
+  /// Number of highest scored pairs for precision.
+  #[structopt(short, default_value = "100")]
+  k: usize,
+
+  /// Connection strength types to also use as scores. Defaults to every
+  /// type, with each of its normalizations if it can be normalized.
+  #[structopt(long, use_delimiter = true)]
+  connection_str_types: Vec<ConnectionStrengthTypes>,
+
+  /// Number of null networks to sample for monte-carlo-z connection
+  /// strengths.
+  #[structopt(long, default_value = "100")]
+  monte_carlo_samples: usize,
+
+  #[structopt(long, default_value = "1287341")]
+  seed: u64,
+
+  #[structopt(short, long, default_value = "output_data/link_prediction.csv")]
+  output: PathBuf,
+
+  /// Maximum number of samples (mostly useful for testing).
+  #[structopt(short, long)]
+  limit: Option<usize>,
+
+  /// Eliminate users with very large contribution to remove (some) bots and
+  /// spammers.
+  #[structopt(long, default_value = "500000")]
+  max_user_contributions: usize,
+}
+
+fn run_unnormalized<T: ConnectionStrength>(
+  kind: PairKind,
+  train: &Dataset,
+  candidates: &Candidates,
+  connection_strength: T,
+) -> (String, Vec<f64>) {
+  (
+    format!("{:?}", connection_strength),
+    strength_scores(kind, train, candidates, &connection_strength),
+  )
+}
+
+fn run_strength<T: ConnectionStrength>(
+  kind: PairKind,
+  train: &Dataset,
+  candidates: &Candidates,
+  inner: T,
+  norm: Normalization,
+  monte_carlo: &MonteCarloOptions,
+) -> (String, Vec<f64>) {
+  let accelerators = &UserRepoPair::<()>::default()
+    .map_with(|_, item_type| ExpectationAccelerator::new(item_type, train));
+  match norm {
+    Normalization::None => run_unnormalized(kind, train, candidates, inner),
+    Normalization::Expectation => run_unnormalized(
+      kind,
+      train,
+      candidates,
+      Normalized {
+        inner,
+        accelerators,
+      },
+    ),
+    Normalization::ZScore => run_unnormalized(
+      kind,
+      train,
+      candidates,
+      ZScore {
+        inner,
+        accelerators,
+      },
+    ),
+    Normalization::MonteCarloZ => {
+      // pairs are only scored through the repo projection
+      let null = MonteCarloNull::new(
+        inner,
+        ItemType::Repo,
+        train,
+        &Default::default(),
+        monte_carlo,
+      );
+      run_unnormalized(kind, train, candidates, MonteCarloZ { null: &null })
+    }
+  }
+}
+
+pub fn main() -> Result<()> {
+  let opt = Opt::from_args();
+
+  let (_, dataset) =
+    DatasetInfo::load_limited(opt.limit, Some(opt.max_user_contributions))?;
+
+  println!("users: {}", dataset.lens().user);
+  println!("repos: {}", dataset.lens().repo);
+  println!("connections: {}", dataset.contributions().len());
+
+  let mut rng = StdRng::seed_from_u64(opt.seed);
+  let holdout = random_holdout(&dataset, opt.holdout_fraction, &mut rng);
+  println!("held out {} contributions", holdout.test.len());
+
+  if let Some(parent) = opt.output.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let mut writer = csv_writer(&opt.output)?;
+
+  let connection_str_types = if opt.connection_str_types.is_empty() {
+    ConnectionStrengthTypes::all()
+  } else {
+    opt.connection_str_types.clone()
+  };
+
+  for &kind in &opt.pair_kinds {
+    println!("running {} pairs", kind.as_str());
+    let candidates = candidates(
+      kind,
+      &dataset,
+      &holdout,
+      opt.max_positives,
+      opt.negatives_per_positive,
+      &mut rng,
+    );
+
+    let mut scorers: Vec<_> = TOPOLOGY_SCORERS
+      .iter()
+      .map(|name| name.to_string())
+      .zip(topology_scores(kind, &holdout.train, &candidates))
+      .collect();
+
+    for &t in &connection_str_types {
+      let train = &holdout.train;
+      let monte_carlo = &MonteCarloOptions {
+        num_samples: opt.monte_carlo_samples,
+        curveball: Default::default(),
+        seed: opt.seed,
+      };
+      let candidates = &candidates;
+      type Types = ConnectionStrengthTypes;
+      scorers.push(match t {
+        Types::NumCommonNodes(norm) => run_strength(
+          kind,
+          train,
+          candidates,
+          NumCommonNodes,
+          norm,
+          monte_carlo,
+        ),
+        Types::MinNumEvents(norm) => {
+          run_strength(kind, train, candidates, MinNumEvents, norm, monte_carlo)
+        }
+        Types::TotalNumEvents(norm) => run_strength(
+          kind,
+          train,
+          candidates,
+          TotalNumEvents,
+          norm,
+          monte_carlo,
+        ),
+        Types::GeometricMeanEvents(norm) => run_strength(
+          kind,
+          train,
+          candidates,
+          GeometricMeanEvents,
+          norm,
+          monte_carlo,
+        ),
+        Types::Hypergeometric => {
+          run_unnormalized(kind, train, candidates, Hypergeometric)
+        }
+        Types::Jaccard => run_unnormalized(kind, train, candidates, Jaccard),
+        Types::Cosine => run_unnormalized(kind, train, candidates, Cosine),
+        Types::Sorensen => run_unnormalized(kind, train, candidates, Sorensen),
+        Types::HubPromoted => {
+          run_unnormalized(kind, train, candidates, HubPromoted)
+        }
+        Types::HubDepressed => {
+          run_unnormalized(kind, train, candidates, HubDepressed)
+        }
+        Types::Newman => run_unnormalized(kind, train, candidates, Newman),
+        Types::ResourceAllocation => {
+          run_unnormalized(kind, train, candidates, ResourceAllocation)
+        }
+      });
+    }
+
+    for (scorer, scores) in scorers {
+      let entry =
+        LinkPredictionCsvEntry::new(kind, scorer, &candidates, &scores, opt.k);
+      println!(
+        "{}: auc {}, precision@{} {}, average precision {}",
+        entry.scorer,
+        entry.auc,
+        entry.k,
+        entry.precision_at_k,
+        entry.average_precision
+      );
+      writer.serialize(entry)?;
+    }
+  }
+
+  Ok(())
+}
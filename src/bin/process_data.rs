@@ -1,29 +1,59 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use github_net::{
-  component_sizes_csv::save_component_sizes,
-  configuration_model,
+  backbone::{backbone, edge_p_values, BackboneMethod, BackboneOptions},
+  centrality::{
+    betweenness, exact_closeness, sampled_closeness, save_closeness,
+    save_top_centrality, BetweennessMode,
+  },
+  clustering::robins_alexander_clustering,
+  component_sizes_csv::{
+    giant_component, save_component_membership, save_component_sizes,
+    save_component_summaries, ComponentSummaryOptions,
+  },
+  components::ComponentsMethod,
   connection_str_stats::save_connection_str_stats,
   connection_strength::*,
   contribution_dist_csv::{
     save_contribution_dist, save_contribution_dist_item,
   },
+  curveball::CurveballOptions,
   dataset::{Dataset, DatasetInfo, DatasetNameID, Lens},
   degree_dist_csv::save_degrees,
   distances::{average_distance, compute_pseudo_diameter},
+  ensemble::{save_ensemble, NetworkStats},
+  flat_graph::FlatGraph,
+  hubs::{save_hub_report, HubFilter, HubOptions, HubPolicy},
   item_name_to_save_name,
-  projected_graph::ProjectedGraph,
+  link_analysis::{hits, pagerank, LinkAnalysisOptions, LinkAnalysisResult},
+  manifest::Manifest,
+  monte_carlo::{
+    save_calibration, MonteCarloNull, MonteCarloOptions, MonteCarloZ,
+  },
+  null_models::{
+    ChungLu, ConfigurationModel, Curveball, DcSbm, ErdosRenyi, NullModel,
+    NullModelKind, StrengthPreserving,
+  },
+  percolation::{percolation_sweep, save_percolation},
+  projected_graph::{ProjectedGraph, ProjectionOptions},
   save_subgraph::save_subgraph,
-  traversal::Node,
+  saved_projection::{
+    load_projected_graph, load_projected_graph_header, save_projected_edges,
+    save_projected_graph, ProjectedGraphHeader,
+  },
+  sharded_projection::{project_to_disk, ShardOptions},
+  traversal::{Component, Node, ProjectedTraversalWorkspace},
   ItemType, UserRepoPair,
 };
 use rand::prelude::*;
+use serde::Serialize;
 use std::{
   fs,
   path::{Path, PathBuf},
+  time::Instant,
 };
 use structopt::StructOpt;
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Serialize)]
 #[structopt(
   name = "process_data",
   about = "load and process data, runs different computations depending on arguments"
@@ -37,10 +67,51 @@ struct Opt {
   #[structopt(long)]
   no_original_network: bool,
 
-  /// Also run analysis on the configuration model with the same degrees.
+  /// Also run analysis on the configuration model with the same degrees
+  /// (same as --null-models configuration-model).
   #[structopt(long)]
   use_configuration_model: bool,
 
+  /// Also run analysis on a network sampled from each of these null models:
+  /// configuration-model, curveball (exactly the same degrees), erdos-renyi
+  /// (same density), chung-lu (same expected degrees), strength-preserving
+  /// (same total contributions) or dc-sbm (degree corrected stochastic block
+  /// model of the communities in --communities-csv).
+  #[structopt(long, use_delimiter = true)]
+  null_models: Vec<NullModelKind>,
+
+  /// Csv with the item_type (user or repo), name and community of items for
+  /// the dc-sbm null model.
+  #[structopt(long, parse(from_os_str))]
+  communities_csv: Option<PathBuf>,
+
+  /// Rounds of the curveball chain (each trades every user once) from the
+  /// dataset to the first sample, for the curveball null model and the
+  /// sampled nulls (fdsm backbone and monte-carlo-z connection strengths).
+  #[structopt(long, default_value = "5")]
+  curveball_burn_in_rounds: usize,
+
+  /// Rounds of the curveball chain between consecutive samples of the fdsm
+  /// backbone and monte-carlo-z connection strengths.
+  #[structopt(long, default_value = "5")]
+  curveball_thinning_rounds: usize,
+
+  /// Make the curveball chain also keep the numbers of contributions of each
+  /// user (they're always kept for repos).
+  #[structopt(long)]
+  curveball_preserve_nums: bool,
+
+  /// Run the analyses on this many null networks (with different seeds) and
+  /// save the mean and percentile bands of the degree and component size
+  /// distributions, giant component share, pseudo diameter, average distance
+  /// and clustering next to the results for the actual network.
+  #[structopt(long, default_value = "0")]
+  null_ensemble_size: usize,
+
+  /// Null model for the null ensemble (see --null-models).
+  #[structopt(long, default_value = "curveball")]
+  null_ensemble_model: NullModelKind,
+
   /// Eliminate users with very large contribution to remove (some) bots and
   /// spammers.
   #[structopt(long, default_value = "500000")]
@@ -66,6 +137,38 @@ struct Opt {
   #[structopt(short, long)]
   components: bool,
 
+  /// Compute the (Robins-Alexander) bipartite clustering coefficient.
+  #[structopt(long)]
+  clustering: bool,
+
+  /// Use the parallel (direction optimizing) breadth first search for
+  /// components, pseudo diameter and average distance.
+  #[structopt(long)]
+  parallel_bfs: bool,
+
+  /// How to find components: bfs, parallel-bfs or union-find (defaults to
+  /// parallel-bfs if --parallel-bfs is passed and bfs otherwise).
+  #[structopt(long)]
+  components_method: Option<ComponentsMethod>,
+
+  /// Save the component id of every user and repo.
+  #[structopt(long, requires("components"))]
+  component_membership: bool,
+
+  /// Save a summary (sizes, edges, diameter, top repos, ...) of every
+  /// component with at least this many nodes.
+  #[structopt(long, requires("components"))]
+  component_summary_min_size: Option<usize>,
+
+  /// Components up to this size get an exact diameter in the summary (larger
+  /// components get a pseudo diameter).
+  #[structopt(long, default_value = "1000")]
+  component_summary_exact_diameter_max_size: usize,
+
+  /// Number of highest degree repos to list for each summarized component.
+  #[structopt(long, default_value = "5")]
+  component_summary_top_repos: usize,
+
   /// Compute pseudo diameter of the giant component.
   #[structopt(long, requires("components"))]
   pseudo_diameter: bool,
@@ -75,6 +178,63 @@ struct Opt {
   #[structopt(long, requires("components"))]
   average_distance_samples: Option<usize>,
 
+  /// Compute betweenness centrality on the bipartite network and save the
+  /// top users and repos. Sources are sampled from the giant component if
+  /// --betweenness-samples is given.
+  #[structopt(long, requires("components"))]
+  betweenness: bool,
+
+  /// Compute betweenness centrality on every projected graph and save the top
+  /// items.
+  #[structopt(long)]
+  projected_betweenness: bool,
+
+  /// Number of sampled sources for betweenness (exact if not given).
+  #[structopt(long)]
+  betweenness_samples: Option<usize>,
+
+  /// Number of top users and repos to save for centrality measures.
+  #[structopt(long, default_value = "100")]
+  centrality_top_n: usize,
+
+  /// Compute exact closeness and harmonic centrality for these users.
+  #[structopt(long, use_delimiter = true)]
+  closeness_user: Vec<String>,
+
+  /// Compute exact closeness and harmonic centrality for these repos.
+  #[structopt(long, use_delimiter = true)]
+  closeness_repo: Vec<String>,
+
+  /// Approximate closeness and harmonic centrality for the whole giant
+  /// component by traversing from this many sampled pivots.
+  #[structopt(long, requires("components"))]
+  closeness_pivots: Option<usize>,
+
+  /// Compute PageRank (weighted by the number of contributions) and save the
+  /// top users and repos.
+  #[structopt(long)]
+  pagerank: bool,
+
+  /// Compute HITS (weighted by the number of contributions) with users as
+  /// hubs and repos as authorities and save the top users and repos.
+  #[structopt(long)]
+  hits: bool,
+
+  #[structopt(long, default_value = "0.85")]
+  pagerank_damping: f64,
+
+  /// Damping for HITS: 1 is standard HITS and lower values mix in uniform
+  /// teleportation.
+  #[structopt(long, default_value = "1")]
+  hits_damping: f64,
+
+  /// Stop PageRank and HITS iteration once the L1 change is below this.
+  #[structopt(long, default_value = "1e-10")]
+  link_analysis_tolerance: f64,
+
+  #[structopt(long, default_value = "100")]
+  link_analysis_max_iterations: usize,
+
   /// Save the projected subgraph close to this user.
   #[structopt(long, use_delimiter = true)]
   subgraph_user: Vec<String>,
@@ -96,11 +256,91 @@ struct Opt {
   #[structopt(long, use_delimiter = true)]
   repo_min_connection_str: Vec<f64>,
 
+  /// Keep the edges of projected graphs which are significant under this
+  /// backbone method (disparity, sdsm, fdsm or polya-urn) instead of
+  /// thresholding on connection strength. Edges below the lowest minimum
+  /// connection strength (if given) are still dropped first. Disparity and
+  /// polya-urn weigh edges by strength, so they only work with
+  /// num-common-nodes, min-num-events and total-num-events.
+  #[structopt(long)]
+  backbone: Option<BackboneMethod>,
+
+  /// Significance levels to extract backbones for.
+  #[structopt(long, use_delimiter = true, default_value = "0.05")]
+  backbone_alpha: Vec<f64>,
+
+  /// Number of sampled networks for the fdsm backbone.
+  #[structopt(long, default_value = "100")]
+  fdsm_samples: usize,
+
+  /// Reinforcement parameter for the polya-urn backbone.
+  #[structopt(long, default_value = "1")]
+  polya_reinforcement: f64,
+
+  /// Only keep the strongest edges of each item in projected graphs (an edge
+  /// is kept if it's among the strongest of either item). Bounds memory for
+  /// large datasets.
+  #[structopt(long)]
+  projection_top_k: Option<usize>,
+
+  /// What to do with items of the other type which have more than
+  /// --hub-max-degree connections when projecting: skip, cap (keep the
+  /// connections with the most events) or subsample. How much was dropped
+  /// is saved for each hub.
+  #[structopt(long)]
+  hub_policy: Option<HubPolicy>,
+
+  #[structopt(long, default_value = "1000")]
+  hub_max_degree: usize,
+
+  /// Project out of core: edges are written to sorted shards in this
+  /// directory and merged into a CSR on disk (projected_csr in the output
+  /// directory of each projected graph). Each minimum connection strength
+  /// then only loads the edges with at least that strength, one at a time,
+  /// so only the graph being analysed has to fit in memory. The percolation
+  /// sweep, --save-projected and backbones load the edges at the lowest
+  /// minimum connection strength.
+  #[structopt(long, parse(from_os_str))]
+  shard_dir: Option<PathBuf>,
+
+  /// Edges buffered in memory before a shard is written (each edge counts
+  /// twice and takes 24 bytes, so the default buffers about 240 MB).
+  #[structopt(long, default_value = "10000000")]
+  max_shard_edges: usize,
+
+  /// Save the percolation curve (number of components, giant component size,
+  /// mean degree) of every projected graph at this many thresholds, going
+  /// down to the lowest minimum connection strength.
+  #[structopt(long)]
+  percolation_points: Option<usize>,
+
+  /// Save every projected graph (at the lowest minimum connection strength)
+  /// to projected_graph.bin in its output directory.
+  #[structopt(long)]
+  save_projected: bool,
+
+  /// Also save every projected graph as a csv edge list with names and
+  /// strengths.
+  #[structopt(long, requires("save_projected"))]
+  save_projected_csv: bool,
+
+  /// Load projected graphs saved with --save-projected instead of projecting
+  /// again if they were saved for the same dataset, connection strength and
+  /// projection options (and a low enough minimum connection strength).
+  #[structopt(long)]
+  load_projected: bool,
+
   /// What type of connection strength metrics to use - typically just 1 should
   /// be specified.
   #[structopt(long, use_delimiter = true)]
   connection_str_types: Vec<ConnectionStrengthTypes>,
 
+  /// Number of null networks to sample for monte-carlo-z connection
+  /// strengths. The null moments of every pair the projection evaluates
+  /// (after --hub-policy) are kept for one item type at a time.
+  #[structopt(long, default_value = "100")]
+  monte_carlo_samples: usize,
+
   /// Compute and save statistics about connection strengths.
   #[structopt(long)]
   connection_str_stats: bool,
@@ -160,30 +400,140 @@ struct RunConnectionStrArgs<'a, D: DatasetNameID> {
   dataset: &'a Dataset,
   dataset_info: &'a D,
   connection_str_stats: bool,
+  projected_betweenness: Option<BetweennessMode>,
+  centrality_top_n: usize,
+  backbone: Option<(BackboneMethod, BackboneOptions, &'a [f64])>,
+  monte_carlo: MonteCarloOptions,
+  projection: ProjectionOptions,
+  shards: Option<ShardOptions>,
+  percolation_points: Option<usize>,
+  save_projected: bool,
+  save_projected_csv: bool,
+  load_projected: bool,
+  manifest: &'a mut Manifest,
 }
 
+impl<'a, D: DatasetNameID> RunConnectionStrArgs<'a, D> {
+  /// Borrow the arguments again to run more than once.
+  fn reborrow(&mut self) -> RunConnectionStrArgs<'_, D> {
+    RunConnectionStrArgs {
+      output_dir: self.output_dir,
+      min_connection_str: &mut *self.min_connection_str,
+      subgraph_names: self.subgraph_names,
+      subgraph_limits: self.subgraph_limits,
+      dataset: self.dataset,
+      dataset_info: self.dataset_info,
+      connection_str_stats: self.connection_str_stats,
+      projected_betweenness: self.projected_betweenness,
+      centrality_top_n: self.centrality_top_n,
+      backbone: self.backbone,
+      monte_carlo: self.monte_carlo,
+      projection: self.projection,
+      shards: self.shards.clone(),
+      percolation_points: self.percolation_points,
+      save_projected: self.save_projected,
+      save_projected_csv: self.save_projected_csv,
+      load_projected: self.load_projected,
+      manifest: &mut *self.manifest,
+    }
+  }
+}
+
+const ITEM_TYPES: [ItemType; 2] = [ItemType::User, ItemType::Repo];
+
 fn run_connection_outer<T: ConnectionStrength, D: DatasetNameID>(
   args: RunConnectionStrArgs<'_, D>,
   inner: T,
-  norm: bool,
+  norm: Normalization,
 ) -> Result<()> {
   let accelerators = &UserRepoPair::<()>::default().map_with(|_, item_type| {
     ExpectationAccelerator::new(item_type, args.dataset)
   });
-  if norm {
-    run_connection_str(
+  match norm {
+    Normalization::None => {
+      run_connection_str(args, inner, accelerators, &ITEM_TYPES)
+    }
+    Normalization::Expectation => run_connection_str(
       args,
       Normalized {
         inner,
         accelerators,
       },
       accelerators,
-    )
-  } else {
-    run_connection_str(args, inner, accelerators)
+      &ITEM_TYPES,
+    ),
+    Normalization::ZScore => run_connection_str(
+      args,
+      ZScore {
+        inner,
+        accelerators,
+      },
+      accelerators,
+      &ITEM_TYPES,
+    ),
+    Normalization::MonteCarloZ => {
+      let mut args = args;
+      fs::create_dir_all(args.output_dir)?;
+      // the null is only kept for the item type being projected
+      for item_type in ITEM_TYPES {
+        println!(
+          "sampling {} null networks for {}",
+          args.monte_carlo.num_samples,
+          item_type.as_str()
+        );
+
+        let start = Instant::now();
+        let null = MonteCarloNull::new(
+          inner,
+          item_type,
+          args.dataset,
+          &args.projection,
+          &args.monte_carlo,
+        );
+        let name = format!("monte_carlo_{}_{:?}", item_type.as_str(), inner);
+        args
+          .manifest
+          .add_timing(args.output_dir, &name, start.elapsed());
+        args
+          .manifest
+          .add_seed(args.output_dir, &name, args.monte_carlo.seed);
+
+        save_calibration(
+          &args.output_dir.join(format!(
+            "monte_carlo_calibration_{}_{:?}.csv",
+            item_type.as_str(),
+            inner
+          )),
+          &null,
+          &accelerators[item_type],
+          args.dataset,
+        )?;
+
+        run_connection_str(
+          args.reborrow(),
+          MonteCarloZ { null: &null },
+          accelerators,
+          &[item_type],
+        )?;
+      }
+
+      Ok(())
+    }
   }
 }
 
+/// For connection strengths which can't be normalized: expectations (for
+/// the stats) are of the number of common nodes.
+fn run_connection_unnormalized<T: ConnectionStrength, D: DatasetNameID>(
+  args: RunConnectionStrArgs<'_, D>,
+  connection_strength: T,
+) -> Result<()> {
+  let accelerators = &UserRepoPair::<()>::default().map_with(|_, item_type| {
+    ExpectationAccelerator::<NumCommonNodes>::new(item_type, args.dataset)
+  });
+  run_connection_str(args, connection_strength, accelerators, &ITEM_TYPES)
+}
+
 fn run_connection_str<
   'a,
   T: ConnectionStrength,
@@ -193,6 +543,7 @@ fn run_connection_str<
   args: RunConnectionStrArgs<'a, D>,
   connection_strength: T,
   accelerators: &UserRepoPair<ExpectationAccelerator<V>>,
+  item_types: &[ItemType],
 ) -> Result<()> {
   let RunConnectionStrArgs {
     output_dir,
@@ -202,14 +553,25 @@ fn run_connection_str<
     dataset,
     dataset_info,
     connection_str_stats,
+    projected_betweenness,
+    centrality_top_n,
+    backbone: backbone_args,
+    monte_carlo: _,
+    projection,
+    shards,
+    percolation_points,
+    save_projected,
+    save_projected_csv,
+    load_projected,
+    manifest,
   } = args;
 
-  let prefixs = UserRepoPair {
-    user: "user",
-    repo: "repo",
-  };
+  if let Some((method, _, _)) = backbone_args {
+    method.check_strength(&connection_strength)?;
+  }
 
-  for (item_type, prefix) in prefixs.iter_with() {
+  for &item_type in item_types {
+    let prefix = item_type.as_str();
     let output_dir: PathBuf = output_dir
       .join(&format!("projected_{}", prefix))
       .join(format!("{:?}", connection_strength));
@@ -222,7 +584,8 @@ fn run_connection_str<
     );
 
     if connection_str_stats {
-      save_connection_str_stats(
+      let start = Instant::now();
+      let stats = save_connection_str_stats(
         &output_dir,
         item_type,
         &connection_strength,
@@ -230,6 +593,25 @@ fn run_connection_str<
         dataset,
         dataset_info,
       )?;
+      manifest.add_timing(&output_dir, "connection_str_stats", start.elapsed());
+      manifest.add_results(&output_dir, &stats)?;
+    }
+
+    if let Some(hubs) = &projection.hubs {
+      let (dropped, total) = save_hub_report(
+        &output_dir.join("hubs_dropped.csv"),
+        item_type,
+        &HubFilter::new(item_type, Some(hubs), dataset),
+        dataset,
+        dataset_info,
+      )?;
+      println!(
+        "hubs drop {} of {} projected contribution pairs",
+        dropped, total
+      );
+      manifest.add_seed(&output_dir, "hubs", hubs.seed);
+      manifest.add_result(&output_dir, "hub_dropped_pairs", dropped)?;
+      manifest.add_result(&output_dir, "hub_total_pairs", total)?;
     }
 
     let min_connection_str = &mut min_connection_str[item_type];
@@ -241,82 +623,490 @@ fn run_connection_str<
       .map(|v| ConnectionStrengthValue::from_float(*v))
       .collect::<anyhow::Result<_>>()?;
 
-    let lowest = if let Some(lowest) = min_connection_str.get(0) {
-      lowest
-    } else {
-      continue;
+    let lowest = match (min_connection_str.get(0), backbone_args) {
+      (Some(lowest), _) => lowest.clone(),
+      // backbones need every edge, and scores like z-scores can be negative
+      // (f64::MIN rather than -inf, which json headers can't hold)
+      (None, Some(_)) if T::Value::IS_COUNT => Default::default(),
+      (None, Some(_)) => T::Value::from_float(f64::MIN)?,
+      (None, None) => continue,
     };
 
-    let mut projected_graph = ProjectedGraph::from_dataset(
+    let start = Instant::now();
+    let projected_path = output_dir.join("projected_graph.bin");
+    let header = ProjectedGraphHeader::new(
       item_type,
       &connection_strength,
-      lowest,
+      &lowest,
+      &projection,
       dataset,
     );
 
+    let loaded = if load_projected && projected_path.exists() {
+      match load_projected_graph_header(&projected_path)
+        .and_then(|saved| saved.check_reusable(&header))
+      {
+        Ok(()) => {
+          println!("loading saved projected graph");
+          let (_, mut loaded) = load_projected_graph(&projected_path)?;
+          loaded.truncate(&lowest);
+          Some(loaded)
+        }
+        Err(err) => {
+          println!("WARN! can't use saved projected graph: {}", err);
+          None
+        }
+      }
+    } else {
+      None
+    };
+    let is_loaded = loaded.is_some();
+
+    // out of core, the graph at the lowest minimum connection strength is
+    // only loaded if something needs all of it
+    let (mut projected_graph, csr) = match loaded {
+      Some(loaded) => (Some(loaded), None),
+      None => match &shards {
+        Some(shards) => {
+          let csr = project_to_disk(
+            item_type,
+            &connection_strength,
+            &lowest,
+            &projection,
+            dataset,
+            shards,
+            &output_dir.join("projected_csr"),
+          )?;
+          let needs_lowest = save_projected
+            || percolation_points.is_some()
+            || backbone_args.is_some();
+          let lowest_graph = if needs_lowest {
+            Some(csr.load_filtered(&lowest)?)
+          } else {
+            None
+          };
+          (lowest_graph, Some(csr))
+        }
+        None => (
+          Some(ProjectedGraph::from_dataset_with_options(
+            item_type,
+            &connection_strength,
+            &lowest,
+            &projection,
+            dataset,
+          )),
+          None,
+        ),
+      },
+    };
+
+    manifest.add_timing(&output_dir, "projection", start.elapsed());
+    manifest.add_result(&output_dir, "loaded_projection", is_loaded)?;
+    manifest.add_size(
+      &output_dir,
+      "projected_edges",
+      match (&projected_graph, &csr) {
+        (Some(graph), _) => graph.edges().len(),
+        (None, Some(csr)) => csr.num_edges(),
+        (None, None) => unreachable!(),
+      },
+    );
+
+    if let Some(projected_graph) = &projected_graph {
+      if save_projected && !is_loaded {
+        println!("saving projected graph");
+        save_projected_graph(&projected_path, projected_graph, &header)?;
+      }
+      if save_projected_csv {
+        save_projected_edges(
+          &output_dir.join("projected_edges.csv"),
+          projected_graph,
+          item_type,
+          dataset_info,
+        )?;
+      }
+
+      if let Some(num_points) = percolation_points {
+        println!("running percolation sweep");
+        let start = Instant::now();
+        save_percolation(
+          &output_dir.join("percolation.csv"),
+          &percolation_sweep(projected_graph, num_points),
+        )?;
+        manifest.add_timing(&output_dir, "percolation", start.elapsed());
+      }
+    }
+
+    let mut projected_args = ProjectedOutputArgs {
+      item_type,
+      subgraph_names: subgraph_names[item_type],
+      subgraph_limits,
+      dataset_info,
+      projected_betweenness,
+      centrality_top_n,
+      workspace: ProjectedTraversalWorkspace::new(dataset.lens()[item_type]),
+    };
+
+    if let Some((method, options, alphas)) = backbone_args {
+      let projected_graph = projected_graph
+        .as_ref()
+        .expect("backbones load the lowest projected graph");
+      println!("computing {} backbone p-values", method.as_str());
+
+      let start = Instant::now();
+      let p_values = edge_p_values(
+        projected_graph,
+        &connection_strength,
+        item_type,
+        dataset,
+        method,
+        &options,
+      )?;
+      let backbone_name = format!("backbone_{}", method.as_str());
+      manifest.add_timing(&output_dir, &backbone_name, start.elapsed());
+      manifest.add_seed(&output_dir, &backbone_name, options.seed);
+
+      for &alpha in alphas {
+        println!("running for {} backbone at {}", method.as_str(), alpha);
+
+        let backbone_graph = backbone(
+          projected_graph,
+          dataset.lens()[item_type],
+          &p_values,
+          alpha,
+        );
+
+        let backbone_dir =
+          output_dir.join(format!("{}_{}", backbone_name, alpha));
+        manifest.add_size(&backbone_dir, "edges", backbone_graph.edges().len());
+
+        let start = Instant::now();
+        run_projected_outputs(
+          &backbone_dir,
+          &backbone_graph,
+          &mut projected_args,
+        )?;
+        manifest.add_timing(&backbone_dir, "outputs", start.elapsed());
+      }
+
+      continue;
+    }
+
     for ref min_connection_str in min_connection_str {
       println!("running for min connection strength {}", min_connection_str);
 
-      projected_graph = projected_graph
-        .filter_edges(dataset.lens()[item_type], min_connection_str);
+      let threshold_graph = match (&mut projected_graph, &csr) {
+        (Some(projected_graph), _) => {
+          projected_graph.truncate(min_connection_str);
+          None
+        }
+        (None, Some(csr)) => Some(csr.load_filtered(min_connection_str)?),
+        (None, None) => unreachable!(),
+      };
+      let graph = threshold_graph
+        .as_ref()
+        .or(projected_graph.as_ref())
+        .unwrap();
 
-      let output_dir: PathBuf =
+      let threshold_dir =
         output_dir.join(format!("min_connection_str_{}", &min_connection_str));
+      manifest.add_size(&threshold_dir, "edges", graph.edges().len());
 
-      fs::create_dir_all(&output_dir)?;
+      let start = Instant::now();
+      run_projected_outputs(&threshold_dir, graph, &mut projected_args)?;
+      manifest.add_timing(&threshold_dir, "outputs", start.elapsed());
+    }
+  }
 
-      for &subgraph_limit in subgraph_limits {
-        let output_dir =
-          output_dir.join(format!("subgraph_limit_{}", subgraph_limit));
+  Ok(())
+}
 
-        fs::create_dir_all(&output_dir)?;
+struct ProjectedOutputArgs<'a, D: DatasetNameID> {
+  item_type: ItemType,
+  subgraph_names: &'a Vec<String>,
+  subgraph_limits: &'a [usize],
+  dataset_info: &'a D,
+  projected_betweenness: Option<BetweennessMode>,
+  centrality_top_n: usize,
+  workspace: ProjectedTraversalWorkspace,
+}
 
-        for name in subgraph_names[item_type] {
-          let idx = dataset_info.find_item(item_type, name).unwrap();
+/// Save the subgraphs and centralities of one (filtered) projected graph.
+fn run_projected_outputs<T: ConnectionStrength, D: DatasetNameID>(
+  output_dir: &Path,
+  projected_graph: &ProjectedGraph<T>,
+  args: &mut ProjectedOutputArgs<'_, D>,
+) -> Result<()> {
+  let ProjectedOutputArgs {
+    item_type,
+    subgraph_names,
+    subgraph_limits,
+    dataset_info,
+    projected_betweenness,
+    centrality_top_n,
+    workspace,
+  } = args;
+  let item_type = *item_type;
 
-          println!("saving subgraph for {:?} {}", item_type, name);
+  fs::create_dir_all(output_dir)?;
 
-          save_subgraph(
-            &output_dir,
-            idx,
-            subgraph_limit,
-            &projected_graph,
-            item_type,
-            dataset_info,
-          )?;
-        }
-      }
+  for &subgraph_limit in *subgraph_limits {
+    let output_dir =
+      output_dir.join(format!("subgraph_limit_{}", subgraph_limit));
+
+    fs::create_dir_all(&output_dir)?;
+
+    for name in *subgraph_names {
+      let idx = dataset_info.find_item(item_type, name).unwrap();
+
+      println!("saving subgraph for {:?} {}", item_type, name);
+
+      save_subgraph(
+        &output_dir,
+        idx,
+        subgraph_limit,
+        projected_graph,
+        item_type,
+        *dataset_info,
+        workspace,
+      )?;
     }
   }
 
+  if let Some(mode) = *projected_betweenness {
+    println!("running projected betweenness");
+    let nodes: Vec<_> = (0..projected_graph.num_nodes())
+      .filter(|&node| projected_graph.degree(node) > 0)
+      .collect();
+    let result = betweenness(projected_graph, &nodes, mode);
+    save_top_centrality(
+      &output_dir.join("betweenness.csv"),
+      item_type,
+      &result.values,
+      result.std_errors.as_deref(),
+      |idx| projected_graph.degree(idx),
+      *centrality_top_n,
+      *dataset_info,
+    )?;
+  }
+
+  Ok(())
+}
+
+fn flat_nodes(component: &Component, lens: UserRepoPair<usize>) -> Vec<usize> {
+  component
+    .as_ref()
+    .iter_with()
+    .flat_map(|(item_type, idxs)| {
+      idxs
+        .iter()
+        .map(move |&idx| Node { item_type, idx }.to_flat(lens))
+    })
+    .collect()
+}
+
+fn run_betweenness(
+  output_dir: &Path,
+  dataset: &Dataset,
+  dataset_info: &impl DatasetNameID,
+  nodes: &[usize],
+  mode: BetweennessMode,
+  num_top: usize,
+) -> Result<()> {
+  let result = betweenness(dataset, nodes, mode);
+
+  let names = UserRepoPair {
+    user: "user_betweenness.csv",
+    repo: "repo_betweenness.csv",
+  };
+
+  for (item_type, name) in names.iter_with() {
+    let start = Node { item_type, idx: 0 }.to_flat(dataset.lens());
+    let range = start..start + dataset.lens()[item_type];
+    save_top_centrality(
+      &output_dir.join(name),
+      item_type,
+      &result.values[range.clone()],
+      result
+        .std_errors
+        .as_ref()
+        .map(|errors| &errors[range.clone()]),
+      |idx| dataset.contribution_idxs()[item_type][idx].len(),
+      num_top,
+      dataset_info,
+    )?;
+  }
+
+  Ok(())
+}
+
+fn save_link_analysis(
+  csv_names: UserRepoPair<&str>,
+  result: &LinkAnalysisResult,
+  output_dir: &Path,
+  dataset: &Dataset,
+  dataset_info: &impl DatasetNameID,
+  num_top: usize,
+) -> Result<()> {
+  if result.converged {
+    println!("converged after {} iterations", result.iterations);
+  } else {
+    println!(
+      "WARN! didn't converge after {} iterations",
+      result.iterations
+    );
+  }
+
+  for (item_type, name) in csv_names.iter_with() {
+    save_top_centrality(
+      &output_dir.join(name),
+      item_type,
+      &result.scores[item_type],
+      None,
+      |idx| dataset.contribution_idxs()[item_type][idx].len(),
+      num_top,
+      dataset_info,
+    )?;
+  }
+
   Ok(())
 }
 
+fn curveball_options(opts: &Opt) -> CurveballOptions {
+  CurveballOptions {
+    burn_in_rounds: opts.curveball_burn_in_rounds,
+    thinning_rounds: opts.curveball_thinning_rounds,
+    preserve_nums: opts.curveball_preserve_nums,
+  }
+}
+
+fn null_model(
+  kind: NullModelKind,
+  opts: &Opt,
+  dataset_info: &DatasetInfo,
+) -> Result<Box<dyn NullModel>> {
+  let out: Box<dyn NullModel> = match kind {
+    NullModelKind::ConfigurationModel => Box::new(ConfigurationModel),
+    NullModelKind::Curveball => Box::new(Curveball(curveball_options(opts))),
+    NullModelKind::ErdosRenyi => Box::new(ErdosRenyi),
+    NullModelKind::ChungLu => Box::new(ChungLu),
+    NullModelKind::StrengthPreserving => Box::new(StrengthPreserving),
+    NullModelKind::DcSbm => {
+      let csv_path = opts
+        .communities_csv
+        .as_ref()
+        .ok_or_else(|| anyhow!("dc-sbm needs --communities-csv"))?;
+      Box::new(DcSbm {
+        communities: DcSbm::load_communities(csv_path, dataset_info)?,
+      })
+    }
+  };
+
+  Ok(out)
+}
+
+fn null_model_seed(kind: NullModelKind) -> u64 {
+  match kind {
+    NullModelKind::ConfigurationModel => 812_388_383,
+    NullModelKind::Curveball => 3_904_127,
+    NullModelKind::ErdosRenyi => 1_730_297,
+    NullModelKind::ChungLu => 6_620_151,
+    NullModelKind::StrengthPreserving => 2_915_443,
+    NullModelKind::DcSbm => 8_071_569,
+  }
+}
+
+/// Sample from `model` and record the seed, the time it took and how many
+/// contributions it has compared to `dataset` (for the configuration model
+/// the difference is the number of dropped duplicate edges).
+fn gen_null_sample(
+  model: &dyn NullModel,
+  seed: u64,
+  dataset: &Dataset,
+  output_dir: &Path,
+  manifest: &mut Manifest,
+) -> Dataset {
+  let start = Instant::now();
+  let sample = model.gen_graph(dataset, &mut StdRng::seed_from_u64(seed));
+  manifest.add_seed(output_dir, "null_model", seed);
+  manifest.add_timing(output_dir, "null_model", start.elapsed());
+  manifest.add_dataset_sizes(output_dir, &sample);
+  manifest.add_size(
+    output_dir,
+    "contributions_lost",
+    dataset
+      .contributions()
+      .len()
+      .saturating_sub(sample.contributions().len()),
+  );
+
+  sample
+}
+
+/// Returns the stats used for null ensembles for each min contributions (in
+/// sorted order).
 fn run(
   opts: &Opt,
   dataset: &mut Dataset,
   dataset_info: &impl DatasetNameID,
   output_dir: &Path,
-) -> Result<()> {
+  manifest: &mut Manifest,
+) -> Result<Vec<NetworkStats>> {
   let Opt {
     contribution,
     contributions_for_user,
     contributions_for_repo,
     degrees,
     components,
+    clustering,
+    parallel_bfs,
+    components_method,
+    component_membership,
+    component_summary_min_size,
+    component_summary_exact_diameter_max_size,
+    component_summary_top_repos,
     pseudo_diameter,
     average_distance_samples,
+    betweenness: run_bipartite_betweenness,
+    projected_betweenness,
+    betweenness_samples,
+    centrality_top_n,
+    closeness_user,
+    closeness_repo,
+    closeness_pivots,
+    pagerank: run_pagerank,
+    hits: run_hits,
+    pagerank_damping,
+    hits_damping,
+    link_analysis_tolerance,
+    link_analysis_max_iterations,
     subgraph_user,
     subgraph_repo,
     subgraph_limits,
     user_min_connection_str,
     repo_min_connection_str,
+    backbone,
+    backbone_alpha,
+    fdsm_samples,
+    polya_reinforcement,
+    monte_carlo_samples,
+    projection_top_k,
+    hub_policy,
+    hub_max_degree,
+    shard_dir,
+    max_shard_edges,
+    percolation_points,
+    save_projected,
+    save_projected_csv,
+    load_projected,
     connection_str_types,
     connection_str_stats,
     min_contributions,
     ..
   } = opts;
 
+  let curveball = curveball_options(opts);
+
   let contribution_names = UserRepoPair {
     user: (contributions_for_user, "user"),
     repo: (contributions_for_repo, "repo"),
@@ -332,6 +1122,14 @@ fn run(
     repo: repo_min_connection_str.clone(),
   };
 
+  let betweenness_mode = match *betweenness_samples {
+    Some(num_samples) => BetweennessMode::Sampled {
+      num_samples,
+      seed: 2_384_711,
+    },
+    None => BetweennessMode::Exact,
+  };
+
   let contributions_dir = output_dir.join("contributions");
 
   fs::create_dir_all(&contributions_dir)?;
@@ -366,6 +1164,8 @@ fn run(
   let mut min_contribution = min_contributions.clone();
   min_contribution.sort_unstable();
 
+  let mut all_stats = Vec::new();
+
   for min_contribution in min_contribution {
     println!("running for min contributions {}", min_contribution);
     dataset.filter_contributions(min_contribution);
@@ -375,19 +1175,185 @@ fn run(
 
     fs::create_dir_all(&output_dir)?;
 
+    let run_start = Instant::now();
+    manifest.add_dataset_sizes(&output_dir, dataset);
+    if let BetweennessMode::Sampled { seed, .. } = betweenness_mode {
+      manifest.add_seed(&output_dir, "betweenness", seed);
+    }
+
+    let mut stats = NetworkStats::default();
+
     if *degrees {
       println!("running degrees");
-      run_degrees(&output_dir, dataset, dataset_info)?
+      let start = Instant::now();
+      run_degrees(&output_dir, dataset, dataset_info)?;
+      manifest.add_timing(&output_dir, "degrees", start.elapsed());
+
+      for (item_type, name) in [
+        (ItemType::User, "user_degree"),
+        (ItemType::Repo, "repo_degree"),
+      ] {
+        stats.add_distribution(
+          name,
+          dataset.contribution_idxs()[item_type]
+            .iter()
+            .map(|idxs| idxs.len()),
+        );
+      }
+    }
+
+    if *clustering {
+      println!("running clustering");
+      let start = Instant::now();
+      let clustering = robins_alexander_clustering(dataset);
+      manifest.add_timing(&output_dir, "clustering", start.elapsed());
+      println!("clustering coefficient is {}", clustering);
+      stats.add_scalar("clustering", clustering);
+    }
+
+    let closeness_names = UserRepoPair {
+      user: closeness_user,
+      repo: closeness_repo,
+    };
+    let lens = dataset.lens();
+    let closeness_nodes: Vec<_> = closeness_names
+      .iter_with()
+      .flat_map(|(item_type, names)| {
+        names.iter().map(move |name| {
+          let idx = dataset_info.find_item(item_type, name).unwrap();
+          Node { item_type, idx }.to_flat(lens)
+        })
+      })
+      .collect();
+    if !closeness_nodes.is_empty() {
+      println!("running exact closeness");
+      let start = Instant::now();
+      save_closeness(
+        &output_dir.join("closeness_exact.csv"),
+        &closeness_nodes,
+        &exact_closeness(dataset, &closeness_nodes),
+        dataset,
+        dataset_info,
+      )?;
+      manifest.add_timing(&output_dir, "exact_closeness", start.elapsed());
+    }
+
+    let link_analysis_options = |damping| LinkAnalysisOptions {
+      damping,
+      tolerance: *link_analysis_tolerance,
+      max_iterations: *link_analysis_max_iterations,
+    };
+
+    if *run_pagerank {
+      println!("running pagerank");
+      let start = Instant::now();
+      let result = pagerank(dataset, link_analysis_options(*pagerank_damping));
+      manifest.add_timing(&output_dir, "pagerank", start.elapsed());
+      manifest.add_result(
+        &output_dir,
+        "pagerank_iterations",
+        result.iterations,
+      )?;
+      manifest.add_result(
+        &output_dir,
+        "pagerank_converged",
+        result.converged,
+      )?;
+      save_link_analysis(
+        UserRepoPair {
+          user: "user_pagerank.csv",
+          repo: "repo_pagerank.csv",
+        },
+        &result,
+        &output_dir,
+        dataset,
+        dataset_info,
+        *centrality_top_n,
+      )?;
+    }
+
+    if *run_hits {
+      println!("running hits");
+      let start = Instant::now();
+      let result = hits(dataset, link_analysis_options(*hits_damping));
+      manifest.add_timing(&output_dir, "hits", start.elapsed());
+      manifest.add_result(&output_dir, "hits_iterations", result.iterations)?;
+      manifest.add_result(&output_dir, "hits_converged", result.converged)?;
+      save_link_analysis(
+        UserRepoPair {
+          user: "user_hits_hub.csv",
+          repo: "repo_hits_authority.csv",
+        },
+        &result,
+        &output_dir,
+        dataset,
+        dataset_info,
+        *centrality_top_n,
+      )?;
     }
 
     if *components {
       println!("running components");
-      let giant_component = save_component_sizes(
+      let start = Instant::now();
+      let (components, labels) = save_component_sizes(
         &dataset,
         &output_dir.join("component_sizes.csv"),
+        components_method.unwrap_or(if *parallel_bfs {
+          ComponentsMethod::ParallelBfs
+        } else {
+          ComponentsMethod::Bfs
+        }),
       )?;
+      manifest.add_timing(&output_dir, "components", start.elapsed());
+      manifest.add_result(&output_dir, "num_components", components.len())?;
+
+      if *component_membership {
+        println!("saving component membership");
+        save_component_membership(
+          &output_dir.join("component_membership.csv"),
+          &labels,
+          dataset_info,
+        )?;
+      }
+
+      if let Some(min_size) = *component_summary_min_size {
+        println!("saving component summaries");
+        save_component_summaries(
+          &output_dir.join("component_summary.csv"),
+          &components,
+          &labels,
+          dataset,
+          dataset_info,
+          ComponentSummaryOptions {
+            min_size,
+            exact_diameter_max_size: *component_summary_exact_diameter_max_size,
+            num_top_repos: *component_summary_top_repos,
+          },
+        )?;
+      }
+
+      stats.add_distribution(
+        "component_size",
+        components.iter().map(|c| c.user.len() + c.repo.len()),
+      );
+
+      if let Some(giant_component) = giant_component(&components) {
+        manifest.add_size(
+          &output_dir,
+          "giant_component_users",
+          giant_component.user.len(),
+        );
+        manifest.add_size(
+          &output_dir,
+          "giant_component_repos",
+          giant_component.repo.len(),
+        );
+        stats.add_scalar(
+          "giant_component_share",
+          (giant_component.user.len() + giant_component.repo.len()) as f64
+            / (lens.user + lens.repo) as f64,
+        );
 
-      if let Some(giant_component) = giant_component {
         let giant_n_repos = giant_component.repo.len();
         let total_n_repos = dataset.lens().repo;
         if giant_n_repos < total_n_repos / 4 {
@@ -399,22 +1365,31 @@ fn run(
         if *pseudo_diameter {
           println!("running pseudo diameter");
 
+          let start = Instant::now();
           let pseudo_diameter = compute_pseudo_diameter(
             Node {
               item_type: ItemType::Repo,
               idx: giant_component[ItemType::Repo][0],
             },
             &dataset,
+            *parallel_bfs,
           );
 
+          manifest.add_timing(&output_dir, "pseudo_diameter", start.elapsed());
           println!("found pseudo diameter {}", pseudo_diameter);
+          stats.add_scalar("pseudo_diameter", pseudo_diameter as f64);
         }
 
         if let Some(num_samples) = average_distance_samples {
           println!("running average distances");
 
-          let distances =
-            average_distance(&giant_component, *num_samples, &dataset);
+          let start = Instant::now();
+          let distances = average_distance(
+            giant_component,
+            *num_samples,
+            &dataset,
+            *parallel_bfs,
+          );
 
           let total = distances.iter().map(|&(_, d)| d).sum::<f64>();
           let total_sqr =
@@ -423,11 +1398,59 @@ fn run(
           let avg = total / distances.len() as f64;
           let avg_sqr = total_sqr / distances.len() as f64;
           let var = avg_sqr - avg.powi(2);
+          manifest.add_timing(&output_dir, "average_distance", start.elapsed());
+          manifest.add_result(&output_dir, "average_distance_variance", var)?;
 
           println!(
             "average distance is {} while variance of samples is {}",
             avg, var
           );
+          stats.add_scalar("average_distance", avg);
+        }
+
+        if let Some(num_pivots) = *closeness_pivots {
+          println!("running sampled closeness");
+
+          let start = Instant::now();
+          let nodes = flat_nodes(giant_component, lens);
+          let seed = 9_127_733;
+
+          save_closeness(
+            &output_dir.join("closeness_sampled.csv"),
+            &nodes,
+            &sampled_closeness(dataset, &nodes, num_pivots, seed),
+            dataset,
+            dataset_info,
+          )?;
+          manifest.add_seed(&output_dir, "closeness_pivots", seed);
+          manifest.add_timing(
+            &output_dir,
+            "sampled_closeness",
+            start.elapsed(),
+          );
+        }
+
+        if *run_bipartite_betweenness {
+          println!("running betweenness");
+
+          let start = Instant::now();
+
+          let nodes: Vec<_> = match betweenness_mode {
+            BetweennessMode::Exact => (0..dataset.num_nodes()).collect(),
+            BetweennessMode::Sampled { .. } => {
+              flat_nodes(giant_component, lens)
+            }
+          };
+
+          run_betweenness(
+            &output_dir,
+            dataset,
+            dataset_info,
+            &nodes,
+            betweenness_mode,
+            *centrality_top_n,
+          )?;
+          manifest.add_timing(&output_dir, "betweenness", start.elapsed());
         }
       } else {
         println!(
@@ -443,6 +1466,43 @@ fn run(
         subgraph_limits,
         subgraph_names,
         connection_str_stats: *connection_str_stats,
+        projected_betweenness: if *projected_betweenness {
+          Some(betweenness_mode)
+        } else {
+          None
+        },
+        centrality_top_n: *centrality_top_n,
+        backbone: backbone.map(|method| {
+          let options = BackboneOptions {
+            fdsm_samples: *fdsm_samples,
+            curveball,
+            seed: 4_410_923,
+            polya_reinforcement: *polya_reinforcement,
+          };
+          (method, options, &backbone_alpha[..])
+        }),
+        monte_carlo: MonteCarloOptions {
+          num_samples: *monte_carlo_samples,
+          curveball,
+          seed: 6_218_395,
+        },
+        projection: ProjectionOptions {
+          top_k: *projection_top_k,
+          hubs: hub_policy.map(|policy| HubOptions {
+            policy,
+            max_degree: *hub_max_degree,
+            seed: 5_102_887,
+          }),
+        },
+        shards: shard_dir.as_ref().map(|shard_dir| ShardOptions {
+          shard_dir: shard_dir.clone(),
+          max_shard_edges: *max_shard_edges,
+        }),
+        percolation_points: *percolation_points,
+        save_projected: *save_projected,
+        save_projected_csv: *save_projected_csv,
+        load_projected: *load_projected,
+        manifest: &mut *manifest,
         dataset,
         dataset_info,
       };
@@ -462,11 +1522,30 @@ fn run(
         CST::GeometricMeanEvents(norm) => {
           run_connection_outer(args, GeometricMeanEvents::default(), norm)
         }
+        CST::Hypergeometric => {
+          run_connection_unnormalized(args, Hypergeometric)
+        }
+        CST::Jaccard => run_connection_unnormalized(args, Jaccard),
+        CST::Cosine => run_connection_unnormalized(args, Cosine),
+        CST::Sorensen => run_connection_unnormalized(args, Sorensen),
+        CST::HubPromoted => run_connection_unnormalized(args, HubPromoted),
+        CST::HubDepressed => run_connection_unnormalized(args, HubDepressed),
+        CST::Newman => run_connection_unnormalized(args, Newman),
+        CST::ResourceAllocation => {
+          run_connection_unnormalized(args, ResourceAllocation)
+        }
       }?;
     }
+
+    for (name, &value) in &stats.scalars {
+      manifest.add_result(&output_dir, name, value)?;
+    }
+    manifest.add_timing(&output_dir, "total", run_start.elapsed());
+
+    all_stats.push(stats);
   }
 
-  Ok(())
+  Ok(all_stats)
 }
 
 pub fn main() -> Result<()> {
@@ -474,37 +1553,118 @@ pub fn main() -> Result<()> {
 
   let output_dir = PathBuf::from("output_data");
 
-  if opt.use_configuration_model || !opt.no_original_network {
+  let start = Instant::now();
+  let mut manifest = Manifest::new(&output_dir, &opt)?;
+
+  let mut null_models = opt.null_models.clone();
+  if opt.use_configuration_model
+    && !null_models.contains(&NullModelKind::ConfigurationModel)
+  {
+    null_models.insert(0, NullModelKind::ConfigurationModel);
+  }
+
+  if !null_models.is_empty()
+    || opt.null_ensemble_size > 0
+    || !opt.no_original_network
+  {
+    let load_start = Instant::now();
     let (dataset_info, dataset) =
       DatasetInfo::load_limited(opt.limit, Some(opt.max_user_contributions))?;
+    manifest.add_timing(&output_dir, "load_dataset", load_start.elapsed());
+    manifest.set_dataset(&dataset);
 
     println!("users: {}", dataset.lens().user);
     println!("repos: {}", dataset.lens().repo);
     println!("connections: {}", dataset.contributions().len());
 
-    if opt.use_configuration_model {
-      println!("=== running for configuration model ===\n");
+    for kind in null_models {
+      println!("=== running for {} ===\n", kind.as_str());
 
-      let mut rng = StdRng::seed_from_u64(812388383);
+      let null_dir = output_dir.join(kind.as_str());
+      let mut sample = gen_null_sample(
+        &*null_model(kind, &opt, &dataset_info)?,
+        null_model_seed(kind),
+        &dataset,
+        &null_dir,
+        &mut manifest,
+      );
+
+      run(&opt, &mut sample, &dataset_info, &null_dir, &mut manifest)?;
+      manifest.save()?;
+    }
+
+    let ensemble_dir = output_dir.join("null_ensemble");
+    let ensemble_model = if opt.null_ensemble_size > 0 {
+      Some(null_model(opt.null_ensemble_model, &opt, &dataset_info)?)
+    } else {
+      None
+    };
+    let mut ensemble_stats = Vec::new();
+    for (sample, model) in
+      (0..opt.null_ensemble_size).zip(ensemble_model.iter().cycle())
+    {
+      println!("=== running for null ensemble sample {} ===\n", sample);
+
+      let sample_dir = ensemble_dir.join(format!("sample_{}", sample));
+      let mut sample = gen_null_sample(
+        &**model,
+        7_345_091 + sample as u64,
+        &dataset,
+        &sample_dir,
+        &mut manifest,
+      );
 
-      run(
+      ensemble_stats.push(run(
         &opt,
-        &mut configuration_model::gen_graph(&dataset, &mut rng),
+        &mut sample,
         &dataset_info,
-        &output_dir.join("configuration_model"),
-      )?;
+        &sample_dir,
+        &mut manifest,
+      )?);
+      manifest.save()?;
     }
-    if !opt.no_original_network {
+
+    let real_stats = if opt.no_original_network {
+      None
+    } else {
       let mut dataset = dataset;
       println!("=== running for actual network ===\n");
-      run(
+      Some(run(
         &opt,
         &mut dataset,
         &dataset_info,
         &output_dir.join("actual_graph"),
-      )?;
+        &mut manifest,
+      )?)
+    };
+
+    if !ensemble_stats.is_empty() {
+      println!("saving null ensemble bands");
+
+      // next to the actual network results if there are any
+      let ensemble_dir = if real_stats.is_some() {
+        output_dir.join("actual_graph")
+      } else {
+        ensemble_dir
+      };
+      let mut min_contributions = opt.min_contributions.clone();
+      min_contributions.sort_unstable();
+      for (i, min_contribution) in min_contributions.into_iter().enumerate() {
+        let samples: Vec<_> = ensemble_stats
+          .iter()
+          .map(|stats| stats[i].clone())
+          .collect();
+        save_ensemble(
+          &ensemble_dir.join(format!("min_contribution_{}", min_contribution)),
+          &samples,
+          real_stats.as_ref().map(|stats| &stats[i]),
+        )?;
+      }
     }
   }
 
+  manifest.add_timing(&output_dir, "total", start.elapsed());
+  manifest.save()?;
+
   Ok(())
 }
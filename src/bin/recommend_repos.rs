@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Result};
+use github_net::{
+  dataset::{DatasetInfo, DatasetNameID, Lens},
+  recommend::{recommend_repos, save_recommendations, PprMethod, PprOptions},
+  traversal::Node,
+  ItemType,
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+  name = "recommend_repos",
+  about = "rank repos for a user or for a set of seed repos by personalized pagerank"
+)]
+struct Opt {
+  /// Recommend repos this user hasn't contributed to.
+  #[structopt(long, required_unless("repos"))]
+  user: Option<String>,
+
+  /// Recommend repos related to these repos.
+  #[structopt(long, use_delimiter = true)]
+  repos: Vec<String>,
+
+  /// Number of repos to recommend.
+  #[structopt(short, long, default_value = "20")]
+  num: usize,
+
+  /// Probability of restarting at the seeds at each step.
+  #[structopt(long, default_value = "0.15")]
+  restart: f64,
+
+  /// Use forward push instead of power iteration over the whole dataset.
+  #[structopt(long)]
+  push: bool,
+
+  /// Forward push accuracy (relative to the number of contributions).
+  #[structopt(long, default_value = "1e-7")]
+  push_epsilon: f64,
+
+  #[structopt(long, default_value = "1e-10")]
+  tolerance: f64,
+
+  #[structopt(long, default_value = "100")]
+  max_iterations: usize,
+
+  /// Also save the recommendations to this csv.
+  #[structopt(short, long)]
+  output: Option<PathBuf>,
+
+  /// Maximum number of samples (mostly useful for testing).
+  #[structopt(short, long)]
+  limit: Option<usize>,
+
+  /// Eliminate users with very large contribution to remove (some) bots and
+  /// spammers.
+  #[structopt(long, default_value = "500000")]
+  max_user_contributions: usize,
+}
+
+pub fn main() -> Result<()> {
+  let opt = Opt::from_args();
+
+  let (dataset_info, dataset) =
+    DatasetInfo::load_limited(opt.limit, Some(opt.max_user_contributions))?;
+
+  println!("users: {}", dataset.lens().user);
+  println!("repos: {}", dataset.lens().repo);
+  println!("connections: {}", dataset.contributions().len());
+
+  let find = |item_type: ItemType, name: &str| {
+    dataset_info
+      .find_item(item_type, name)
+      .map(|idx| Node { item_type, idx })
+      .ok_or_else(|| anyhow!("{} {} not found", item_type.as_str(), name))
+  };
+
+  let seeds = opt
+    .user
+    .iter()
+    .map(|name| find(ItemType::User, name))
+    .chain(opt.repos.iter().map(|name| find(ItemType::Repo, name)))
+    .collect::<Result<Vec<_>>>()?;
+
+  let method = if opt.push {
+    PprMethod::ForwardPush {
+      epsilon: opt.push_epsilon,
+    }
+  } else {
+    PprMethod::PowerIteration {
+      tolerance: opt.tolerance,
+      max_iterations: opt.max_iterations,
+    }
+  };
+
+  let recommendations = recommend_repos(
+    &dataset,
+    &seeds,
+    PprOptions {
+      restart: opt.restart,
+      method,
+    },
+    opt.num,
+  );
+
+  for (rank, recommendation) in recommendations.iter().enumerate() {
+    println!(
+      "{}: {} ({})",
+      rank,
+      dataset_info.repo_name(recommendation.idx),
+      recommendation.score
+    );
+  }
+
+  if let Some(path) = &opt.output {
+    save_recommendations(path, &recommendations, &dataset, &dataset_info)?;
+  }
+
+  Ok(())
+}
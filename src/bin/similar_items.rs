@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use github_net::{
+  connection_strength::*,
+  dataset::{Dataset, DatasetInfo, DatasetNameID, Lens},
+  monte_carlo::{MonteCarloNull, MonteCarloOptions, MonteCarloZ},
+  similar::{save_similar, top_k_similar},
+  ItemType, UserRepoPair,
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+  name = "similar_items",
+  about = "find the items most strongly connected to a user or repo"
+)]
+struct Opt {
+  /// Find the users most strongly connected to this user.
+  #[structopt(long, required_unless("repo"), conflicts_with("repo"))]
+  user: Option<String>,
+
+  /// Find the repos most strongly connected to this repo.
+  #[structopt(long)]
+  repo: Option<String>,
+
+  /// Connection strength type to rank by (for example
+  /// num-common-nodes-normalized).
+  #[structopt(long, default_value = "num-common-nodes-normalized")]
+  connection_str_type: ConnectionStrengthTypes,
+
+  /// Number of null networks to sample for monte-carlo-z connection
+  /// strengths.
+  #[structopt(long, default_value = "100")]
+  monte_carlo_samples: usize,
+
+  /// Number of items to find.
+  #[structopt(short, default_value = "20")]
+  k: usize,
+
+  /// Number of shared items to list for each item found.
+  #[structopt(long, default_value = "10")]
+  num_shared: usize,
+
+  /// Also save the items found to this csv.
+  #[structopt(short, long)]
+  output: Option<PathBuf>,
+
+  /// Maximum number of samples (mostly useful for testing).
+  #[structopt(short, long)]
+  limit: Option<usize>,
+
+  /// Eliminate users with very large contribution to remove (some) bots and
+  /// spammers.
+  #[structopt(long, default_value = "500000")]
+  max_user_contributions: usize,
+}
+
+struct Query<'a> {
+  item_type: ItemType,
+  idx: usize,
+  opt: &'a Opt,
+  dataset: &'a Dataset,
+  dataset_info: &'a DatasetInfo,
+}
+
+fn run_outer<T: ConnectionStrength>(
+  query: Query<'_>,
+  inner: T,
+  norm: Normalization,
+) -> Result<()> {
+  let accelerators = &UserRepoPair::<()>::default().map_with(|_, item_type| {
+    ExpectationAccelerator::new(item_type, query.dataset)
+  });
+  match norm {
+    Normalization::None => run(query, inner, inner, accelerators),
+    Normalization::Expectation => run(
+      query,
+      Normalized {
+        inner,
+        accelerators,
+      },
+      inner,
+      accelerators,
+    ),
+    Normalization::ZScore => run(
+      query,
+      ZScore {
+        inner,
+        accelerators,
+      },
+      inner,
+      accelerators,
+    ),
+    Normalization::MonteCarloZ => {
+      let options = MonteCarloOptions {
+        num_samples: query.opt.monte_carlo_samples,
+        curveball: Default::default(),
+        seed: 6_218_395,
+      };
+      let null = MonteCarloNull::new(
+        inner,
+        query.item_type,
+        query.dataset,
+        &Default::default(),
+        &options,
+      );
+      run(query, MonteCarloZ { null: &null }, inner, accelerators)
+    }
+  }
+}
+
+/// For connection strengths which can't be normalized: the expectation
+/// reported is of the number of common nodes.
+fn run_unnormalized<T: ConnectionStrength>(
+  query: Query<'_>,
+  connection_strength: T,
+) -> Result<()> {
+  let accelerators = &UserRepoPair::<()>::default().map_with(|_, item_type| {
+    ExpectationAccelerator::new(item_type, query.dataset)
+  });
+  run(query, connection_strength, NumCommonNodes, accelerators)
+}
+
+fn run<T: ConnectionStrength, V: ConnectionStrength>(
+  query: Query<'_>,
+  connection_strength: T,
+  raw: V,
+  accelerators: &UserRepoPair<ExpectationAccelerator<V>>,
+) -> Result<()> {
+  let Query {
+    item_type,
+    idx,
+    opt,
+    dataset,
+    dataset_info,
+  } = query;
+
+  let similar = top_k_similar(
+    item_type,
+    idx,
+    opt.k,
+    &connection_strength,
+    &raw,
+    &accelerators[item_type],
+    dataset,
+  );
+
+  for (rank, item) in similar.iter().enumerate() {
+    println!(
+      "{}: {} (strength {}, raw strength {}, expectation {}, {} shared)",
+      rank,
+      dataset_info.get_name(item_type, item.idx),
+      item.strength,
+      item.raw_strength,
+      item.expectation,
+      item.shared.len()
+    );
+  }
+
+  if let Some(path) = &opt.output {
+    save_similar(
+      path,
+      item_type,
+      &similar,
+      opt.num_shared,
+      dataset,
+      dataset_info,
+    )?;
+  }
+
+  Ok(())
+}
+
+pub fn main() -> Result<()> {
+  let opt = Opt::from_args();
+
+  let (dataset_info, dataset) =
+    DatasetInfo::load_limited(opt.limit, Some(opt.max_user_contributions))?;
+
+  println!("users: {}", dataset.lens().user);
+  println!("repos: {}", dataset.lens().repo);
+  println!("connections: {}", dataset.contributions().len());
+
+  let (item_type, name) = match (&opt.user, &opt.repo) {
+    (Some(name), _) => (ItemType::User, name),
+    (None, Some(name)) => (ItemType::Repo, name),
+    (None, None) => unreachable!(),
+  };
+  let idx = dataset_info
+    .find_item(item_type, name)
+    .ok_or_else(|| anyhow!("{} {} not found", item_type.as_str(), name))?;
+
+  let query = Query {
+    item_type,
+    idx,
+    opt: &opt,
+    dataset: &dataset,
+    dataset_info: &dataset_info,
+  };
+
+  type Types = ConnectionStrengthTypes;
+  match opt.connection_str_type {
+    Types::NumCommonNodes(norm) => run_outer(query, NumCommonNodes, norm),
+    Types::MinNumEvents(norm) => run_outer(query, MinNumEvents, norm),
+    Types::TotalNumEvents(norm) => run_outer(query, TotalNumEvents, norm),
+    Types::GeometricMeanEvents(norm) => {
+      run_outer(query, GeometricMeanEvents, norm)
+    }
+    Types::Hypergeometric => run_unnormalized(query, Hypergeometric),
+    Types::Jaccard => run_unnormalized(query, Jaccard),
+    Types::Cosine => run_unnormalized(query, Cosine),
+    Types::Sorensen => run_unnormalized(query, Sorensen),
+    Types::HubPromoted => run_unnormalized(query, HubPromoted),
+    Types::HubDepressed => run_unnormalized(query, HubDepressed),
+    Types::Newman => run_unnormalized(query, Newman),
+    Types::ResourceAllocation => run_unnormalized(query, ResourceAllocation),
+  }
+}
@@ -0,0 +1,535 @@
+use crate::{
+  dataset::{Dataset, DatasetNameID, Lens},
+  flat_graph::FlatGraph,
+  output_data::csv_writer,
+  progress_bar::get_bar,
+  traversal::{Node, WorkspacePool},
+  ItemType,
+};
+use anyhow::Result;
+use indicatif::ParallelProgressIterator;
+use rand::{distributions::Uniform, prelude::*};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{cmp::Ordering, path::Path};
+
+const UNVISITED: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BetweennessMode {
+  /// Every node is used as a source.
+  Exact,
+  /// Sources are sampled uniformly (with replacement) and the result is
+  /// scaled up (Brandes and Pich 2007).
+  Sampled { num_samples: usize, seed: u64 },
+}
+
+/// Betweenness of every node (counting each unordered pair of endpoints
+/// once). For sampled betweenness the standard error of each estimate is also
+/// given: the exact value is within `1.96` standard errors with roughly 95%
+/// probability.
+#[derive(Clone, Debug)]
+pub struct Betweenness {
+  pub values: Vec<f64>,
+  pub std_errors: Option<Vec<f64>>,
+}
+
+/// Per thread state for single source shortest path counting. Only the nodes
+/// reached by the last source are reset.
+struct BrandesWorkspace {
+  dists: Vec<u32>,
+  num_paths: Vec<f64>,
+  dependency: Vec<f64>,
+  /// nodes in order of discovery (also used as the queue)
+  order: Vec<usize>,
+  total: Vec<f64>,
+  total_sqr: Vec<f64>,
+}
+
+impl BrandesWorkspace {
+  fn new(num_nodes: usize) -> Self {
+    Self {
+      dists: vec![UNVISITED; num_nodes],
+      num_paths: vec![0.; num_nodes],
+      dependency: vec![0.; num_nodes],
+      order: Vec::new(),
+      total: vec![0.; num_nodes],
+      total_sqr: vec![0.; num_nodes],
+    }
+  }
+
+  /// Adds the dependencies of `source` on every other node. Predecessors
+  /// aren't stored, they are found again by checking the distances of
+  /// neighbors.
+  fn add_source(&mut self, graph: &impl FlatGraph, source: usize) {
+    let Self {
+      dists,
+      num_paths,
+      dependency,
+      order,
+      total,
+      total_sqr,
+    } = self;
+
+    dists[source] = 0;
+    num_paths[source] = 1.;
+    order.push(source);
+
+    let mut head = 0;
+    while let Some(&node) = order.get(head) {
+      head += 1;
+      let next_dist = dists[node] + 1;
+      let paths = num_paths[node];
+      graph.for_each_neighbor(node, |other| {
+        if dists[other] == UNVISITED {
+          dists[other] = next_dist;
+          order.push(other);
+        }
+        if dists[other] == next_dist {
+          num_paths[other] += paths;
+        }
+      });
+    }
+
+    for &node in order.iter().rev() {
+      let coeff = (1. + dependency[node]) / num_paths[node];
+      let prev_dist = dists[node].wrapping_sub(1);
+      graph.for_each_neighbor(node, |other| {
+        if dists[other] == prev_dist {
+          dependency[other] += num_paths[other] * coeff;
+        }
+      });
+      if node != source {
+        total[node] += dependency[node];
+        total_sqr[node] += dependency[node].powi(2);
+      }
+    }
+
+    for &node in order.iter() {
+      dists[node] = UNVISITED;
+      num_paths[node] = 0.;
+      dependency[node] = 0.;
+    }
+    order.clear();
+  }
+}
+
+/// Uniform with replacement.
+fn sample_nodes(nodes: &[usize], num_samples: usize, seed: u64) -> Vec<usize> {
+  if nodes.is_empty() {
+    return Vec::new();
+  }
+  let mut rng = StdRng::seed_from_u64(seed);
+  Uniform::from(0..nodes.len())
+    .sample_iter(&mut rng)
+    .take(num_samples)
+    .map(|i| nodes[i])
+    .collect()
+}
+
+/// Brandes betweenness in parallel over sources. Sources are `nodes` (or
+/// sampled from `nodes`), so exact betweenness within a subset which is a
+/// union of components (for example a single component) can be computed by
+/// passing just the subset.
+#[must_use]
+pub fn betweenness(
+  graph: &impl FlatGraph,
+  nodes: &[usize],
+  mode: BetweennessMode,
+) -> Betweenness {
+  let num_nodes = graph.num_nodes();
+
+  let sources = match mode {
+    BetweennessMode::Exact => nodes.to_vec(),
+    BetweennessMode::Sampled { num_samples, seed } => {
+      sample_nodes(nodes, num_samples, seed)
+    }
+  };
+
+  let workspaces = WorkspacePool::new(|| BrandesWorkspace::new(num_nodes));
+  let bar = get_bar(Some(sources.len() as u64), 100);
+  sources.par_iter().progress_with(bar).for_each(|&source| {
+    workspaces.with(|workspace| workspace.add_source(graph, source))
+  });
+
+  let mut total = vec![0.; num_nodes];
+  let mut total_sqr = vec![0.; num_nodes];
+  for workspace in workspaces.into_workspaces() {
+    for (sum, v) in total.iter_mut().zip(workspace.total) {
+      *sum += v;
+    }
+    for (sum, v) in total_sqr.iter_mut().zip(workspace.total_sqr) {
+      *sum += v;
+    }
+  }
+
+  // every pair is counted from both endpoints
+  match mode {
+    BetweennessMode::Exact => Betweenness {
+      values: total.into_iter().map(|v| v / 2.).collect(),
+      std_errors: None,
+    },
+    BetweennessMode::Sampled { .. } => {
+      let num_samples = sources.len().max(1) as f64;
+      // each sample estimates the betweenness as scale * dependency
+      let scale = nodes.len() as f64 / 2.;
+      let values = total.iter().map(|v| scale * v / num_samples).collect();
+      let std_errors = total
+        .iter()
+        .zip(&total_sqr)
+        .map(|(v, v_sqr)| {
+          if num_samples < 2. {
+            return f64::INFINITY;
+          }
+          let mean = v / num_samples;
+          let var = (v_sqr / num_samples - mean.powi(2)).max(0.) * num_samples
+            / (num_samples - 1.);
+          scale * (var / num_samples).sqrt()
+        })
+        .collect();
+      Betweenness {
+        values,
+        std_errors: Some(std_errors),
+      }
+    }
+  }
+}
+
+/// Breadth first search from a single source which only resets the nodes
+/// reached by the last search.
+struct BfsWorkspace {
+  dists: Vec<u32>,
+  order: Vec<usize>,
+}
+
+impl BfsWorkspace {
+  fn new(num_nodes: usize) -> Self {
+    Self {
+      dists: vec![UNVISITED; num_nodes],
+      order: Vec::new(),
+    }
+  }
+
+  /// Reached nodes in order (starting with the source) and the distances of
+  /// all nodes.
+  fn traverse(
+    &mut self,
+    graph: &impl FlatGraph,
+    source: usize,
+  ) -> (&[usize], &[u32]) {
+    let Self { dists, order } = self;
+    for &node in order.iter() {
+      dists[node] = UNVISITED;
+    }
+    order.clear();
+
+    dists[source] = 0;
+    order.push(source);
+
+    let mut head = 0;
+    while let Some(&node) = order.get(head) {
+      head += 1;
+      let next_dist = dists[node] + 1;
+      graph.for_each_neighbor(node, |other| {
+        if dists[other] == UNVISITED {
+          dists[other] = next_dist;
+          order.push(other);
+        }
+      });
+    }
+
+    (order, dists)
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Closeness {
+  /// Number of other reachable nodes over the total distance to them, so it
+  /// is only comparable within a component.
+  pub closeness: f64,
+  /// Sum of the inverse distances to every other node.
+  pub harmonic: f64,
+}
+
+impl Closeness {
+  fn new(num_others: f64, total_dist: f64, total_inv_dist: f64) -> Self {
+    Self {
+      closeness: if total_dist > 0. {
+        num_others / total_dist
+      } else {
+        0.
+      },
+      harmonic: total_inv_dist,
+    }
+  }
+}
+
+/// Closeness and harmonic centrality of each of `nodes` from a full
+/// traversal each (in parallel).
+#[must_use]
+pub fn exact_closeness(
+  graph: &impl FlatGraph,
+  nodes: &[usize],
+) -> Vec<Closeness> {
+  let workspaces = WorkspacePool::new(|| BfsWorkspace::new(graph.num_nodes()));
+  let bar = get_bar(Some(nodes.len() as u64), 100);
+
+  nodes
+    .par_iter()
+    .progress_with(bar)
+    .map(|&node| {
+      workspaces.with(|workspace| {
+        let (order, dists) = workspace.traverse(graph, node);
+        let (total_dist, total_inv_dist) = order[1..]
+          .iter()
+          .map(|&other| dists[other] as f64)
+          .fold((0., 0.), |(total, total_inv), dist| {
+            (total + dist, total_inv + 1. / dist)
+          });
+        Closeness::new((order.len() - 1) as f64, total_dist, total_inv_dist)
+      })
+    })
+    .collect()
+}
+
+struct PivotWorkspace {
+  bfs: BfsWorkspace,
+  total_dist: Vec<f64>,
+  total_inv_dist: Vec<f64>,
+}
+
+/// Approximate closeness and harmonic centrality for every node of `nodes`,
+/// which should be a single component, by traversing from pivots sampled
+/// uniformly from `nodes` (Eppstein and Wang 2004). The total distance from
+/// each node is estimated as `nodes.len() / num_pivots` times the total
+/// distance to the pivots.
+#[must_use]
+pub fn sampled_closeness(
+  graph: &impl FlatGraph,
+  nodes: &[usize],
+  num_pivots: usize,
+  seed: u64,
+) -> Vec<Closeness> {
+  let num_nodes = graph.num_nodes();
+  let pivots = sample_nodes(nodes, num_pivots, seed);
+
+  let workspaces = WorkspacePool::new(|| PivotWorkspace {
+    bfs: BfsWorkspace::new(num_nodes),
+    total_dist: vec![0.; num_nodes],
+    total_inv_dist: vec![0.; num_nodes],
+  });
+  let bar = get_bar(Some(pivots.len() as u64), 100);
+  pivots.par_iter().progress_with(bar).for_each(|&pivot| {
+    workspaces.with(|workspace| {
+      let PivotWorkspace {
+        bfs,
+        total_dist,
+        total_inv_dist,
+      } = workspace;
+      let (order, dists) = bfs.traverse(graph, pivot);
+      for &node in &order[1..] {
+        let dist = dists[node] as f64;
+        total_dist[node] += dist;
+        total_inv_dist[node] += 1. / dist;
+      }
+    })
+  });
+
+  let mut total_dist = vec![0.; num_nodes];
+  let mut total_inv_dist = vec![0.; num_nodes];
+  for workspace in workspaces.into_workspaces() {
+    for (sum, v) in total_dist.iter_mut().zip(workspace.total_dist) {
+      *sum += v;
+    }
+    for (sum, v) in total_inv_dist.iter_mut().zip(workspace.total_inv_dist) {
+      *sum += v;
+    }
+  }
+
+  let scale = nodes.len() as f64 / pivots.len().max(1) as f64;
+  nodes
+    .iter()
+    .map(|&node| {
+      Closeness::new(
+        nodes.len().saturating_sub(1) as f64,
+        scale * total_dist[node],
+        scale * total_inv_dist[node],
+      )
+    })
+    .collect()
+}
+
+#[derive(Serialize)]
+pub struct ClosenessCsvEntry<ID> {
+  pub item_type: &'static str,
+  pub github_id: ID,
+  pub name: String,
+  pub degree: usize,
+  pub closeness: f64,
+  pub harmonic: f64,
+}
+
+/// `nodes` are flat `Dataset` nodes and `values` has the centrality of each.
+pub fn save_closeness<D: DatasetNameID>(
+  csv_path: &Path,
+  nodes: &[usize],
+  values: &[Closeness],
+  dataset: &Dataset,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+
+  for (&node, value) in nodes.iter().zip(values) {
+    let Node { item_type, idx } = Node::from_flat(node, dataset.lens());
+    writer.serialize(ClosenessCsvEntry {
+      item_type: item_type.as_str(),
+      github_id: dataset_info.get_id(item_type, idx),
+      name: dataset_info.get_name(item_type, idx),
+      degree: dataset.degree(node),
+      closeness: value.closeness,
+      harmonic: value.harmonic,
+    })?;
+  }
+
+  Ok(())
+}
+
+#[derive(Serialize)]
+pub struct CentralityCsvEntry<ID> {
+  pub rank: usize,
+  pub github_id: ID,
+  pub name: String,
+  pub degree: usize,
+  pub value: f64,
+  pub std_error: Option<f64>,
+}
+
+/// Saves the `num_top` items with the highest values (ties are broken by
+/// index). `values`, `std_errors` and `degree` are indexed by the index of
+/// the item.
+pub fn save_top_centrality<D: DatasetNameID>(
+  csv_path: &Path,
+  item_type: ItemType,
+  values: &[f64],
+  std_errors: Option<&[f64]>,
+  degree: impl Fn(usize) -> usize,
+  num_top: usize,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut idxs: Vec<usize> = (0..values.len()).collect();
+  let cmp = |&l: &usize, &r: &usize| {
+    values[r]
+      .partial_cmp(&values[l])
+      .unwrap_or(Ordering::Equal)
+      .then(l.cmp(&r))
+  };
+  if num_top < idxs.len() {
+    idxs.select_nth_unstable_by(num_top, cmp);
+    idxs.truncate(num_top);
+  }
+  idxs.sort_unstable_by(cmp);
+
+  let mut writer = csv_writer(csv_path)?;
+  for (rank, idx) in idxs.into_iter().enumerate() {
+    writer.serialize(CentralityCsvEntry {
+      rank,
+      github_id: dataset_info.get_id(item_type, idx),
+      name: dataset_info.get_name(item_type, idx),
+      degree: degree(idx),
+      value: values[idx],
+      std_error: std_errors.map(|errors| errors[idx]),
+    })?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::traversal::test::{
+    fully_connected_dataset, two_dense_components_dataset,
+  };
+
+  #[test]
+  fn path() {
+    for &count in &[1, 2, 3, 8] {
+      let dataset = fully_connected_dataset(count);
+      let nodes: Vec<_> = (0..dataset.num_nodes()).collect();
+      let result = betweenness(&dataset, &nodes, BetweennessMode::Exact);
+      assert!(result.std_errors.is_none());
+
+      // path is repo 0, user 0, repo 1, user 1, ...
+      let n = 2 * count;
+      for (i, &value) in result.values.iter().enumerate() {
+        let pos = if i < count {
+          2 * i + 1
+        } else {
+          2 * (i - count)
+        };
+        assert_eq!(value, (pos * (n - 1 - pos)) as f64);
+      }
+
+      let sampled = betweenness(
+        &dataset,
+        &nodes,
+        BetweennessMode::Sampled {
+          num_samples: 2000,
+          seed: 0,
+        },
+      );
+      let std_errors = sampled.std_errors.unwrap();
+      for ((estimate, exact), std_error) in
+        sampled.values.iter().zip(&result.values).zip(&std_errors)
+      {
+        assert!((estimate - exact).abs() <= 5. * std_error + 1e-9);
+      }
+    }
+  }
+
+  #[test]
+  fn subset_is_component() {
+    let dataset = two_dense_components_dataset();
+    let all: Vec<_> = (0..dataset.num_nodes()).collect();
+    let full = betweenness(&dataset, &all, BetweennessMode::Exact);
+
+    // users 0, 1, 3, 5 and repos 0..4 (offset by the 8 users)
+    let component = [0, 1, 3, 5, 8, 9, 10, 11];
+    let subset = betweenness(&dataset, &component, BetweennessMode::Exact);
+    for &node in &component {
+      assert!((full.values[node] - subset.values[node]).abs() < 1e-9);
+    }
+  }
+
+  #[test]
+  fn closeness_path() {
+    let count = 8;
+    let dataset = fully_connected_dataset(count);
+    let nodes: Vec<_> = (0..dataset.num_nodes()).collect();
+    let n = 2 * count;
+
+    let exact = exact_closeness(&dataset, &nodes);
+    for (i, value) in exact.iter().enumerate() {
+      let pos = if i < count {
+        2 * i + 1
+      } else {
+        2 * (i - count)
+      };
+      let total_dist = pos * (pos + 1) / 2 + (n - 1 - pos) * (n - pos) / 2;
+      assert!(
+        (value.closeness - (n - 1) as f64 / total_dist as f64).abs() < 1e-9
+      );
+      let harmonic: f64 = (1..=pos)
+        .chain(1..n - pos)
+        .map(|dist| 1. / dist as f64)
+        .sum();
+      assert!((value.harmonic - harmonic).abs() < 1e-9);
+    }
+
+    let sampled = sampled_closeness(&dataset, &nodes, 4000, 0);
+    for (estimate, exact) in sampled.iter().zip(&exact) {
+      assert!((estimate.closeness / exact.closeness - 1.).abs() < 0.1);
+      assert!((estimate.harmonic / exact.harmonic - 1.).abs() < 0.1);
+    }
+  }
+}
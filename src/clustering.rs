@@ -0,0 +1,147 @@
+//! Bipartite clustering.
+
+use crate::{
+  dataset::{Dataset, Lens},
+  hubs::HubFilter,
+  projected_graph::transitive_edge_compute,
+  ItemType,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of 4-cycles (a user pair sharing a repo pair).
+#[must_use]
+pub fn num_four_cycles(dataset: &Dataset) -> u64 {
+  // projecting enumerates pairs through each item of the other type, so
+  // project the type which makes that cheaper
+  let cost = |item_type: ItemType| -> u64 {
+    dataset.contribution_idxs()[item_type.other()]
+      .iter()
+      .map(|idxs| (idxs.len() as u64).pow(2))
+      .sum()
+  };
+  let item_type = if cost(ItemType::User) <= cost(ItemType::Repo) {
+    ItemType::User
+  } else {
+    ItemType::Repo
+  };
+
+  let total = AtomicU64::new(0);
+  transitive_edge_compute(
+    item_type,
+    dataset,
+    &HubFilter::default(),
+    |_, edge_map| {
+      let count: u64 = edge_map
+        .values()
+        .map(|contrib_idxs| {
+          let shared = contrib_idxs.len() as u64;
+          shared * shared.saturating_sub(1) / 2
+        })
+        .sum();
+      total.fetch_add(count, Ordering::Relaxed);
+    },
+  );
+
+  total.into_inner()
+}
+
+/// Robins and Alexander's bipartite clustering coefficient: the fraction of
+/// paths of length 3 which are closed into 4-cycles (each 4-cycle closes 4
+/// such paths).
+#[must_use]
+pub fn robins_alexander_clustering(dataset: &Dataset) -> f64 {
+  let degree = |item_type: ItemType, idx: usize| {
+    dataset.contribution_idxs()[item_type][idx].len() as u64
+  };
+  let num_paths: u64 = dataset
+    .contributions()
+    .iter()
+    .map(|contrib| {
+      (degree(ItemType::User, contrib.idx.user) - 1)
+        * (degree(ItemType::Repo, contrib.idx.repo) - 1)
+    })
+    .sum();
+
+  if num_paths == 0 || dataset.lens().user == 0 {
+    return 0.;
+  }
+
+  4. * num_four_cycles(dataset) as f64 / num_paths as f64
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{dataset, dataset::Contribution, UserRepoPair};
+  use proptest::prelude::*;
+
+  fn from_edges(
+    lens: UserRepoPair<usize>,
+    edges: &[(usize, usize)],
+  ) -> Dataset {
+    Dataset::new(
+      lens,
+      edges
+        .iter()
+        .map(|&(user, repo)| Contribution {
+          idx: UserRepoPair { user, repo },
+          num: 1,
+        })
+        .collect(),
+    )
+  }
+
+  #[test]
+  fn small_graphs() {
+    let square = from_edges(
+      UserRepoPair { user: 2, repo: 2 },
+      &[(0, 0), (0, 1), (1, 0), (1, 1)],
+    );
+    assert_eq!(num_four_cycles(&square), 1);
+    assert!((robins_alexander_clustering(&square) - 1.).abs() < 1e-12);
+
+    let path =
+      from_edges(UserRepoPair { user: 2, repo: 2 }, &[(0, 0), (0, 1), (1, 1)]);
+    assert_eq!(num_four_cycles(&path), 0);
+    assert_eq!(robins_alexander_clustering(&path), 0.);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_four_cycles(
+        dataset in dataset::strategy(
+          1usize..12,
+          1usize..12,
+          1usize..=4,
+          1usize..60,
+        ),
+      ) {
+        let lens = dataset.lens();
+        let mut connected = vec![vec![false; lens.repo]; lens.user];
+        for contrib in dataset.contributions() {
+          connected[contrib.idx.user][contrib.idx.repo] = true;
+        }
+
+        let mut expected = 0;
+        for u in 0..lens.user {
+          for v in u + 1..lens.user {
+            for r in 0..lens.repo {
+              for s in r + 1..lens.repo {
+                if connected[u][r]
+                  && connected[u][s]
+                  && connected[v][r]
+                  && connected[v][s]
+                {
+                  expected += 1;
+                }
+              }
+            }
+          }
+        }
+
+        prop_assert_eq!(num_four_cycles(&dataset), expected);
+        let clustering = robins_alexander_clustering(&dataset);
+        prop_assert!((0. ..=1.).contains(&clustering));
+      }
+  }
+}
@@ -1,11 +1,16 @@
 use crate::{
-  components::components_callback,
-  dataset::{Dataset, Lens},
+  components::{components_labels, ComponentLabels, ComponentsMethod},
+  dataset::{Dataset, DatasetNameID, Lens},
+  distances::{exact_diameter, pseudo_diameter},
   output_data::{csv_reader, csv_writer},
+  parallel_traversal::AnyTraversalWorkspace,
   progress_bar::get_bar,
-  traversal::Component,
+  traversal::{Component, Node},
+  ItemType,
 };
 use anyhow::Result;
+use indicatif::ProgressIterator;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::Path};
 
@@ -16,30 +21,47 @@ pub struct ComponentSizeCsvEntry {
   pub count: usize,
 }
 
-/// Returns giant component.
+#[derive(Serialize)]
+pub struct ComponentMembershipCsvEntry<ID> {
+  pub item_type: &'static str,
+  pub github_id: ID,
+  pub name: String,
+  pub component_id: usize,
+}
+
+#[derive(Serialize)]
+pub struct ComponentSummaryCsvEntry {
+  pub component_id: usize,
+  pub user_size: usize,
+  pub repo_size: usize,
+  pub num_edges: usize,
+  pub total_contributions: usize,
+  /// edges over possible (user, repo) edges
+  pub density: f64,
+  pub diameter: usize,
+  /// if false, the diameter is a pseudo diameter (lower bound)
+  pub diameter_exact: bool,
+  /// separated by ';'
+  pub top_repos_by_degree: String,
+}
+
+/// Returns all components (ordered by id) and the labels.
 pub fn save_component_sizes(
   dataset: &Dataset,
   csv_path: &Path,
-) -> Result<Option<Component>> {
+  method: ComponentsMethod,
+) -> Result<(Vec<Component>, ComponentLabels)> {
   let mut counts = HashMap::new();
   let bar = get_bar(
     Some(dataset.lens().into_iter().map(|l| l as u64).sum()),
     1000,
   );
 
-  let mut giant_component = None;
-  let mut max_num_users = 0;
+  let (components, labels) = components_labels(dataset, method, |_| bar.inc(1));
+  bar.finish();
 
-  for item in components_callback(dataset, |_| bar.inc(1)).map(|component| {
-    let out = (component.user.len(), component.repo.len());
-
-    if component.user.len() > max_num_users {
-      max_num_users = component.user.len();
-      giant_component = Some(component);
-    }
-
-    out
-  }) {
+  for component in &components {
+    let item = (component.user.len(), component.repo.len());
     *counts.entry(item).or_insert(0) += 1;
   }
 
@@ -59,7 +81,132 @@ pub fn save_component_sizes(
   assert_eq!(total_user_size, dataset.lens().user);
   assert_eq!(total_repo_size, dataset.lens().repo);
 
-  Ok(giant_component)
+  Ok((components, labels))
+}
+
+/// Component with the most users (None if there are no users).
+#[must_use]
+pub fn giant_component(components: &[Component]) -> Option<&Component> {
+  let mut giant_component = None;
+  let mut max_num_users = 0;
+  for component in components {
+    if component.user.len() > max_num_users {
+      max_num_users = component.user.len();
+      giant_component = Some(component);
+    }
+  }
+
+  giant_component
+}
+
+pub fn save_component_membership<D: DatasetNameID>(
+  csv_path: &Path,
+  labels: &ComponentLabels,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+
+  for (item_type, ids) in labels.ids().as_ref().iter_with() {
+    for (idx, &component_id) in ids.iter().enumerate() {
+      writer.serialize(ComponentMembershipCsvEntry {
+        item_type: item_type.as_str(),
+        github_id: dataset_info.get_id(item_type, idx),
+        name: dataset_info.get_name(item_type, idx),
+        component_id,
+      })?;
+    }
+  }
+
+  Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ComponentSummaryOptions {
+  /// only components with at least this many nodes are summarized
+  pub min_size: usize,
+  /// diameter is exact for components with at most this many nodes
+  pub exact_diameter_max_size: usize,
+  pub num_top_repos: usize,
+}
+
+pub fn save_component_summaries<D: DatasetNameID>(
+  csv_path: &Path,
+  components: &[Component],
+  labels: &ComponentLabels,
+  dataset: &Dataset,
+  dataset_info: &D,
+  options: ComponentSummaryOptions,
+) -> Result<()> {
+  let ComponentSummaryOptions {
+    min_size,
+    exact_diameter_max_size,
+    num_top_repos,
+  } = options;
+
+  let mut num_edges = vec![0; labels.num_components()];
+  let mut total_contributions = vec![0; labels.num_components()];
+  for contrib in dataset.contributions() {
+    let id = labels.ids().user[contrib.idx.user];
+    debug_assert_eq!(id, labels.ids().repo[contrib.idx.repo]);
+    num_edges[id] += 1;
+    total_contributions[id] += contrib.num;
+  }
+
+  let mut writer = csv_writer(csv_path)?;
+  let mut workspace = AnyTraversalWorkspace::new(dataset.lens(), false);
+
+  let size =
+    |component: &Component| component.user.len() + component.repo.len();
+  let num_to_save = components.iter().filter(|c| size(c) >= min_size).count();
+  let bar = get_bar(Some(num_to_save as u64), 100);
+
+  for (component_id, component) in components
+    .iter()
+    .enumerate()
+    .filter(|(_, c)| size(c) >= min_size)
+    .progress_with(bar)
+  {
+    let (user_size, repo_size) = (component.user.len(), component.repo.len());
+
+    let diameter_exact = size(component) <= exact_diameter_max_size;
+    let diameter = if diameter_exact {
+      exact_diameter(component, dataset, &mut workspace)
+    } else {
+      let start = component
+        .as_ref()
+        .iter_with()
+        .find_map(|(item_type, idxs)| {
+          idxs.first().map(|&idx| Node { item_type, idx })
+        })
+        .unwrap();
+      pseudo_diameter(start, dataset, &mut workspace, false)
+    };
+
+    let degree =
+      |&idx: &usize| dataset.contribution_idxs()[ItemType::Repo][idx].len();
+    let top_repos_by_degree = component
+      .repo
+      .iter()
+      .sorted_by_key(|idx| std::cmp::Reverse(degree(idx)))
+      .take(num_top_repos)
+      .map(|&idx| dataset_info.repo_name(idx))
+      .join(";");
+
+    writer.serialize(ComponentSummaryCsvEntry {
+      component_id,
+      user_size,
+      repo_size,
+      num_edges: num_edges[component_id],
+      total_contributions: total_contributions[component_id],
+      density: num_edges[component_id] as f64
+        / (user_size * repo_size).max(1) as f64,
+      diameter,
+      diameter_exact,
+      top_repos_by_degree,
+    })?;
+  }
+
+  Ok(())
 }
 
 pub fn load_component_sizes(
@@ -67,3 +214,75 @@ pub fn load_component_sizes(
 ) -> Result<impl Iterator<Item = csv::Result<ComponentSizeCsvEntry>>> {
   Ok(csv_reader(csv_path)?.into_deserialize())
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{dataset, output_data::test_dir};
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_component_summaries(
+        dataset in dataset::small_strategy(),
+        min_size in 1usize..4,
+        exact_diameter_max_size in 0usize..20,
+      ) {
+        let (components, labels) =
+          components_labels(&dataset, ComponentsMethod::Bfs, |_| {});
+        let path = test_dir("component_summaries").join("summaries.csv");
+        let options = ComponentSummaryOptions {
+          min_size,
+          exact_diameter_max_size,
+          num_top_repos: 2,
+        };
+        save_component_summaries(
+          &path,
+          &components,
+          &labels,
+          &dataset,
+          &dataset.lens(),
+          options,
+        )
+        .unwrap();
+
+        let rows: Vec<csv::StringRecord> = csv_reader(&path)
+          .unwrap()
+          .records()
+          .collect::<csv::Result<_>>()
+          .unwrap();
+        let expected: Vec<_> = components
+          .iter()
+          .enumerate()
+          .filter(|(_, c)| c.user.len() + c.repo.len() >= min_size)
+          .collect();
+        prop_assert_eq!(rows.len(), expected.len());
+
+        let mut workspace = AnyTraversalWorkspace::new(dataset.lens(), false);
+        for (row, (component_id, component)) in rows.iter().zip(expected) {
+          let field = |i: usize| row[i].parse::<usize>().unwrap();
+          prop_assert_eq!(field(0), component_id);
+          prop_assert_eq!(field(1), component.user.len());
+          prop_assert_eq!(field(2), component.repo.len());
+          let num_edges = dataset
+            .contributions()
+            .iter()
+            .filter(|c| labels.ids().user[c.idx.user] == component_id)
+            .count();
+          prop_assert_eq!(field(3), num_edges);
+
+          let size = component.user.len() + component.repo.len();
+          let exact = exact_diameter(component, &dataset, &mut workspace);
+          prop_assert_eq!(&row[7], (size <= exact_diameter_max_size).to_string());
+          if size <= exact_diameter_max_size {
+            prop_assert_eq!(field(6), exact);
+          } else {
+            prop_assert!(field(6) <= exact);
+          }
+          prop_assert!(
+            row[8].split(';').filter(|name| !name.is_empty()).count() <= 2
+          );
+        }
+      }
+  }
+}
@@ -1,10 +1,15 @@
 use crate::{
   dataset::{Dataset, Lens},
-  traversal::{default_visited, traverse, Component, Node},
-  UserRepoPair,
+  parallel_traversal::ParallelTraversalWorkspace,
+  traversal::{default_visited, traverse, Component, Node, VisitedMark},
+  union_find::ConcurrentUnionFind,
+  ItemType, UserRepoPair,
 };
+use rayon::prelude::*;
+use serde::Serialize;
 /// MUCH better perf on pop
 use std::collections::BTreeSet as Set;
+use std::str::FromStr;
 
 struct ComponentIterator<'a, F> {
   dataset: &'a Dataset,
@@ -12,6 +17,7 @@ struct ComponentIterator<'a, F> {
   not_visited: UserRepoPair<Set<usize>>,
   empty: bool,
   callback: F,
+  parallel_workspace: Option<ParallelTraversalWorkspace>,
 }
 
 pub fn pop<T>(set: &mut Set<T>) -> Option<T>
@@ -43,10 +49,8 @@ where
     let callback = &self.callback;
     let not_visited = &mut self.not_visited;
 
-    let mut component = if let Some(start) = start {
-      start.set_visited(&mut self.visited);
-      callback(start);
-      start.into()
+    let start = if let Some(start) = start {
+      start
     } else {
       self.empty = true;
       assert!(
@@ -56,25 +60,47 @@ where
       return None;
     };
 
-    traverse(
-      &mut component,
-      &mut self.visited,
-      self.dataset,
-      None,
-      |node, _| {
-        callback(node);
-        let Node { item_type, idx } = node;
-        let present = not_visited.as_mut()[item_type].remove(&idx);
-        debug_assert!(present);
-      },
-    );
+    start.set_visited(&mut self.visited);
+    callback(start);
+    let mut component: Component = start.into();
+
+    if let Some(workspace) = &mut self.parallel_workspace {
+      let visited = &mut self.visited;
+      let found = workspace.traverse(start, self.dataset, None, |_, _| {});
+      for (item_type, idxs) in found.as_ref().iter_with() {
+        // skip the start, which was already handled
+        for &idx in &idxs.idxs()[(item_type == start.item_type) as usize..] {
+          callback(Node { item_type, idx });
+          visited[item_type].visit(idx);
+          let present = not_visited.as_mut()[item_type].remove(&idx);
+          debug_assert!(present);
+        }
+        component[item_type] = idxs.idxs().to_vec();
+      }
+    } else {
+      traverse(
+        &mut component,
+        &mut self.visited,
+        self.dataset,
+        None,
+        |node, _| {
+          callback(node);
+          let Node { item_type, idx } = node;
+          let present = not_visited.as_mut()[item_type].remove(&idx);
+          debug_assert!(present);
+        },
+      );
+    }
 
     Some(component)
   }
 }
 
+/// `parallel` selects the parallel (direction optimizing) traversal, which is
+/// worth it for datasets with a large giant component.
 pub fn components_callback<'a>(
   dataset: &'a Dataset,
+  parallel: bool,
   callback: impl Fn(Node) + 'a,
 ) -> impl Iterator<Item = Component> + 'a {
   ComponentIterator {
@@ -83,11 +109,165 @@ pub fn components_callback<'a>(
     not_visited: dataset.lens().map(|l| (0..l).collect()),
     empty: false,
     callback,
+    parallel_workspace: if parallel {
+      Some(ParallelTraversalWorkspace::new(dataset.lens()))
+    } else {
+      None
+    },
   }
 }
 
 pub fn components(dataset: &Dataset) -> impl Iterator<Item = Component> + '_ {
-  components_callback(dataset, |_| {})
+  components_callback(dataset, false, |_| {})
+}
+
+/// How to find components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ComponentsMethod {
+  Bfs,
+  ParallelBfs,
+  UnionFind,
+}
+
+impl FromStr for ComponentsMethod {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let out = match s {
+      "bfs" => Self::Bfs,
+      "parallel-bfs" => Self::ParallelBfs,
+      "union-find" => Self::UnionFind,
+      _ => return Err(format!("Unrecognized components method: {}", s)),
+    };
+
+    Ok(out)
+  }
+}
+
+/// Component id of every node. Ids are in the order `components` finds
+/// components in: by smallest user and then by smallest repo for components
+/// without users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentLabels {
+  ids_v: UserRepoPair<Vec<usize>>,
+  num_components_v: usize,
+}
+
+impl ComponentLabels {
+  #[must_use]
+  pub fn ids(&self) -> &UserRepoPair<Vec<usize>> {
+    &self.ids_v
+  }
+
+  #[must_use]
+  pub fn num_components(&self) -> usize {
+    self.num_components_v
+  }
+
+  #[must_use]
+  pub fn from_components<'a>(
+    lens: UserRepoPair<usize>,
+    components: impl IntoIterator<Item = &'a Component>,
+  ) -> Self {
+    let mut ids_v = lens.map(|l| vec![usize::MAX; l]);
+    let mut num_components_v = 0;
+    for (id, component) in components.into_iter().enumerate() {
+      for (item_type, idxs) in component.as_ref().iter_with() {
+        for &idx in idxs {
+          ids_v[item_type][idx] = id;
+        }
+      }
+      num_components_v = id + 1;
+    }
+    debug_assert!(ids_v
+      .as_ref()
+      .into_iter()
+      .all(|ids| ids.iter().all(|&id| id < num_components_v)));
+
+    Self {
+      ids_v,
+      num_components_v,
+    }
+  }
+
+  /// Components (ordered by id) with sorted items.
+  #[must_use]
+  pub fn components(&self) -> Vec<Component> {
+    let mut out = vec![Component::default(); self.num_components()];
+    for (item_type, ids) in self.ids().as_ref().iter_with() {
+      for (idx, &id) in ids.iter().enumerate() {
+        out[id][item_type].push(idx);
+      }
+    }
+    out
+  }
+}
+
+/// Parallel union find over the contributions. Much faster than repeated
+/// traversal for large datasets.
+#[must_use]
+pub fn union_find_labels(dataset: &Dataset) -> ComponentLabels {
+  let lens = dataset.lens();
+  let flat =
+    |item_type: ItemType, idx: usize| Node { item_type, idx }.to_flat(lens);
+
+  let sets = ConcurrentUnionFind::new(lens.user + lens.repo);
+  dataset.contributions().par_iter().for_each(|contrib| {
+    sets.union(
+      flat(ItemType::User, contrib.idx.user),
+      flat(ItemType::Repo, contrib.idx.repo),
+    )
+  });
+  let roots = sets.roots();
+  drop(sets);
+
+  // roots are the smallest node in each component, so numbering them in
+  // order gives the same order as traversal
+  let mut root_ids = vec![usize::MAX; roots.len()];
+  let mut num_components_v = 0;
+  for (i, &root) in roots.iter().enumerate() {
+    if root == i {
+      root_ids[i] = num_components_v;
+      num_components_v += 1;
+    }
+  }
+
+  let ids_v = lens.map_with(|l, item_type| {
+    (0..l)
+      .into_par_iter()
+      .map(|idx| root_ids[roots[flat(item_type, idx)]])
+      .collect()
+  });
+
+  ComponentLabels {
+    ids_v,
+    num_components_v,
+  }
+}
+
+/// All components (in the same order for each method) along with the
+/// labels. The callback is called for every node found by traversal methods
+/// and isn't called for union find.
+pub fn components_labels(
+  dataset: &Dataset,
+  method: ComponentsMethod,
+  callback: impl Fn(Node),
+) -> (Vec<Component>, ComponentLabels) {
+  type CM = ComponentsMethod;
+  match method {
+    CM::Bfs | CM::ParallelBfs => {
+      let components: Vec<_> =
+        components_callback(dataset, method == CM::ParallelBfs, callback)
+          .collect();
+      let labels =
+        ComponentLabels::from_components(dataset.lens(), &components);
+      (components, labels)
+    }
+    CM::UnionFind => {
+      let labels = union_find_labels(dataset);
+      (labels.components(), labels)
+    }
+  }
 }
 
 #[cfg(test)]
@@ -119,6 +299,20 @@ mod test {
     dataset: &Dataset,
   ) -> Result<HashSet<Component>, TestCaseError> {
     let actual: HashSet<_> = components(dataset).map(sort_component).collect();
+    let parallel: HashSet<_> = components_callback(dataset, true, |_| {})
+      .map(sort_component)
+      .collect();
+    proptest::prop_assert_eq!(&actual, &parallel);
+
+    let (bfs, bfs_labels) =
+      components_labels(dataset, ComponentsMethod::Bfs, |_| {});
+    let (union_find, union_find_labels) =
+      components_labels(dataset, ComponentsMethod::UnionFind, |_| {});
+    proptest::prop_assert_eq!(&bfs_labels, &union_find_labels);
+    proptest::prop_assert_eq!(
+      bfs.into_iter().map(sort_component).collect::<Vec<_>>(),
+      union_find
+    );
     for component in actual.iter() {
       for (item_type, idxs) in component.as_ref().iter_with() {
         for &idx in idxs {
@@ -75,9 +75,8 @@ pub fn gen_graph<R: Rng + ?Sized>(dataset: &Dataset, rng: &mut R) -> Dataset {
     }
   }
 
-  let &actual_last = bins.last().expect("empty input not allowed");
-
-  if actual_last != last {
+  // small inputs might not fill a single bin
+  if bins.last() != Some(&last) {
     bins.push(last);
   }
 
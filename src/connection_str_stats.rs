@@ -1,10 +1,11 @@
 use crate::{
   connection_strength::{
     bin_float, bin_float_place, ConnectionStrength, ConnectionStrengthValue,
-    ExpectationAccelerator,
+    ExpectationAccelerator, StrengthContext,
   },
   dataset::{Dataset, DatasetNameID},
   degree_dist_csv::save_sort_items,
+  hubs::HubFilter,
   projected_graph::transitive_edge_compute,
   ItemType,
 };
@@ -38,6 +39,27 @@ pub struct ConnectionStrengthExpectedCsvEntry<ID> {
   pub example_github_id_second: ID,
 }
 
+/// Summary statistics over all projected pairs.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionStrStats {
+  pub total_contributions: f64,
+  pub total_degree: f64,
+  pub strength_over_degree: f64,
+  pub strength_over_contributions: f64,
+  pub mean_expected: f64,
+  pub mean_strength: f64,
+  pub mean_sqr_strength: f64,
+  pub mean_sqr_expected: f64,
+  pub mean_strength_expected: f64,
+  pub correlation_strength_expected: f64,
+  pub strength_variance: f64,
+  pub mean_normalized_strength: f64,
+  pub normalized_strength_variance: f64,
+  pub normalized_strength_mean_sqr: f64,
+  pub mean_z_score_strength: f64,
+  pub z_score_strength_variance: f64,
+}
+
 type CountExamples1<ID> = (usize, ID);
 type CountExamples2<ID> = (usize, ID, ID);
 
@@ -45,6 +67,7 @@ struct State<T: ConnectionStrength, ID> {
   degree_counts: Map<usize, CountExamples1<ID>>,
   strength_counts: Map<T::Value, CountExamples2<ID>>,
   strength_normalized_counts: Map<NotNan<f64>, CountExamples2<ID>>,
+  strength_z_score_counts: Map<NotNan<f64>, CountExamples2<ID>>,
   expected_counts: Map<NotNan<f64>, CountExamples2<ID>>,
   strength_expected_counts: Map<(NotNan<f64>, NotNan<f64>), CountExamples2<ID>>,
   total_expected: f64,
@@ -54,6 +77,8 @@ struct State<T: ConnectionStrength, ID> {
   total_strength_expected: f64,
   total_strength_normalized: f64,
   total_sqr_strength_normalized: f64,
+  total_strength_z_score: f64,
+  total_sqr_strength_z_score: f64,
   count: usize,
 }
 
@@ -64,7 +89,7 @@ pub fn save_connection_str_stats<T, V, D>(
   accelerator: &ExpectationAccelerator<V>,
   dataset: &Dataset,
   dataset_info: &D,
-) -> Result<()>
+) -> Result<ConnectionStrStats>
 where
   T: ConnectionStrength,
   V: ConnectionStrength,
@@ -74,6 +99,7 @@ where
     degree_counts: Default::default(),
     strength_counts: Default::default(),
     strength_normalized_counts: Default::default(),
+    strength_z_score_counts: Default::default(),
     expected_counts: Default::default(),
     strength_expected_counts: Default::default(),
     total_expected: Default::default(),
@@ -83,6 +109,8 @@ where
     total_strength_expected: Default::default(),
     total_strength_normalized: Default::default(),
     total_sqr_strength_normalized: Default::default(),
+    total_strength_z_score: Default::default(),
+    total_sqr_strength_z_score: Default::default(),
     count: Default::default(),
   };
   let state = Mutex::new(state);
@@ -93,12 +121,15 @@ where
       .map(|(end_idx, contrib_idxs)| {
         let end_idx: usize = end_idx;
 
-        let strength =
-          connection_strength.strength(item_type, &contrib_idxs, dataset);
+        let strength = connection_strength.strength(&StrengthContext::new(
+          item_type,
+          &contrib_idxs,
+          dataset,
+        ));
 
-        let expected = accelerator.expectation([start_idx, end_idx]);
+        let (expected, var) = accelerator.moments([start_idx, end_idx]);
 
-        (strength, expected, end_idx)
+        (strength, expected, var, end_idx)
       })
       .collect();
 
@@ -111,7 +142,7 @@ where
       .or_insert((0, example_github_id_first))
       .0 += values.len();
     state.count += values.len();
-    for (strength, expected, end_idx) in values {
+    for (strength, expected, var, end_idx) in values {
       let example_github_id_second = dataset_info.get_id(item_type, end_idx);
 
       let start_triple = (0, example_github_id_first, example_github_id_second);
@@ -130,6 +161,16 @@ where
         .entry(bin_float(strength_normalized))
         .or_insert(start_triple)
         .0 += 1;
+      let strength_z_score = if var > 0. {
+        (strength - expected) / var.sqrt()
+      } else {
+        0.
+      };
+      state
+        .strength_z_score_counts
+        .entry(bin_float_place(strength_z_score, 2))
+        .or_insert(start_triple)
+        .0 += 1;
       state
         .expected_counts
         .entry(bin_float(expected))
@@ -148,15 +189,18 @@ where
       state.total_strength_expected += strength * expected;
       state.total_strength_normalized += strength_normalized;
       state.total_sqr_strength_normalized += strength_normalized.powi(2);
+      state.total_strength_z_score += strength_z_score;
+      state.total_sqr_strength_z_score += strength_z_score.powi(2);
     }
   };
 
-  transitive_edge_compute(item_type, dataset, f);
+  transitive_edge_compute(item_type, dataset, &HubFilter::default(), f);
 
   let State {
     degree_counts,
     strength_counts,
     strength_normalized_counts,
+    strength_z_score_counts,
     expected_counts,
     strength_expected_counts,
     total_expected,
@@ -166,6 +210,8 @@ where
     total_strength_expected,
     total_strength_normalized,
     total_sqr_strength_normalized,
+    total_strength_z_score,
+    total_sqr_strength_z_score,
     count,
   } = state.into_inner().unwrap();
 
@@ -184,41 +230,69 @@ where
   let mean_strength_normalized = total_strength_normalized / count as f64;
   let mean_sqr_strength_normalized =
     total_sqr_strength_normalized / count as f64;
+  let mean_strength_z_score = total_strength_z_score / count as f64;
+  let mean_sqr_strength_z_score = total_sqr_strength_z_score / count as f64;
+
+  let stats = ConnectionStrStats {
+    total_contributions,
+    total_degree,
+    strength_over_degree: total_strength / total_degree,
+    strength_over_contributions: total_strength / total_contributions,
+    mean_expected,
+    mean_strength,
+    mean_sqr_strength,
+    mean_sqr_expected,
+    mean_strength_expected,
+    correlation_strength_expected: (mean_strength_expected
+      - mean_strength * mean_expected)
+      / ((mean_sqr_strength - mean_strength.powi(2)).sqrt()
+        * (mean_sqr_expected - mean_expected.powi(2)).sqrt()),
+    strength_variance: mean_sqr_strength - mean_strength.powi(2),
+    mean_normalized_strength: mean_strength_normalized,
+    normalized_strength_variance: mean_sqr_strength_normalized
+      - mean_strength_normalized.powi(2),
+    normalized_strength_mean_sqr: mean_sqr_strength_normalized,
+    mean_z_score_strength: mean_strength_z_score,
+    z_score_strength_variance: mean_sqr_strength_z_score
+      - mean_strength_z_score.powi(2),
+  };
 
-  println!("total contributions is {}", total_contributions);
-  println!("total degree is {}", total_degree);
+  println!("total contributions is {}", stats.total_contributions);
+  println!("total degree is {}", stats.total_degree);
   println!(
     "total strength over total degree is {}",
-    total_strength / total_degree
+    stats.strength_over_degree
   );
   println!(
     "total strength over total contributions is {}",
-    total_strength / total_contributions
+    stats.strength_over_contributions
   );
-  println!("mean expected is {}", mean_expected);
-  println!("mean strength is {}", mean_strength);
-  println!("mean sqr strength is {}", mean_sqr_strength);
-  println!("mean sqr expected is {}", mean_sqr_expected);
-  println!("mean strength expected is {}", mean_strength_expected);
+  println!("mean expected is {}", stats.mean_expected);
+  println!("mean strength is {}", stats.mean_strength);
+  println!("mean sqr strength is {}", stats.mean_sqr_strength);
+  println!("mean sqr expected is {}", stats.mean_sqr_expected);
+  println!("mean strength expected is {}", stats.mean_strength_expected);
   println!(
     "correlation strength-expected is {}",
-    (mean_strength_expected - mean_strength * mean_expected)
-      / ((mean_sqr_strength - mean_strength.powi(2)).sqrt()
-        * (mean_sqr_expected - mean_expected.powi(2)).sqrt())
+    stats.correlation_strength_expected
   );
-  println!("mean strength expected is {}", mean_strength_expected);
+  println!("strength variance is {}", stats.strength_variance);
   println!(
-    "strength variance is {}",
-    mean_sqr_strength - mean_strength.powi(2)
+    "mean normalized strength is {}",
+    stats.mean_normalized_strength
   );
-  println!("mean normalized strength is {}", mean_strength_normalized);
   println!(
     "normalized strength variance is {}",
-    mean_sqr_strength_normalized - mean_strength_normalized.powi(2)
+    stats.normalized_strength_variance
   );
   println!(
     "normalized strength mean sqr is {}",
-    mean_sqr_strength_normalized
+    stats.normalized_strength_mean_sqr
+  );
+  println!("mean z-score strength is {}", stats.mean_z_score_strength);
+  println!(
+    "z-score strength variance is {}",
+    stats.z_score_strength_variance
   );
 
   save_sort_items(
@@ -260,6 +334,20 @@ where
     },
   )?;
 
+  save_sort_items(
+    &output_dir.join("strengths_z_score.csv"),
+    strength_z_score_counts,
+    |(strength, _)| *strength,
+    |(strength, (count, example_github_id_first, example_github_id_second))| {
+      ConnectionStrengthCsvEntry {
+        strength: strength.to_serializable(),
+        count,
+        example_github_id_first,
+        example_github_id_second,
+      }
+    },
+  )?;
+
   save_sort_items(
     &output_dir.join("expected.csv"),
     expected_counts,
@@ -290,5 +378,5 @@ where
     },
   )?;
 
-  Ok(())
+  Ok(stats)
 }
@@ -2,13 +2,14 @@ use crate::{
   dataset::{Dataset, Lens},
   edge_vec::EdgeVec,
   progress_bar::get_bar,
+  stats::ln_hypergeometric_sf,
   ItemType, UserRepoPair,
 };
 use fnv::FnvHashMap as Map;
 use indicatif::ParallelProgressIterator;
-use itertools::Itertools;
 use ordered_float::NotNan;
 use rayon::prelude::*;
+use serde::Serialize;
 use std::{fmt, hash::Hash, iter, marker::PhantomData, ops, str::FromStr};
 
 pub trait ConnectionStrengthValue:
@@ -25,6 +26,10 @@ pub trait ConnectionStrengthValue:
 {
   type S: serde::Serialize;
 
+  /// Whether values are whole counts (e.g. of shared items or events) rather
+  /// than scores which may be fractional or negative.
+  const IS_COUNT: bool;
+
   fn from_float(v: f64) -> anyhow::Result<Self>;
 
   fn to_float(self) -> f64;
@@ -49,6 +54,8 @@ pub fn bin_float(v: f64) -> NotNan<f64> {
 impl ConnectionStrengthValue for NotNan<f64> {
   type S = f64;
 
+  const IS_COUNT: bool = false;
+
   fn from_float(v: f64) -> anyhow::Result<Self> {
     Ok(NotNan::new(v)?)
   }
@@ -69,6 +76,8 @@ impl ConnectionStrengthValue for NotNan<f64> {
 impl ConnectionStrengthValue for usize {
   type S = Self;
 
+  const IS_COUNT: bool = true;
+
   fn from_float(v: f64) -> anyhow::Result<Self> {
     if v.fract() != 0. {
       return Err(anyhow::anyhow!(
@@ -92,8 +101,26 @@ impl ConnectionStrengthValue for usize {
   }
 }
 
+/// Middle items of the other type with the same number of other
+/// connections.
+#[derive(Clone, Copy, Debug, Default)]
+struct MiddleGroup {
+  /// number of connections other than ours
+  pow: i32,
+  /// totals over the middle items of the mean operation with the other
+  /// connections, of its square and of the mean squared operation
+  mean_op: f64,
+  mean_op_sqr: f64,
+  mean_sqr_op: f64,
+}
+
+/// Under the model each middle item connects to the other item with
+/// probability 1 - (1 - p)^pow where p is the other item's share of all
+/// contributions. A connecting middle item adds the operation with one of its
+/// other connections chosen uniformly. Moments are conditional on the pair
+/// being connected at all.
 pub struct ExpectationAccelerator<'a, T: ConnectionStrength> {
-  cached_items: EdgeVec<(i32, f64)>,
+  cached_items: EdgeVec<MiddleGroup>,
   overall_counts: Vec<i32>,
   dataset: &'a Dataset,
   item_type: ItemType,
@@ -112,7 +139,7 @@ impl<'a, T: ConnectionStrength> ExpectationAccelerator<'a, T> {
         let contribs = &dataset.contribution_idxs()[item_type][idx];
 
         let mut overall_count = 0;
-        let mut totals = Map::default();
+        let mut totals: Map<i32, MiddleGroup> = Map::default();
         for &contrib_idx in contribs {
           let contrib = dataset.contributions()[contrib_idx];
 
@@ -130,7 +157,7 @@ impl<'a, T: ConnectionStrength> ExpectationAccelerator<'a, T> {
             continue;
           }
 
-          let total_operation = other_contrib_idxs
+          let (total_operation, total_sqr_operation) = other_contrib_idxs
             .iter()
             .map(|&other_contrib_idx| {
               debug_assert_eq!(
@@ -150,17 +177,25 @@ impl<'a, T: ConnectionStrength> ExpectationAccelerator<'a, T> {
 
               T::operation([contrib.num, other_contrib.num]).to_float()
             })
-            .sum::<f64>();
+            .fold((0., 0.), |(total, total_sqr), op| {
+              (total + op, total_sqr + op.powi(2))
+            });
 
           let len_other_than_us = (other_contrib_idxs.len() - 1) as i32;
 
           overall_count += len_other_than_us;
 
-          *totals.entry(len_other_than_us).or_insert(0.) +=
-            total_operation / len_other_than_us as f64;
+          let mean_op = total_operation / len_other_than_us as f64;
+          let group = totals.entry(len_other_than_us).or_insert(MiddleGroup {
+            pow: len_other_than_us,
+            ..Default::default()
+          });
+          group.mean_op += mean_op;
+          group.mean_op_sqr += mean_op.powi(2);
+          group.mean_sqr_op += total_sqr_operation / len_other_than_us as f64;
         }
 
-        (totals.into_iter().collect(), overall_count)
+        (totals.into_values().collect(), overall_count)
       })
       .unzip();
 
@@ -173,51 +208,137 @@ impl<'a, T: ConnectionStrength> ExpectationAccelerator<'a, T> {
     }
   }
 
+  /// Mean and variance of the strength from `idx` to `other_idx`.
+  fn directed_moments(&self, idx: usize, other_idx: usize) -> (f64, f64) {
+    let total_degree = self.dataset.contributions().len() as f64;
+    let other_degree =
+      self.dataset.contribution_idxs()[self.item_type][other_idx].len() as f64;
+    let p = other_degree / total_degree;
+
+    let (mean, mean_sqr, mean_sqr_correction) = self.cached_items[idx]
+      .iter()
+      .fold((0., 0., 0.), |(mean, mean_sqr, correction), group| {
+        let q = 1. - (1. - p).powi(group.pow);
+        (
+          mean + q * group.mean_op,
+          mean_sqr + q * group.mean_sqr_op,
+          correction + q.powi(2) * group.mean_op_sqr,
+        )
+      });
+    // E[S^2] for independent middle items
+    let mean_sqr = mean_sqr + mean.powi(2) - mean_sqr_correction;
+
+    let connected = 1. - (1. - p).powi(self.overall_counts[idx]);
+    let mean = mean / connected;
+    let var = (mean_sqr / connected - mean.powi(2)).max(0.);
+
+    (mean, var)
+  }
+
   #[must_use]
   pub fn expectation(&self, items_idxs: [usize; 2]) -> f64 {
-    let total_degree = self.dataset.contributions().len() as f64;
-    items_idxs
+    self.moments(items_idxs).0
+  }
+
+  /// Expectation and variance of the strength (each averaged over both
+  /// directions).
+  #[must_use]
+  pub fn moments(&self, items_idxs: [usize; 2]) -> (f64, f64) {
+    let [a, b] = items_idxs;
+    let (l_mean, l_var) = self.directed_moments(a, b);
+    let (r_mean, r_var) = self.directed_moments(b, a);
+    ((l_mean + r_mean) / 2., (l_var + r_var) / 2.)
+  }
+}
+
+/// Everything a connection strength may use to score one pair of items of
+/// the same type.
+#[derive(Clone, Copy)]
+pub struct StrengthContext<'a> {
+  pub item_type: ItemType,
+  /// Contribution pairs (one per shared item of the other type) connecting
+  /// the two items, with the first item's contribution first.
+  pub contrib_idxs: &'a [[usize; 2]],
+  pub dataset: &'a Dataset,
+}
+
+impl<'a> StrengthContext<'a> {
+  #[must_use]
+  pub fn new(
+    item_type: ItemType,
+    contrib_idxs: &'a [[usize; 2]],
+    dataset: &'a Dataset,
+  ) -> Self {
+    debug_assert!(!contrib_idxs.is_empty());
+
+    Self {
+      item_type,
+      contrib_idxs,
+      dataset,
+    }
+  }
+
+  #[must_use]
+  pub fn item_idxs(&self) -> [usize; 2] {
+    let get_items = |pair: &[usize; 2]| {
+      pair.map(|idx| self.dataset.contributions()[idx].idx[self.item_type])
+    };
+
+    let out = get_items(&self.contrib_idxs[0]);
+
+    debug_assert!(self.contrib_idxs.iter().all(|p| get_items(p) == out));
+
+    out
+  }
+
+  #[must_use]
+  pub fn num_shared(&self) -> usize {
+    self.contrib_idxs.len()
+  }
+
+  /// Number of items of the other type each item is connected to.
+  #[must_use]
+  pub fn degrees(&self) -> [usize; 2] {
+    self
+      .item_idxs()
+      .map(|idx| self.dataset.contribution_idxs()[self.item_type][idx].len())
+  }
+
+  /// Degree of each shared item of the other type.
+  pub fn shared_degrees(&self) -> impl Iterator<Item = usize> + 'a {
+    let (item_type, dataset) = (self.item_type.other(), self.dataset);
+    self.contrib_idxs.iter().map(move |pair| {
+      let idx = dataset.contributions()[pair[0]].idx[item_type];
+      dataset.contribution_idxs()[item_type][idx].len()
+    })
+  }
+
+  /// Number of contributions of each item to each shared item.
+  pub fn nums(&self) -> impl Iterator<Item = [usize; 2]> + 'a {
+    let dataset = self.dataset;
+    self
+      .contrib_idxs
       .iter()
-      .zip(items_idxs.iter().rev())
-      .map(|(&idx, &other_idx)| {
-        let other_degree = self.dataset.contribution_idxs()[self.item_type]
-          [other_idx]
-          .len() as f64;
-        let p = other_degree / total_degree;
-
-        self.cached_items[idx]
-          .iter()
-          .map(|&(pow, mean_op)| (1. - (1. - p).powi(pow)) * mean_op)
-          .sum::<f64>()
-          / (1. - (1. - p).powi(self.overall_counts[idx]))
-      })
-      .sum::<f64>()
-      / 2.
+      .map(move |pair| pair.map(|idx| dataset.contributions()[idx].num))
   }
 }
 
 pub trait ConnectionStrength: Clone + Copy + fmt::Debug + Sync + Send {
   type Value: ConnectionStrengthValue;
 
-  fn strength(
-    &self,
-    _item_type: ItemType,
-    contrib_idxs: &[[usize; 2]],
-    dataset: &Dataset,
-  ) -> Self::Value {
-    contrib_idxs
-      .iter()
-      .map(|contrib_idxs| {
-        let (l, r) = contrib_idxs
-          .iter()
-          .map(|&idx| dataset.contributions()[idx].num)
-          .next_tuple()
-          .unwrap();
-        Self::operation([l, r])
-      })
-      .sum()
+  /// Name and any parameters which change the strengths, e.g. to check a
+  /// saved projection was made the same way.
+  fn describe(&self) -> String {
+    format!("{:?}", self)
   }
 
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    context.nums().map(Self::operation).sum()
+  }
+
+  /// Contribution of one shared item given both numbers of contributions to
+  /// it. Strengths which don't decompose this way override `strength`
+  /// instead and can't be normalized.
   fn operation(nums: [usize; 2]) -> Self::Value;
 }
 
@@ -280,33 +401,118 @@ impl<'a, T: ConnectionStrength> fmt::Debug for Normalized<'a, T> {
 impl<'a, T: ConnectionStrength> ConnectionStrength for Normalized<'a, T> {
   type Value = NotNan<f64>;
 
-  fn strength(
-    &self,
-    item_type: ItemType,
-    contrib_idxs: &[[usize; 2]],
-    dataset: &Dataset,
-  ) -> Self::Value {
-    let strength = self.inner.strength(item_type, contrib_idxs, dataset);
-
-    let get_items = |iter: &[usize; 2]| {
-      iter
-        .iter()
-        .map(|&idx| dataset.contributions()[idx].idx[item_type])
-        .collect_tuple()
-        .unwrap()
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    let strength = self.inner.strength(context);
+
+    let expected =
+      self.accelerators[context.item_type].expectation(context.item_idxs());
+
+    NotNan::new(strength.to_float() / expected).unwrap()
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// (strength - expectation) / standard deviation under the model of the
+/// `ExpectationAccelerator`.
+#[derive(Clone, Copy)]
+pub struct ZScore<'a, T: ConnectionStrength> {
+  pub inner: T,
+  pub accelerators: &'a UserRepoPair<ExpectationAccelerator<'a, T>>,
+}
+
+impl<'a, T: ConnectionStrength> fmt::Debug for ZScore<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "z_score_{:?}", &self.inner)
+  }
+}
+
+impl<'a, T: ConnectionStrength> ConnectionStrength for ZScore<'a, T> {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    let strength = self.inner.strength(context).to_float();
+
+    let (expected, var) =
+      self.accelerators[context.item_type].moments(context.item_idxs());
+
+    let z = if var > 0. {
+      (strength - expected) / var.sqrt()
+    } else {
+      0.
     };
 
-    let first = contrib_idxs.iter().next().unwrap();
-    let (l_idx, r_idx) = get_items(first);
+    NotNan::new(z).unwrap()
+  }
 
-    debug_assert!(contrib_idxs
-      .iter()
-      .map(get_items)
-      .all(|(l, r)| l == l_idx && r == r_idx));
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
 
-    let expected = self.accelerators[item_type].expectation([l_idx, r_idx]);
+/// -log10 of the probability that two items share at least as many items of
+/// the other type as they do if each picked its items uniformly at random
+/// (hypergeometric tail given both degrees).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Hypergeometric;
 
-    NotNan::new(strength.to_float() / expected).unwrap()
+impl ConnectionStrength for Hypergeometric {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    let [l, r] = context.degrees();
+
+    let ln_p = ln_hypergeometric_sf(
+      context.num_shared() as f64,
+      context.dataset.lens()[context.item_type.other()] as f64,
+      l as f64,
+      r as f64,
+    );
+
+    NotNan::new((-ln_p / std::f64::consts::LN_10).max(0.)).unwrap()
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Number of shared items and both degrees combined by `f`.
+fn degree_index(
+  context: &StrengthContext<'_>,
+  f: impl Fn(f64, f64, f64) -> f64,
+) -> NotNan<f64> {
+  let [l, r] = context.degrees().map(|d| d as f64);
+  NotNan::new(f(context.num_shared() as f64, l, r)).unwrap()
+}
+
+/// Shared items over the items either is connected to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Jaccard;
+
+impl ConnectionStrength for Jaccard {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    degree_index(context, |shared, l, r| shared / (l + r - shared))
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Salton's cosine: shared items over the geometric mean degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Cosine;
+
+impl ConnectionStrength for Cosine {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    degree_index(context, |shared, l, r| shared / (l * r).sqrt())
   }
 
   fn operation(_nums: [usize; 2]) -> Self::Value {
@@ -314,33 +520,194 @@ impl<'a, T: ConnectionStrength> ConnectionStrength for Normalized<'a, T> {
   }
 }
 
-/// Value is if its normalized.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Shared items over the mean degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Sorensen;
+
+impl ConnectionStrength for Sorensen {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    degree_index(context, |shared, l, r| 2. * shared / (l + r))
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Shared items over the smaller degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct HubPromoted;
+
+impl ConnectionStrength for HubPromoted {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    degree_index(context, |shared, l, r| shared / l.min(r))
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Shared items over the larger degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct HubDepressed;
+
+impl ConnectionStrength for HubDepressed {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    degree_index(context, |shared, l, r| shared / l.max(r))
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Newman's collaboration weighting: each shared item of degree k adds
+/// 1 / (k - 1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Newman;
+
+impl ConnectionStrength for Newman {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    // shared items always have degree at least 2
+    NotNan::new(context.shared_degrees().map(|k| 1. / (k - 1) as f64).sum())
+      .unwrap()
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// Resource allocation: each shared item of degree k adds 1 / k.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct ResourceAllocation;
+
+impl ConnectionStrength for ResourceAllocation {
+  type Value = NotNan<f64>;
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    NotNan::new(context.shared_degrees().map(|k| 1. / k as f64).sum()).unwrap()
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+/// How a connection strength is normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Normalization {
+  None,
+  /// Divided by the analytic expectation (`Normalized`).
+  Expectation,
+  /// z-score against the analytic expectation and variance (`ZScore`).
+  ZScore,
+  /// z-score against sampled null networks (`MonteCarloZ`).
+  MonteCarloZ,
+}
+
+impl Normalization {
+  const SUFFIXES: [(&'static str, Self); 3] = [
+    ("-normalized", Self::Expectation),
+    ("-z-score", Self::ZScore),
+    ("-monte-carlo-z", Self::MonteCarloZ),
+  ];
+
+  pub const ALL: [Self; 4] = [
+    Self::None,
+    Self::Expectation,
+    Self::ZScore,
+    Self::MonteCarloZ,
+  ];
+}
+
+/// Value is how its normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum ConnectionStrengthTypes {
-  NumCommonNodes(bool),
-  MinNumEvents(bool),
-  TotalNumEvents(bool),
-  GeometricMeanEvents(bool),
+  NumCommonNodes(Normalization),
+  MinNumEvents(Normalization),
+  TotalNumEvents(Normalization),
+  GeometricMeanEvents(Normalization),
+  Hypergeometric,
+  Jaccard,
+  Cosine,
+  Sorensen,
+  HubPromoted,
+  HubDepressed,
+  Newman,
+  ResourceAllocation,
+}
+
+impl ConnectionStrengthTypes {
+  /// Every type, with every normalization of those which can be normalized.
+  #[must_use]
+  pub fn all() -> Vec<Self> {
+    let normalizable: [fn(Normalization) -> Self; 4] = [
+      Self::NumCommonNodes,
+      Self::MinNumEvents,
+      Self::TotalNumEvents,
+      Self::GeometricMeanEvents,
+    ];
+    normalizable
+      .iter()
+      .flat_map(|t| Normalization::ALL.iter().map(move |&norm| t(norm)))
+      .chain([
+        Self::Hypergeometric,
+        Self::Jaccard,
+        Self::Cosine,
+        Self::Sorensen,
+        Self::HubPromoted,
+        Self::HubDepressed,
+        Self::Newman,
+        Self::ResourceAllocation,
+      ])
+      .collect()
+  }
 }
 
 impl FromStr for ConnectionStrengthTypes {
   type Err = String;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    let norm_s = "-normalized";
-    let c = |rest: &[_]| match rest {
-      [] => Ok(false),
-      [""] => Ok(true),
-      _ => Err(format!("unexpected suffix after '{}', got '{}'", norm_s, s)),
-    };
-    let strs: Vec<_> = s.split(norm_s).collect();
-    let out = match &strs[..] {
-      ["num-common-nodes", ref rest @ ..] => Self::NumCommonNodes(c(rest)?),
-      ["min-num-events", ref rest @ ..] => Self::MinNumEvents(c(rest)?),
-      ["total-num-events", ref rest @ ..] => Self::TotalNumEvents(c(rest)?),
-      ["geometric-mean-events", ref rest @ ..] => {
-        Self::GeometricMeanEvents(c(rest)?)
+    let (base, norm) = Normalization::SUFFIXES
+      .iter()
+      .find_map(|&(suffix, norm)| Some((s.strip_suffix(suffix)?, norm)))
+      .unwrap_or((s, Normalization::None));
+
+    // these don't decompose into operations, so can't be normalized
+    let unnormalized = |out| {
+      if norm == Normalization::None {
+        Ok(out)
+      } else {
+        Err(format!(
+          "connection strength type can't be normalized: {}",
+          s
+        ))
       }
+    };
+
+    let out = match base {
+      "num-common-nodes" => Self::NumCommonNodes(norm),
+      "min-num-events" => Self::MinNumEvents(norm),
+      "total-num-events" => Self::TotalNumEvents(norm),
+      "geometric-mean-events" => Self::GeometricMeanEvents(norm),
+      "hypergeometric" => unnormalized(Self::Hypergeometric)?,
+      "jaccard" => unnormalized(Self::Jaccard)?,
+      "cosine" => unnormalized(Self::Cosine)?,
+      "sorensen" => unnormalized(Self::Sorensen)?,
+      "hub-promoted" => unnormalized(Self::HubPromoted)?,
+      "hub-depressed" => unnormalized(Self::HubDepressed)?,
+      "newman" => unnormalized(Self::Newman)?,
+      "resource-allocation" => unnormalized(Self::ResourceAllocation)?,
       _ => {
         return Err(format!("Unrecognized connnection strength type: {}", s))
       }
@@ -423,3 +790,126 @@ fn basic_expectation() {
   assert!((accel.expectation([1, 3]) - v).abs() < EPS);
   assert!((accel.expectation([3, 1]) - v).abs() < EPS);
 }
+
+#[test]
+fn similarity_indices() {
+  use crate::{projected_graph::two_hop_neighbors, traversal::test::contrib};
+
+  let dataset = Dataset::new(
+    UserRepoPair { user: 3, repo: 5 },
+    vec![
+      contrib(0, 0),
+      contrib(0, 1),
+      contrib(0, 2),
+      contrib(1, 1),
+      contrib(1, 2),
+      contrib(1, 3),
+      contrib(1, 4),
+      contrib(2, 1),
+    ],
+  );
+
+  let contrib_idxs = &two_hop_neighbors(ItemType::User, 0, &dataset)[&1];
+  let context = StrengthContext::new(ItemType::User, contrib_idxs, &dataset);
+
+  assert_eq!(context.item_idxs(), [0, 1]);
+  assert_eq!(context.degrees(), [3, 4]);
+
+  const EPS: f64 = 1e-10;
+  let check = |actual: NotNan<f64>, expected: f64| {
+    assert!((actual.into_inner() - expected).abs() < EPS)
+  };
+
+  check(Jaccard.strength(&context), 2. / 5.);
+  check(Cosine.strength(&context), 2. / 12f64.sqrt());
+  check(Sorensen.strength(&context), 4. / 7.);
+  check(HubPromoted.strength(&context), 2. / 3.);
+  check(HubDepressed.strength(&context), 2. / 4.);
+  check(Newman.strength(&context), 1. / 2. + 1.);
+  check(ResourceAllocation.strength(&context), 1. / 3. + 1. / 2.);
+}
+
+#[test]
+fn expectation_variance() {
+  use crate::traversal::test::contrib_num;
+
+  let contributions = (0..10)
+    .map(|i| contrib_num(i, 0, 10 + i))
+    .chain((5..9).map(|i| contrib_num(i, 2, 10 + i)))
+    .chain(vec![
+      contrib_num(4, 1, 5),
+      contrib_num(4, 2, 10),
+      contrib_num(4, 3, 10),
+      contrib_num(11, 3, 10),
+    ])
+    .collect();
+
+  let dataset = Dataset::new(UserRepoPair { user: 12, repo: 4 }, contributions);
+  let item_type = ItemType::Repo;
+  let accel = ExpectationAccelerator::<MinNumEvents>::new(item_type, &dataset);
+
+  // enumerate every outcome of the model
+  let directed = |idx: usize, other_idx: usize| {
+    let p = dataset.contribution_idxs()[item_type][other_idx].len() as f64
+      / dataset.contributions().len() as f64;
+
+    let mut outcomes = vec![(1., 0.)];
+    for &contrib_idx in &dataset.contribution_idxs()[item_type][idx] {
+      let contrib = dataset.contributions()[contrib_idx];
+      let ops: Vec<_> = dataset.contribution_idxs()[item_type.other()]
+        [contrib.idx[item_type.other()]]
+      .iter()
+      .filter(|&&other| other != contrib_idx)
+      .map(|&other| {
+        MinNumEvents::operation([
+          contrib.num,
+          dataset.contributions()[other].num,
+        ]) as f64
+      })
+      .collect();
+      if ops.is_empty() {
+        continue;
+      }
+      let q = 1. - (1. - p).powi(ops.len() as i32);
+      let mut next = Vec::new();
+      for (prob, value) in outcomes {
+        next.push((prob * (1. - q), value));
+        for op in &ops {
+          next.push((prob * q / ops.len() as f64, value + op));
+        }
+      }
+      outcomes = next;
+    }
+
+    let connected: f64 =
+      outcomes.iter().filter(|o| o.1 > 0.).map(|o| o.0).sum();
+    let mean = outcomes
+      .iter()
+      .map(|&(prob, value)| prob * value)
+      .sum::<f64>()
+      / connected;
+    let var = outcomes
+      .iter()
+      .filter(|o| o.1 > 0.)
+      .map(|&(prob, value)| prob * (value - mean).powi(2))
+      .sum::<f64>()
+      / connected;
+    (mean, var)
+  };
+
+  const EPS: f64 = 1e-9;
+
+  for pair in [[2, 0], [1, 2], [1, 3], [0, 3]] {
+    let (l_mean, l_var) = directed(pair[0], pair[1]);
+    let (r_mean, r_var) = directed(pair[1], pair[0]);
+    let (mean, var) = accel.moments(pair);
+    assert!((mean - (l_mean + r_mean) / 2.).abs() < EPS);
+    assert!(
+      (var - (l_var + r_var) / 2.).abs() < EPS,
+      "{} {}",
+      var,
+      l_var
+    );
+    assert!((accel.expectation(pair) - mean).abs() < EPS);
+  }
+}
@@ -0,0 +1,312 @@
+//! Degree preserving null networks from the (global) curveball Markov chain
+//! (Strona et al., Carstens et al.). Unlike `configuration_model::gen_graph`
+//! every user and repo degree is kept exactly and no edges are dropped.
+//!
+//! In a trade two users keep the repos they share and randomly redistribute
+//! the rest (each keeping its degree). A global round pairs up every user and
+//! does all the trades in parallel. Many samples are drawn from one chain
+//! (`Chain`) with a burn-in before the first and thinning between samples.
+
+use crate::{
+  dataset::{Contribution, Dataset, Lens},
+  progress_bar::get_bar,
+  ItemType, UserRepoPair,
+};
+use indicatif::ProgressIterator;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::{cmp::Ordering, mem};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurveballOptions {
+  /// Global rounds from the dataset to the first sample. Each round trades
+  /// every user once, so every edge not shared by the paired users is up
+  /// for trade.
+  pub burn_in_rounds: usize,
+  /// Global rounds between consecutive samples of a chain.
+  pub thinning_rounds: usize,
+  /// Only trade repos with the same number of contributions, so each user
+  /// keeps the numbers of its contributions as well as its degree (repos
+  /// always do). Mixes more slowly.
+  pub preserve_nums: bool,
+}
+
+impl Default for CurveballOptions {
+  fn default() -> Self {
+    Self {
+      burn_in_rounds: 5,
+      thinning_rounds: 5,
+      preserve_nums: false,
+    }
+  }
+}
+
+/// (repo, num) of each contribution of a user, sorted by repo.
+type Neighbors = Vec<(usize, usize)>;
+
+fn trade<R: Rng + ?Sized>(
+  a: &mut Neighbors,
+  b: &mut Neighbors,
+  preserve_nums: bool,
+  rng: &mut R,
+) {
+  let (mut i, mut j) = (0, 0);
+  let (mut shared_a, mut shared_b) = (Vec::new(), Vec::new());
+  let (mut only_a, mut only_b) = (Vec::new(), Vec::new());
+  while i < a.len() || j < b.len() {
+    let order = match (a.get(i), b.get(j)) {
+      (Some(l), Some(r)) => l.0.cmp(&r.0),
+      (Some(_), None) => Ordering::Less,
+      (None, _) => Ordering::Greater,
+    };
+    match order {
+      Ordering::Less => {
+        only_a.push(a[i]);
+        i += 1;
+      }
+      Ordering::Greater => {
+        only_b.push(b[j]);
+        j += 1;
+      }
+      Ordering::Equal => {
+        shared_a.push(a[i]);
+        shared_b.push(b[j]);
+        i += 1;
+        j += 1;
+      }
+    }
+  }
+
+  if preserve_nums {
+    // trade separately within each number of contributions
+    only_a.sort_unstable_by_key(|&(repo, num)| (num, repo));
+    only_b.sort_unstable_by_key(|&(repo, num)| (num, repo));
+    let (mut i, mut j) = (0, 0);
+    while i < only_a.len() && j < only_b.len() {
+      let num = only_a[i].1.min(only_b[j].1);
+      let end_a = i + only_a[i..].partition_point(|e| e.1 == num);
+      let end_b = j + only_b[j..].partition_point(|e| e.1 == num);
+      trade_pool(&mut only_a[i..end_a], &mut only_b[j..end_b], rng);
+      i = end_a;
+      j = end_b;
+    }
+  } else {
+    trade_pool(&mut only_a, &mut only_b, rng);
+  }
+
+  shared_a.append(&mut only_a);
+  shared_b.append(&mut only_b);
+  shared_a.sort_unstable();
+  shared_b.sort_unstable();
+  *a = shared_a;
+  *b = shared_b;
+}
+
+/// Randomly redistribute the items of `a` and `b` keeping the lengths.
+fn trade_pool<R: Rng + ?Sized>(
+  a: &mut [(usize, usize)],
+  b: &mut [(usize, usize)],
+  rng: &mut R,
+) {
+  if a.is_empty() || b.is_empty() {
+    return;
+  }
+  let mut pool: Vec<_> = a.iter().chain(b.iter()).copied().collect();
+  pool.shuffle(rng);
+  let (new_a, new_b) = pool.split_at(a.len());
+  a.copy_from_slice(new_a);
+  b.copy_from_slice(new_b);
+}
+
+/// State of a curveball chain started from a dataset.
+pub struct Chain {
+  options: CurveballOptions,
+  lens: UserRepoPair<usize>,
+  neighbors: Vec<Neighbors>,
+  order: Vec<usize>,
+  burnt_in: bool,
+}
+
+impl Chain {
+  #[must_use]
+  pub fn new(dataset: &Dataset, options: &CurveballOptions) -> Self {
+    let neighbors: Vec<Neighbors> = dataset.contribution_idxs()[ItemType::User]
+      .iter()
+      .map(|contrib_idxs| {
+        let mut out: Neighbors = contrib_idxs
+          .iter()
+          .map(|&idx| {
+            let contrib = dataset.contributions()[idx];
+            (contrib.idx.repo, contrib.num)
+          })
+          .collect();
+        out.sort_unstable();
+        out
+      })
+      .collect();
+
+    Self {
+      options: *options,
+      lens: dataset.lens(),
+      order: (0..neighbors.len()).collect(),
+      neighbors,
+      burnt_in: false,
+    }
+  }
+
+  fn run_rounds<R: Rng + ?Sized>(&mut self, num_rounds: usize, rng: &mut R) {
+    let preserve_nums = self.options.preserve_nums;
+    let bar = get_bar(Some(num_rounds as u64), 1);
+    for _ in (0..num_rounds).progress_with(bar) {
+      self.order.shuffle(rng);
+      let round_seed: u64 = rng.gen();
+
+      let neighbors = &mut self.neighbors;
+      let mut paired: Vec<_> = self
+        .order
+        .iter()
+        .map(|&user| mem::take(&mut neighbors[user]))
+        .collect();
+      paired
+        .par_chunks_mut(2)
+        .enumerate()
+        .for_each(|(pair_idx, pair)| {
+          if let [a, b] = pair {
+            let mut rng =
+              StdRng::seed_from_u64(round_seed.wrapping_add(pair_idx as u64));
+            trade(a, b, preserve_nums, &mut rng);
+          }
+        });
+      for (&user, user_neighbors) in self.order.iter().zip(paired) {
+        self.neighbors[user] = user_neighbors;
+      }
+    }
+  }
+
+  /// The next sample: `burn_in_rounds` after the dataset for the first and
+  /// `thinning_rounds` after the previous sample for the rest.
+  pub fn sample<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Dataset {
+    let num_rounds = if self.burnt_in {
+      self.options.thinning_rounds
+    } else {
+      self.options.burn_in_rounds
+    };
+    self.burnt_in = true;
+    self.run_rounds(num_rounds, rng);
+
+    let contributions = self
+      .neighbors
+      .iter()
+      .enumerate()
+      .flat_map(|(user, user_neighbors)| {
+        user_neighbors.iter().map(move |&(repo, num)| Contribution {
+          idx: UserRepoPair { user, repo },
+          num,
+        })
+      })
+      .collect();
+
+    Dataset::new(self.lens, contributions)
+  }
+}
+
+/// Sample a network with the same degrees (and the same numbers of
+/// contributions for each repo) from a new chain started at `dataset`. Use
+/// `Chain` for many samples.
+#[must_use]
+pub fn gen_graph<R: Rng + ?Sized>(
+  dataset: &Dataset,
+  options: &CurveballOptions,
+  rng: &mut R,
+) -> Dataset {
+  Chain::new(dataset, options).sample(rng)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dataset;
+  use proptest::prelude::*;
+
+  /// Sorted numbers of contributions of each item.
+  fn nums(item_type: ItemType, dataset: &Dataset) -> Vec<Vec<usize>> {
+    dataset.contribution_idxs()[item_type]
+      .iter()
+      .map(|contrib_idxs| {
+        let mut nums: Vec<_> = contrib_idxs
+          .iter()
+          .map(|&idx| dataset.contributions()[idx].num)
+          .collect();
+        nums.sort_unstable();
+        nums
+      })
+      .collect()
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_degrees(
+        dataset in dataset::small_strategy(),
+        burn_in_rounds in 0usize..4,
+        thinning_rounds in 0usize..3,
+        preserve_nums in proptest::bool::ANY,
+        seed in 0u64..1000,
+      ) {
+        let options = CurveballOptions {
+          burn_in_rounds,
+          thinning_rounds,
+          preserve_nums,
+        };
+        let rng = &mut StdRng::seed_from_u64(seed);
+        let mut chain = Chain::new(&dataset, &options);
+        let first = chain.sample(rng);
+        let contributions = |dataset: &Dataset| -> Vec<_> {
+          dataset
+            .contributions()
+            .iter()
+            .map(|c| (c.idx.user, c.idx.repo, c.num))
+            .collect()
+        };
+        prop_assert_eq!(
+          contributions(&first),
+          contributions(&gen_graph(
+            &dataset,
+            &options,
+            &mut StdRng::seed_from_u64(seed)
+          ))
+        );
+        for sample in [first, chain.sample(rng)] {
+          prop_assert_eq!(sample.lens(), dataset.lens());
+          prop_assert_eq!(
+            sample.contributions().len(),
+            dataset.contributions().len()
+          );
+          let mut edges: Vec<_> =
+            sample.contributions().iter().map(|c| c.idx).collect();
+          edges.sort_unstable_by_key(|idx| (idx.user, idx.repo));
+          edges.dedup();
+          prop_assert_eq!(edges.len(), sample.contributions().len());
+
+          for item_type in [ItemType::User, ItemType::Repo] {
+            let degrees = |dataset: &Dataset| -> Vec<usize> {
+              dataset.contribution_idxs()[item_type]
+                .iter()
+                .map(|idxs| idxs.len())
+                .collect()
+            };
+            prop_assert_eq!(degrees(&sample), degrees(&dataset));
+          }
+          prop_assert_eq!(
+            nums(ItemType::Repo, &sample),
+            nums(ItemType::Repo, &dataset)
+          );
+          if preserve_nums {
+            prop_assert_eq!(
+              nums(ItemType::User, &sample),
+              nums(ItemType::User, &dataset)
+            );
+          }
+        }
+      }
+  }
+}
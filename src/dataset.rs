@@ -8,7 +8,7 @@ use crate::{
   progress_bar::get_bar,
   EdgeVec, HasGithubID, ItemType, Repo, User, UserRepoPair,
 };
-use fnv::{FnvHashMap as Map, FnvHashSet as Set};
+use fnv::{FnvHashMap as Map, FnvHashSet as Set, FnvHasher};
 use indicatif::ProgressIterator;
 #[cfg(test)]
 use proptest::prelude::*;
@@ -16,7 +16,7 @@ use serde::Serialize;
 use std::{
   fmt,
   fs::{self, File},
-  hash::Hash,
+  hash::{Hash, Hasher},
   path::{Path, PathBuf},
 };
 use unzip_n::unzip_n;
@@ -367,6 +367,22 @@ impl Dataset {
     &self.contribution_idxs().repo
   }
 
+  /// Hash of the lens and contributions (in order) which is stable across
+  /// runs and platforms, for checking saved outputs belong to this dataset.
+  #[must_use]
+  pub fn fingerprint(&self) -> u64 {
+    let mut hasher = FnvHasher::default();
+    for (_, len) in self.lens().iter_with() {
+      hasher.write_u64(len as u64);
+    }
+    for contrib in self.contributions() {
+      hasher.write_u64(contrib.idx.user as u64);
+      hasher.write_u64(contrib.idx.repo as u64);
+      hasher.write_u64(contrib.num as u64);
+    }
+    hasher.finish()
+  }
+
   pub fn set_edges(
     &mut self,
     contributions_v: Vec<Contribution>,
@@ -528,6 +544,13 @@ fn strat_contributions(
   })
 }
 
+/// Datasets small enough for tests which project them or sample null
+/// networks from them many times.
+#[cfg(test)]
+pub fn small_strategy() -> impl Strategy<Value = Dataset> {
+  strategy(1usize..20, 1usize..20, 1usize..=4, 1usize..80)
+}
+
 #[cfg(test)]
 #[must_use]
 pub fn strategy(
@@ -1,47 +1,76 @@
 use crate::{
-  dataset::Dataset,
+  dataset::{Dataset, Lens},
+  parallel_traversal::AnyTraversalWorkspace,
   progress_bar::get_bar,
-  traversal::{default_visited, traverse, Component, Node},
+  traversal::{Component, Node, WorkspacePool},
   ItemType,
 };
-use indicatif::ParallelProgressIterator;
+use indicatif::{ParallelProgressIterator, ProgressBar};
 use rand::{distributions::Uniform, prelude::*};
 use rayon::prelude::*;
 
+/// `parallel` selects the parallel (direction optimizing) traversal.
 pub fn compute_pseudo_diameter(
   giant_component_node: Node,
   dataset: &Dataset,
+  parallel: bool,
 ) -> usize {
-  let mut next = giant_component_node;
+  let mut workspace = AnyTraversalWorkspace::new(dataset.lens(), parallel);
+  pseudo_diameter(giant_component_node, dataset, &mut workspace, true)
+}
+
+/// Pseudo diameter of the component containing `start` (a lower bound on the
+/// diameter) found by repeatedly traversing from the furthest lowest degree
+/// node.
+pub fn pseudo_diameter(
+  start: Node,
+  dataset: &Dataset,
+  workspace: &mut AnyTraversalWorkspace,
+  verbose: bool,
+) -> usize {
+  let mut next = start;
   let mut last_max_dist = 0;
   let mut last_min_degree = 0;
   loop {
-    let mut component = next.into();
-    let mut visited = default_visited(dataset);
-    next.set_visited(&mut visited);
     let mut max_dist = 0;
     let mut min_degree = 0;
-    println!(
-      "starting pseudo diameter traversal with dist {} and degree {}",
-      last_max_dist, last_min_degree
-    );
-
-    let bar = get_bar(None, 10000);
-
-    traverse(&mut component, &mut visited, dataset, None, |node, dist| {
-      bar.inc(1);
-      let degree = dataset.contribution_idxs()[node.item_type][node.idx].len();
-      if dist > max_dist || (dist == max_dist && degree < min_degree) {
-        next = node;
-        max_dist = dist;
-        min_degree = degree;
+    if verbose {
+      println!(
+        "starting pseudo diameter traversal with dist {} and degree {}",
+        last_max_dist, last_min_degree
+      );
+    }
+
+    let bar = if verbose {
+      get_bar(None, 10000)
+    } else {
+      ProgressBar::hidden()
+    };
+
+    let component = workspace.traverse(next, dataset, None, |_, _| bar.inc(1));
+
+    // the graph is bipartite, so all nodes at the same distance have the same
+    // type. The order within a distance depends on the traversal (and races
+    // in the parallel one), so ties are broken by index.
+    for (item_type, idxs) in component.as_ref().iter_with() {
+      for (&idx, &dist) in idxs.idxs().iter().zip(idxs.dists()) {
+        let degree = dataset.contribution_idxs()[item_type][idx].len();
+        if dist > max_dist
+          || (dist == max_dist && (degree, idx) < (min_degree, next.idx))
+        {
+          next = Node { item_type, idx };
+          max_dist = dist;
+          min_degree = degree;
+        }
       }
-    });
+    }
 
-    println!(
-      "finished pseudo diameter traversal with new dist {} and new degree {}",
-      max_dist, min_degree
-    );
+    if verbose {
+      println!(
+        "finished pseudo diameter traversal with new dist {} and new degree {}",
+        max_dist, min_degree
+      );
+    }
 
     assert!(max_dist >= last_max_dist);
 
@@ -53,13 +82,42 @@ pub fn compute_pseudo_diameter(
   }
 }
 
+/// Exact diameter of a component by traversing from every node, so this
+/// should only be used for small components.
+pub fn exact_diameter(
+  component: &Component,
+  dataset: &Dataset,
+  workspace: &mut AnyTraversalWorkspace,
+) -> usize {
+  component
+    .as_ref()
+    .iter_with()
+    .flat_map(|(item_type, idxs)| {
+      idxs.iter().map(move |&idx| Node { item_type, idx })
+    })
+    .map(|node| {
+      workspace
+        .traverse(node, dataset, None, |_, _| {})
+        .as_ref()
+        .into_iter()
+        .flat_map(|idxs| idxs.dists().iter().cloned())
+        .max()
+        .unwrap_or(0)
+    })
+    .max()
+    .unwrap_or(0)
+}
+
 #[must_use]
 pub fn average_distance(
   giant_component: &Component,
   num_samples: usize,
   dataset: &Dataset,
+  parallel: bool,
 ) -> Vec<(Node, f64)> {
   let bar = get_bar(Some(num_samples as u64), 10000);
+  let workspaces =
+    WorkspacePool::new(|| AnyTraversalWorkspace::new(dataset.lens(), parallel));
   rayon::iter::repeatn((), num_samples)
     .progress_with(bar)
     .map(|_| {
@@ -73,19 +131,87 @@ pub fn average_distance(
 
       let node = Node { item_type, idx };
 
-      let mut component = node.into();
-      let mut visited = default_visited(dataset);
-      node.set_visited(&mut visited);
-
-      let mut total_dist = 0;
-      let mut count = 0;
-
-      traverse(&mut component, &mut visited, dataset, None, |_, dist| {
-        total_dist += dist;
-        count += 1;
+      let (total_dist, count) = workspaces.with(|workspace| {
+        workspace
+          .traverse(node, dataset, None, |_, _| {})
+          .as_ref()
+          .into_iter()
+          .flat_map(|idxs| idxs.dists())
+          // start node isn't counted
+          .fold((0, 0), |(total, count), &dist| {
+            (total + dist, count + (dist != 0) as usize)
+          })
       });
 
       (node, total_dist as f64 / count as f64)
     })
     .collect()
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    components::{components_labels, ComponentsMethod},
+    dataset,
+    flat_graph::FlatGraph,
+  };
+  use proptest::prelude::*;
+  use std::collections::VecDeque;
+
+  fn brute_force_diameter(component: &Component, dataset: &Dataset) -> usize {
+    let lens = dataset.lens();
+    let mut diameter = 0;
+    for (item_type, idxs) in component.as_ref().iter_with() {
+      for &idx in idxs {
+        let start = Node { item_type, idx }.to_flat(lens);
+        let mut dists = vec![None; dataset.num_nodes()];
+        dists[start] = Some(0);
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(node) = queue.pop_front() {
+          let dist = dists[node].unwrap();
+          diameter = diameter.max(dist);
+          dataset.for_each_neighbor(node, |neighbor| {
+            if dists[neighbor].is_none() {
+              dists[neighbor] = Some(dist + 1);
+              queue.push_back(neighbor);
+            }
+          });
+        }
+      }
+    }
+    diameter
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_diameters(
+        dataset in dataset::small_strategy(),
+        parallel in any::<bool>(),
+      ) {
+        let (components, _) =
+          components_labels(&dataset, ComponentsMethod::Bfs, |_| {});
+        let mut workspace = AnyTraversalWorkspace::new(dataset.lens(), parallel);
+        for component in &components {
+          let exact = exact_diameter(component, &dataset, &mut workspace);
+          prop_assert_eq!(exact, brute_force_diameter(component, &dataset));
+
+          let mut other_workspace =
+            AnyTraversalWorkspace::new(dataset.lens(), !parallel);
+          for (item_type, idxs) in component.as_ref().iter_with() {
+            for &idx in idxs {
+              let start = Node { item_type, idx };
+              let pseudo =
+                pseudo_diameter(start, &dataset, &mut workspace, false);
+              prop_assert!(pseudo <= exact);
+              // ties don't depend on the traversal order
+              prop_assert_eq!(
+                pseudo,
+                pseudo_diameter(start, &dataset, &mut other_workspace, false)
+              );
+            }
+          }
+        }
+      }
+  }
+}
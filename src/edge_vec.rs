@@ -41,6 +41,23 @@ impl<T> EdgeVec<T> {
     (0..self.len()).map(move |i| &self[i])
   }
 
+  /// Keep the values for which `keep` is true (in place).
+  pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+    let mut start = 0;
+    let mut new_len = 0;
+    for end in &mut self.ends {
+      for i in start..*end {
+        if keep(&self.values[i]) {
+          self.values.swap(new_len, i);
+          new_len += 1;
+        }
+      }
+      start = *end;
+      *end = new_len;
+    }
+    self.values.truncate(new_len);
+  }
+
   pub fn reserve(&mut self, additional: usize) {
     self.ends.reserve(additional);
     self.values.reserve(additional);
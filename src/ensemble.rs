@@ -0,0 +1,216 @@
+//! Stats of many sampled null networks summarized as means and percentile
+//! bands, so the real network can be compared against null models with error
+//! bars rather than against a single sample.
+
+use crate::output_data::csv_writer;
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs,
+  path::Path,
+};
+
+/// Percentiles of the reported band (a 95% band).
+pub const BAND: [f64; 2] = [0.025, 0.975];
+
+/// Whatever stats were computed for one network.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkStats {
+  pub scalars: BTreeMap<String, f64>,
+  /// count of each value (e.g. of each degree)
+  pub distributions: BTreeMap<String, BTreeMap<usize, f64>>,
+}
+
+impl NetworkStats {
+  pub fn add_scalar(&mut self, name: &str, value: f64) {
+    self.scalars.insert(name.to_owned(), value);
+  }
+
+  pub fn add_distribution(
+    &mut self,
+    name: &str,
+    values: impl IntoIterator<Item = usize>,
+  ) {
+    let distribution = self.distributions.entry(name.to_owned()).or_default();
+    for value in values {
+      *distribution.entry(value).or_default() += 1.;
+    }
+  }
+}
+
+/// Linearly interpolated percentile `q` (in [0, 1]) of sorted values.
+#[must_use]
+pub fn percentile(sorted: &[f64], q: f64) -> f64 {
+  if sorted.is_empty() {
+    return f64::NAN;
+  }
+  let pos = q * (sorted.len() - 1) as f64;
+  let (low, high) = (pos.floor() as usize, pos.ceil() as usize);
+  sorted[low] + (sorted[high] - sorted[low]) * (pos - low as f64)
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+  pub mean: f64,
+  pub std_dev: f64,
+  pub low: f64,
+  pub median: f64,
+  pub high: f64,
+}
+
+impl Band {
+  #[must_use]
+  pub fn new(mut values: Vec<f64>) -> Self {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let var = if values.len() < 2 {
+      0.
+    } else {
+      values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1.)
+    };
+
+    Self {
+      mean,
+      std_dev: var.sqrt(),
+      low: percentile(&values, BAND[0]),
+      median: percentile(&values, 0.5),
+      high: percentile(&values, BAND[1]),
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct EnsembleScalarCsvEntry {
+  pub stat: String,
+  pub real: Option<f64>,
+  /// samples which have the stat
+  pub num_samples: usize,
+  pub mean: f64,
+  pub std_dev: f64,
+  pub p2_5: f64,
+  pub median: f64,
+  pub p97_5: f64,
+}
+
+#[derive(Serialize)]
+pub struct EnsembleDistributionCsvEntry {
+  pub distribution: String,
+  pub value: usize,
+  pub real_count: Option<f64>,
+  pub mean_count: f64,
+  pub std_dev: f64,
+  pub p2_5: f64,
+  pub median: f64,
+  pub p97_5: f64,
+}
+
+/// Save the bands of every stat over `samples` (with the value for the real
+/// network if given) to null_ensemble_scalars.csv and
+/// null_ensemble_distributions.csv in `output_dir` (created if needed).
+/// Values missing from a sample's distribution count as 0.
+pub fn save_ensemble(
+  output_dir: &Path,
+  samples: &[NetworkStats],
+  real: Option<&NetworkStats>,
+) -> Result<()> {
+  fs::create_dir_all(output_dir)?;
+
+  let names: BTreeSet<_> =
+    samples.iter().flat_map(|s| s.scalars.keys()).collect();
+  let mut writer = csv_writer(&output_dir.join("null_ensemble_scalars.csv"))?;
+  for name in names {
+    let values: Vec<_> = samples
+      .iter()
+      .filter_map(|s| s.scalars.get(name).copied())
+      .collect();
+    let num_samples = values.len();
+    let band = Band::new(values);
+    writer.serialize(EnsembleScalarCsvEntry {
+      stat: name.clone(),
+      real: real.and_then(|r| r.scalars.get(name).copied()),
+      num_samples,
+      mean: band.mean,
+      std_dev: band.std_dev,
+      p2_5: band.low,
+      median: band.median,
+      p97_5: band.high,
+    })?;
+  }
+
+  let names: BTreeSet<_> = samples
+    .iter()
+    .flat_map(|s| s.distributions.keys())
+    .collect();
+  let mut writer =
+    csv_writer(&output_dir.join("null_ensemble_distributions.csv"))?;
+  for name in names {
+    let get = |stats: &NetworkStats| stats.distributions.get(name).cloned();
+    let real = real.map(|r| get(r).unwrap_or_default());
+    let distributions: Vec<_> =
+      samples.iter().map(|s| get(s).unwrap_or_default()).collect();
+    let values: BTreeSet<usize> = distributions
+      .iter()
+      .chain(&real)
+      .flat_map(|d| d.keys().copied())
+      .collect();
+
+    for value in values {
+      let count = |d: &BTreeMap<usize, f64>| *d.get(&value).unwrap_or(&0.);
+      let band = Band::new(distributions.iter().map(count).collect());
+      writer.serialize(EnsembleDistributionCsvEntry {
+        distribution: name.clone(),
+        value,
+        real_count: real.as_ref().map(count),
+        mean_count: band.mean,
+        std_dev: band.std_dev,
+        p2_5: band.low,
+        median: band.median,
+        p97_5: band.high,
+      })?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::output_data::{csv_reader, test_dir};
+
+  #[test]
+  fn bands() {
+    let values = vec![3., 1., 4., 1., 5.];
+    let band = Band::new(values);
+    assert!((band.mean - 2.8).abs() < 1e-12);
+    assert!((band.std_dev - 3.2f64.sqrt()).abs() < 1e-12);
+    assert_eq!(band.median, 3.);
+    // between the two smallest (both 1)
+    assert_eq!(band.low, 1.);
+    assert!((band.high - (4. + 0.9)).abs() < 1e-12);
+
+    assert_eq!(percentile(&[2.], 0.3), 2.);
+    assert_eq!(percentile(&[0., 10.], 0.25), 2.5);
+  }
+
+  #[test]
+  fn save_to_new_dir() -> Result<()> {
+    let samples: Vec<_> = (0..3)
+      .map(|i| {
+        let mut stats = NetworkStats::default();
+        stats.add_scalar("edges", i as f64);
+        stats
+      })
+      .collect();
+    let output_dir = test_dir("ensemble").join("min_contribution_0");
+    save_ensemble(&output_dir, &samples, None)?;
+
+    let path = output_dir.join("null_ensemble_scalars.csv");
+    assert_eq!(csv_reader(&path)?.records().count(), 1);
+    assert!(output_dir.join("null_ensemble_distributions.csv").exists());
+
+    Ok(())
+  }
+}
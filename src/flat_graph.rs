@@ -0,0 +1,61 @@
+use crate::{
+  connection_strength::ConnectionStrength,
+  dataset::{Dataset, Lens},
+  projected_graph::ProjectedGraph,
+  traversal::Node,
+};
+
+/// Undirected graph with nodes numbered `0..num_nodes()`, so algorithms which
+/// don't care about the bipartite structure can use plain vectors indexed by
+/// node.
+///
+/// `Dataset` nodes are numbered with `Node::to_flat` (users then repos).
+pub trait FlatGraph: Sync {
+  fn num_nodes(&self) -> usize;
+
+  fn degree(&self, node: usize) -> usize;
+
+  fn for_each_neighbor(&self, node: usize, f: impl FnMut(usize));
+}
+
+impl FlatGraph for Dataset {
+  fn num_nodes(&self) -> usize {
+    let lens = self.lens();
+    lens.user + lens.repo
+  }
+
+  fn degree(&self, node: usize) -> usize {
+    let Node { item_type, idx } = Node::from_flat(node, self.lens());
+    self.contribution_idxs()[item_type][idx].len()
+  }
+
+  fn for_each_neighbor(&self, node: usize, mut f: impl FnMut(usize)) {
+    let lens = self.lens();
+    let Node { item_type, idx } = Node::from_flat(node, lens);
+    let other_type = item_type.other();
+    for &contrib_idx in &self.contribution_idxs()[item_type][idx] {
+      f(Node {
+        item_type: other_type,
+        idx: self.contributions()[contrib_idx].idx[other_type],
+      }
+      .to_flat(lens))
+    }
+  }
+}
+
+impl<T: ConnectionStrength> FlatGraph for ProjectedGraph<T> {
+  fn num_nodes(&self) -> usize {
+    self.edge_idxs().len()
+  }
+
+  fn degree(&self, node: usize) -> usize {
+    self.edge_idxs()[node].len()
+  }
+
+  fn for_each_neighbor(&self, node: usize, mut f: impl FnMut(usize)) {
+    for &edge_idx in &self.edge_idxs()[node] {
+      let [a, b] = self.edges()[edge_idx].node_idxs;
+      f(if a == node { b } else { a })
+    }
+  }
+}
@@ -1,4 +1,5 @@
 use crate::github_api;
+use serde::{Deserialize, Serialize};
 use std::{iter, ops};
 
 pub trait HasGithubID {
@@ -43,7 +44,7 @@ impl GithubIDWrapper for Repo {
   }
 }
 
-#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum ItemType {
   User,
   Repo,
@@ -57,6 +58,15 @@ impl ItemType {
       Self::Repo => Self::User,
     }
   }
+
+  /// lower case name (for output)
+  #[must_use]
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::User => "user",
+      Self::Repo => "repo",
+    }
+  }
 }
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone, Default, Hash)]
@@ -0,0 +1,279 @@
+//! Limits on the "middle" items (of the other type) a projection goes
+//! through: a repo with tens of thousands of contributors otherwise adds
+//! hundreds of millions of user pairs.
+
+use crate::{
+  dataset::{Dataset, DatasetNameID},
+  output_data::csv_writer,
+  ItemType,
+};
+use anyhow::Result;
+use fnv::FnvHashMap as Map;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, path::Path, str::FromStr};
+
+/// What to do with middle items with more than the maximum degree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HubPolicy {
+  /// Don't project through them at all.
+  Skip,
+  /// Only project through their contributions with the most events.
+  Cap,
+  /// Only project through a uniformly sampled subset of their
+  /// contributions.
+  Subsample,
+}
+
+impl FromStr for HubPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let out = match s {
+      "skip" => Self::Skip,
+      "cap" => Self::Cap,
+      "subsample" => Self::Subsample,
+      _ => return Err(format!("Unrecognized hub policy: {}", s)),
+    };
+
+    Ok(out)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HubOptions {
+  pub policy: HubPolicy,
+  pub max_degree: usize,
+  /// for `HubPolicy::Subsample`
+  pub seed: u64,
+}
+
+/// The contributions kept for each hub among the middle items of a
+/// projection.
+#[derive(Debug, Clone, Default)]
+pub struct HubFilter {
+  /// sorted contribution indexes by middle item index
+  kept: Map<usize, Vec<usize>>,
+}
+
+impl HubFilter {
+  /// Hubs are middle items for projecting `item_type`, so items of the other
+  /// type.
+  #[must_use]
+  pub fn new(
+    item_type: ItemType,
+    options: Option<&HubOptions>,
+    dataset: &Dataset,
+  ) -> Self {
+    let options = if let Some(options) = options {
+      options
+    } else {
+      return Self::default();
+    };
+
+    let kept = dataset.contribution_idxs()[item_type.other()]
+      .iter()
+      .enumerate()
+      .filter(|(_, contrib_idxs)| contrib_idxs.len() > options.max_degree)
+      .map(|(middle_idx, contrib_idxs)| {
+        let mut kept: Vec<usize> = match options.policy {
+          HubPolicy::Skip => Vec::new(),
+          HubPolicy::Cap => {
+            let mut sorted = contrib_idxs.to_vec();
+            sorted.sort_unstable_by_key(|&idx| {
+              (Reverse(dataset.contributions()[idx].num), idx)
+            });
+            sorted.truncate(options.max_degree);
+            sorted
+          }
+          HubPolicy::Subsample => {
+            // seeded per hub so the sample doesn't depend on traversal order
+            let mut rng = StdRng::seed_from_u64(
+              options.seed.wrapping_add(middle_idx as u64),
+            );
+            contrib_idxs
+              .choose_multiple(&mut rng, options.max_degree)
+              .copied()
+              .collect()
+          }
+        };
+        kept.sort_unstable();
+        (middle_idx, kept)
+      })
+      .collect();
+
+    Self { kept }
+  }
+
+  /// Contributions kept for `middle_idx` if it's a hub.
+  #[must_use]
+  pub fn kept(&self, middle_idx: usize) -> Option<&[usize]> {
+    self.kept.get(&middle_idx).map(|kept| &kept[..])
+  }
+
+  #[must_use]
+  pub fn num_hubs(&self) -> usize {
+    self.kept.len()
+  }
+}
+
+#[derive(Serialize)]
+pub struct HubCsvEntry<ID> {
+  pub github_id: ID,
+  pub name: String,
+  pub degree: usize,
+  pub kept_degree: usize,
+  /// projected contribution pairs through the hub which were dropped (this
+  /// is the dropped num common nodes weight)
+  pub dropped_pairs: usize,
+  /// dropped min num events weight
+  pub dropped_min_num_events: usize,
+}
+
+/// Total over pairs of the minimum number of events.
+fn total_pair_min(contrib_idxs: &[usize], dataset: &Dataset) -> usize {
+  let mut nums: Vec<_> = contrib_idxs
+    .iter()
+    .map(|&idx| dataset.contributions()[idx].num)
+    .collect();
+  nums.sort_unstable();
+  let len = nums.len();
+  // the i-th smallest is the minimum of its pairs with every larger one
+  nums
+    .iter()
+    .enumerate()
+    .map(|(i, &num)| num * (len - 1 - i))
+    .sum()
+}
+
+fn num_pairs(len: usize) -> usize {
+  len * len.saturating_sub(1) / 2
+}
+
+/// Save how much projected weight was dropped through each hub (largest
+/// first). Returns the totals over all hubs of dropped and overall pairs.
+pub fn save_hub_report<D: DatasetNameID>(
+  csv_path: &Path,
+  item_type: ItemType,
+  filter: &HubFilter,
+  dataset: &Dataset,
+  dataset_info: &D,
+) -> Result<(usize, usize)> {
+  let middle_type = item_type.other();
+  let mut entries: Vec<_> = filter
+    .kept
+    .iter()
+    .map(|(&middle_idx, kept)| {
+      let contrib_idxs = &dataset.contribution_idxs()[middle_type][middle_idx];
+      HubCsvEntry {
+        github_id: dataset_info.get_id(middle_type, middle_idx),
+        name: dataset_info.get_name(middle_type, middle_idx),
+        degree: contrib_idxs.len(),
+        kept_degree: kept.len(),
+        dropped_pairs: num_pairs(contrib_idxs.len()) - num_pairs(kept.len()),
+        dropped_min_num_events: total_pair_min(contrib_idxs, dataset)
+          - total_pair_min(kept, dataset),
+      }
+    })
+    .collect();
+  entries.sort_unstable_by_key(|e| (Reverse(e.degree), e.kept_degree));
+
+  let total_pairs = dataset.contribution_idxs()[middle_type]
+    .iter()
+    .map(|contrib_idxs| num_pairs(contrib_idxs.len()))
+    .sum();
+  let dropped_pairs = entries.iter().map(|e| e.dropped_pairs).sum();
+
+  let mut writer = csv_writer(csv_path)?;
+  for entry in entries {
+    writer.serialize(entry)?;
+  }
+
+  Ok((dropped_pairs, total_pairs))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::NumCommonNodes,
+    dataset::{self, Lens},
+    output_data::test_dir,
+    projected_graph::{ProjectedGraph, ProjectionOptions},
+  };
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_matches_filtered_dataset(
+        dataset in dataset::small_strategy(),
+        max_degree in 0usize..5,
+        policy in prop_oneof![
+          Just(HubPolicy::Skip),
+          Just(HubPolicy::Cap),
+          Just(HubPolicy::Subsample),
+        ],
+      ) {
+        let options = HubOptions {
+          policy,
+          max_degree,
+          seed: 0,
+        };
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let filter = HubFilter::new(item_type, Some(&options), &dataset);
+          let filtered = Dataset::new(
+            dataset.lens(),
+            dataset
+              .contributions()
+              .iter()
+              .enumerate()
+              .filter(|&(idx, contrib)| {
+                filter
+                  .kept(contrib.idx[item_type.other()])
+                  .is_none_or(|kept| kept.contains(&idx))
+              })
+              .map(|(_, &contrib)| contrib)
+              .collect(),
+          );
+
+          let edges = |dataset: &Dataset, hubs: Option<HubOptions>| {
+            let projected = ProjectedGraph::from_dataset_with_options(
+              item_type,
+              &NumCommonNodes,
+              &Default::default(),
+              &ProjectionOptions {
+                hubs,
+                ..Default::default()
+              },
+              dataset,
+            );
+            let mut edges: Vec<_> = projected
+              .edges()
+              .iter()
+              .map(|e| (e.node_idxs, e.strength))
+              .collect();
+            edges.sort_unstable();
+            edges
+          };
+
+          let actual = edges(&dataset, Some(options));
+          prop_assert_eq!(&actual, &edges(&filtered, None));
+
+          let full = edges(&dataset, None);
+          let (dropped, total) = save_hub_report(
+            &test_dir("hubs").join("hubs.csv"),
+            item_type,
+            &filter,
+            &dataset,
+            &dataset.lens(),
+          )
+          .unwrap();
+          let total_strength = |edges: &[(_, usize)]| -> usize {
+            edges.iter().map(|e| e.1).sum()
+          };
+          prop_assert_eq!(total, total_strength(&full));
+          prop_assert_eq!(dropped, total - total_strength(&actual));
+        }
+      }
+  }
+}
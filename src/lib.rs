@@ -1,3 +1,6 @@
+pub mod backbone;
+pub mod centrality;
+pub mod clustering;
 pub mod component_sizes_csv;
 pub mod components;
 pub mod configuration_model;
@@ -6,17 +9,35 @@ pub mod connection_strength;
 pub mod contribution_dist_csv;
 pub mod csv_items;
 pub mod csv_items_iter;
+pub mod curveball;
 pub mod dataset;
 pub mod degree_dist_csv;
 pub mod distances;
 mod edge_vec;
+pub mod ensemble;
+pub mod flat_graph;
 pub mod github_api;
 mod github_types;
+pub mod hubs;
+pub mod link_analysis;
+pub mod link_prediction;
+pub mod manifest;
+pub mod monte_carlo;
+pub mod null_models;
 pub mod output_data;
+pub mod parallel_traversal;
+pub mod percolation;
+pub mod power_law;
 pub mod progress_bar;
 pub mod projected_graph;
+pub mod recommend;
 pub mod save_subgraph;
+pub mod saved_projection;
+pub mod sharded_projection;
+pub mod similar;
+pub mod stats;
 pub mod traversal;
+pub mod union_find;
 
 pub use edge_vec::EdgeVec;
 pub use github_types::{
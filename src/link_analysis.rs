@@ -0,0 +1,264 @@
+use crate::{
+  dataset::{Dataset, Lens},
+  ItemType, UserRepoPair,
+};
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LinkAnalysisOptions {
+  /// Probability of following an edge rather than teleporting to a uniformly
+  /// random node.
+  pub damping: f64,
+  /// Iteration stops once the L1 change of the scores is below this.
+  pub tolerance: f64,
+  pub max_iterations: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct LinkAnalysisResult {
+  pub scores: UserRepoPair<Vec<f64>>,
+  pub iterations: usize,
+  pub converged: bool,
+}
+
+/// Sum over the contributions of each item of `item_type` of the number of
+/// contributions times the value of the other item.
+pub(crate) fn pull(
+  dataset: &Dataset,
+  item_type: ItemType,
+  from: &[f64],
+) -> Vec<f64> {
+  let other_type = item_type.other();
+  (0..dataset.lens()[item_type])
+    .into_par_iter()
+    .map(|idx| {
+      dataset.contribution_idxs()[item_type][idx]
+        .iter()
+        .map(|&contrib_idx| {
+          let contrib = dataset.contributions()[contrib_idx];
+          contrib.num as f64 * from[contrib.idx[other_type]]
+        })
+        .sum()
+    })
+    .collect()
+}
+
+/// Total number of contributions of every item.
+pub(crate) fn strengths(dataset: &Dataset) -> UserRepoPair<Vec<f64>> {
+  let ones = dataset.lens().map(|l| vec![1.; l]);
+  dataset
+    .lens()
+    .map_with(|_, item_type| pull(dataset, item_type, &ones[item_type.other()]))
+}
+
+pub(crate) fn l1_diff(l: &[f64], r: &[f64]) -> f64 {
+  l.par_iter().zip(r).map(|(l, r)| (l - r).abs()).sum()
+}
+
+/// PageRank on the bipartite graph where each edge is weighted by the number
+/// of contributions. Items without contributions teleport uniformly. Scores
+/// of users and repos together sum to 1.
+#[must_use]
+pub fn pagerank(
+  dataset: &Dataset,
+  options: LinkAnalysisOptions,
+) -> LinkAnalysisResult {
+  let LinkAnalysisOptions {
+    damping,
+    tolerance,
+    max_iterations,
+  } = options;
+
+  let lens = dataset.lens();
+  let num_nodes = (lens.user + lens.repo).max(1) as f64;
+  let strengths = strengths(dataset);
+
+  let mut scores = lens.map(|l| vec![1. / num_nodes; l]);
+  let mut iterations = 0;
+  let mut converged = false;
+
+  while iterations < max_iterations && !converged {
+    iterations += 1;
+
+    let dangling: f64 = scores
+      .as_ref()
+      .iter_with()
+      .map(|(item_type, scores)| {
+        scores
+          .par_iter()
+          .zip(&strengths[item_type])
+          .filter(|(_, &strength)| strength == 0.)
+          .map(|(score, _)| score)
+          .sum::<f64>()
+      })
+      .sum();
+    let base = (1. - damping) / num_nodes + damping * dangling / num_nodes;
+
+    let out = scores.as_ref().map_with(|scores, item_type| {
+      scores
+        .par_iter()
+        .zip(&strengths[item_type])
+        .map(
+          |(score, &strength)| {
+            if strength == 0. {
+              0.
+            } else {
+              score / strength
+            }
+          },
+        )
+        .collect::<Vec<_>>()
+    });
+
+    let new_scores = lens.map_with(|_, item_type| {
+      let mut pulled = pull(dataset, item_type, &out[item_type.other()]);
+      pulled.par_iter_mut().for_each(|v| *v = base + damping * *v);
+      pulled
+    });
+
+    let diff: f64 = new_scores
+      .as_ref()
+      .iter_with()
+      .map(|(item_type, new)| l1_diff(new, &scores[item_type]))
+      .sum();
+    scores = new_scores;
+    converged = diff < tolerance;
+  }
+
+  LinkAnalysisResult {
+    scores,
+    iterations,
+    converged,
+  }
+}
+
+/// Normalizes to sum to 1 and mixes in uniform teleportation.
+fn teleport(mut values: Vec<f64>, damping: f64) -> Vec<f64> {
+  let len = values.len().max(1) as f64;
+  let total: f64 = values.par_iter().sum();
+  values.par_iter_mut().for_each(|v| {
+    let normalized = if total > 0. { *v / total } else { 1. / len };
+    *v = (1. - damping) / len + damping * normalized;
+  });
+  values
+}
+
+/// HITS with users as hubs and repos as authorities, weighted by the number
+/// of contributions. Scores on each side sum to 1. A damping below 1 mixes in
+/// uniform teleportation after every step (randomized HITS), which makes the
+/// result unique on disconnected graphs.
+#[must_use]
+pub fn hits(
+  dataset: &Dataset,
+  options: LinkAnalysisOptions,
+) -> LinkAnalysisResult {
+  let LinkAnalysisOptions {
+    damping,
+    tolerance,
+    max_iterations,
+  } = options;
+
+  let mut scores = dataset.lens().map(|l| vec![1. / l.max(1) as f64; l]);
+  let mut iterations = 0;
+  let mut converged = false;
+
+  while iterations < max_iterations && !converged {
+    iterations += 1;
+
+    let authorities =
+      teleport(pull(dataset, ItemType::Repo, &scores.user), damping);
+    let hubs = teleport(pull(dataset, ItemType::User, &authorities), damping);
+
+    let diff =
+      l1_diff(&hubs, &scores.user) + l1_diff(&authorities, &scores.repo);
+    scores = UserRepoPair {
+      user: hubs,
+      repo: authorities,
+    };
+    converged = diff < tolerance;
+  }
+
+  LinkAnalysisResult {
+    scores,
+    iterations,
+    converged,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dataset;
+  use crate::traversal::test::{
+    contrib, contrib_num, small_disconnected_dataset,
+  };
+  use proptest::prelude::*;
+
+  const OPTIONS: LinkAnalysisOptions = LinkAnalysisOptions {
+    damping: 0.85,
+    tolerance: 1e-12,
+    max_iterations: 1000,
+  };
+
+  #[test]
+  fn symmetric() {
+    let count = 4;
+    let result = pagerank(&small_disconnected_dataset(count), OPTIONS);
+    assert!(result.converged);
+    for scores in result.scores.as_ref() {
+      for score in scores {
+        assert!((score - 1. / (2 * count) as f64).abs() < 1e-9);
+      }
+    }
+  }
+
+  #[test]
+  fn hits_hub() {
+    // user 0 contributes to every repo, the others to one each
+    let dataset = Dataset::new(
+      UserRepoPair { user: 3, repo: 3 },
+      vec![
+        contrib(0, 0),
+        contrib(0, 1),
+        contrib_num(0, 2, 3),
+        contrib(1, 0),
+        contrib(2, 1),
+      ]
+      .into_iter()
+      .collect(),
+    );
+    let result = hits(
+      &dataset,
+      LinkAnalysisOptions {
+        damping: 1.,
+        ..OPTIONS
+      },
+    );
+    assert!(result.converged);
+    let UserRepoPair { user, repo } = &result.scores;
+    assert!(user[0] > user[1] && user[0] > user[2]);
+    assert!(repo[2] > repo[0] && repo[0] > 0.);
+    assert!((user.iter().sum::<f64>() - 1.).abs() < 1e-9);
+    assert!((repo.iter().sum::<f64>() - 1.).abs() < 1e-9);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_pagerank_sums_to_one(
+        dataset in dataset::strategy(
+          1usize..30,
+          1usize..30,
+          1usize..=5,
+          0usize..100,
+        ),
+      ) {
+        let result = pagerank(&dataset, OPTIONS);
+        let total: f64 =
+          result.scores.as_ref().into_iter().flatten().sum();
+        prop_assert!((total - 1.).abs() < 1e-9);
+        prop_assert!(
+          result.scores.as_ref().into_iter().flatten().all(|&s| s > 0.)
+        );
+      }
+  }
+}
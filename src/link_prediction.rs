@@ -0,0 +1,553 @@
+use crate::{
+  connection_strength::{
+    ConnectionStrength, ConnectionStrengthValue, StrengthContext,
+  },
+  dataset::{Contribution, Dataset, Lens},
+  progress_bar::get_bar,
+  projected_graph::two_hop_neighbors,
+  ItemType,
+};
+use fnv::FnvHashSet as Set;
+use indicatif::ParallelProgressIterator;
+use rand::{distributions::Uniform, prelude::*};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{cmp::Ordering, str::FromStr};
+
+/// Which pairs are predicted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PairKind {
+  /// Held out contributions.
+  UserRepo,
+  /// Repos which share a contributor only through held out contributions.
+  RepoRepo,
+}
+
+impl PairKind {
+  #[must_use]
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::UserRepo => "user-repo",
+      Self::RepoRepo => "repo-repo",
+    }
+  }
+}
+
+impl FromStr for PairKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let out = match s {
+      "user-repo" => Self::UserRepo,
+      "repo-repo" => Self::RepoRepo,
+      _ => return Err(format!("Unrecognized pair kind: {}", s)),
+    };
+
+    Ok(out)
+  }
+}
+
+pub struct Holdout {
+  pub train: Dataset,
+  pub test: Vec<Contribution>,
+}
+
+/// Holds out a uniformly random fraction of contributions. Contributions
+/// don't have timestamps, so splitting by time isn't possible.
+#[must_use]
+pub fn random_holdout(
+  dataset: &Dataset,
+  fraction: f64,
+  rng: &mut impl Rng,
+) -> Holdout {
+  let (test, train): (Vec<_>, Vec<_>) = dataset
+    .contributions()
+    .iter()
+    .partition(|_| rng.gen_bool(fraction));
+
+  Holdout {
+    train: Dataset::new(dataset.lens(), train),
+    test,
+  }
+}
+
+fn neighbors(
+  dataset: &Dataset,
+  item_type: ItemType,
+  idx: usize,
+) -> impl Iterator<Item = usize> + '_ {
+  dataset.contribution_idxs()[item_type][idx]
+    .iter()
+    .map(move |&i| dataset.contributions()[i].idx[item_type.other()])
+}
+
+fn degree(dataset: &Dataset, item_type: ItemType, idx: usize) -> usize {
+  dataset.contribution_idxs()[item_type][idx].len()
+}
+
+fn share_neighbor(
+  dataset: &Dataset,
+  item_type: ItemType,
+  pair: [usize; 2],
+) -> bool {
+  let [l, r] = if degree(dataset, item_type, pair[0])
+    <= degree(dataset, item_type, pair[1])
+  {
+    pair
+  } else {
+    [pair[1], pair[0]]
+  };
+  let l_neighbors: Set<_> = neighbors(dataset, item_type, l).collect();
+  neighbors(dataset, item_type, r).any(|idx| l_neighbors.contains(&idx))
+}
+
+fn is_edge(dataset: &Dataset, user: usize, repo: usize) -> bool {
+  if degree(dataset, ItemType::User, user)
+    <= degree(dataset, ItemType::Repo, repo)
+  {
+    neighbors(dataset, ItemType::User, user).any(|idx| idx == repo)
+  } else {
+    neighbors(dataset, ItemType::Repo, repo).any(|idx| idx == user)
+  }
+}
+
+/// Pairs to score and whether each is a held out (positive) pair.
+///
+/// User repo pairs are held out contributions (positive) and random
+/// pairs without a contribution (negative). Repo pairs are pairs which share
+/// a user in the full dataset but not in the training dataset (positive) and
+/// random pairs which don't share a user (negative). Only pairs where both
+/// items are in the training dataset are used.
+#[derive(Clone, Debug, Default)]
+pub struct Candidates {
+  pub pairs: Vec<[usize; 2]>,
+  pub labels: Vec<bool>,
+}
+
+#[must_use]
+pub fn candidates(
+  kind: PairKind,
+  dataset: &Dataset,
+  holdout: &Holdout,
+  max_positives: usize,
+  negatives_per_positive: usize,
+  rng: &mut impl Rng,
+) -> Candidates {
+  let train = &holdout.train;
+  let in_train = |item_type, idx| degree(train, item_type, idx) > 0;
+
+  let mut positives: Vec<[usize; 2]> = match kind {
+    PairKind::UserRepo => holdout
+      .test
+      .iter()
+      .map(|contrib| [contrib.idx.user, contrib.idx.repo])
+      .filter(|&[user, repo]| {
+        in_train(ItemType::User, user) && in_train(ItemType::Repo, repo)
+      })
+      .collect(),
+    PairKind::RepoRepo => {
+      let pairs: Set<[usize; 2]> = holdout
+        .test
+        .iter()
+        .filter(|contrib| in_train(ItemType::Repo, contrib.idx.repo))
+        .flat_map(|contrib| {
+          let repo = contrib.idx.repo;
+          neighbors(dataset, ItemType::User, contrib.idx.user)
+            .filter(move |&other| other != repo)
+            .map(move |other| [repo.min(other), repo.max(other)])
+        })
+        .filter(|&[l, r]| {
+          in_train(ItemType::Repo, l) && in_train(ItemType::Repo, r)
+        })
+        .collect();
+      let mut pairs: Vec<_> = pairs.into_iter().collect();
+      pairs.sort_unstable();
+      pairs
+        .into_iter()
+        .filter(|&pair| !share_neighbor(train, ItemType::Repo, pair))
+        .collect()
+    }
+  };
+  positives.shuffle(rng);
+  positives.truncate(max_positives);
+
+  let nodes = |item_type| -> Vec<usize> {
+    (0..train.lens()[item_type])
+      .filter(|&idx| in_train(item_type, idx))
+      .collect()
+  };
+  let [left_type, right_type] = match kind {
+    PairKind::UserRepo => [ItemType::User, ItemType::Repo],
+    PairKind::RepoRepo => [ItemType::Repo, ItemType::Repo],
+  };
+  let (left_nodes, right_nodes) = (nodes(left_type), nodes(right_type));
+
+  let num_negatives = positives.len() * negatives_per_positive;
+  let mut negatives = Vec::with_capacity(num_negatives);
+  if !left_nodes.is_empty() && !right_nodes.is_empty() {
+    let left_dist = Uniform::from(0..left_nodes.len());
+    let right_dist = Uniform::from(0..right_nodes.len());
+    // give up eventually for (nearly) complete graphs
+    for _ in 0..num_negatives.saturating_mul(100) {
+      if negatives.len() == num_negatives {
+        break;
+      }
+      let pair = [
+        left_nodes[left_dist.sample(rng)],
+        right_nodes[right_dist.sample(rng)],
+      ];
+      let is_positive = match kind {
+        PairKind::UserRepo => is_edge(dataset, pair[0], pair[1]),
+        PairKind::RepoRepo => {
+          pair[0] == pair[1] || share_neighbor(dataset, ItemType::Repo, pair)
+        }
+      };
+      if !is_positive {
+        negatives.push(pair);
+      }
+    }
+  }
+
+  let labels = positives
+    .iter()
+    .map(|_| true)
+    .chain(negatives.iter().map(|_| false))
+    .collect();
+  positives.extend(negatives);
+
+  Candidates {
+    pairs: positives,
+    labels,
+  }
+}
+
+pub const TOPOLOGY_SCORERS: [&str; 4] = [
+  "common_neighbors",
+  "jaccard",
+  "adamic_adar",
+  "resource_allocation",
+];
+
+/// Common neighbors, Jaccard, Adamic Adar and resource allocation from
+/// neighbor sets and the degrees of common neighbors.
+fn set_scores(
+  left: &Set<usize>,
+  right: &Set<usize>,
+  degree: impl Fn(usize) -> usize,
+) -> [f64; 4] {
+  let (smaller, larger) = if left.len() <= right.len() {
+    (left, right)
+  } else {
+    (right, left)
+  };
+  let mut out = [0.; 4];
+  for &common in smaller.iter().filter(|idx| larger.contains(idx)) {
+    let degree = degree(common) as f64;
+    out[0] += 1.;
+    if degree > 1. {
+      out[2] += 1. / degree.ln();
+    }
+    out[3] += 1. / degree;
+  }
+  let union = (left.len() + right.len()) as f64 - out[0];
+  out[1] = if union > 0. { out[0] / union } else { 0. };
+  out
+}
+
+/// Number of items of the same type sharing an item of the other type.
+fn projected_degrees(item_type: ItemType, dataset: &Dataset) -> Vec<usize> {
+  let bar = get_bar(Some(dataset.lens()[item_type] as u64), 10_000);
+  (0..dataset.lens()[item_type])
+    .into_par_iter()
+    .progress_with(bar)
+    .map(|idx| two_hop_neighbors(item_type, idx, dataset).len())
+    .collect()
+}
+
+/// Scores for each of `TOPOLOGY_SCORERS` (in order) computed on `train`.
+///
+/// For a user and a repo the neighbors compared are the repos of the user
+/// and the repos sharing a user with the repo (so common neighbors counts
+/// paths of length 3). For two repos the neighbors are the repos sharing a
+/// user with each, and degrees are taken in the projected graph.
+#[must_use]
+pub fn topology_scores(
+  kind: PairKind,
+  train: &Dataset,
+  candidates: &Candidates,
+) -> Vec<Vec<f64>> {
+  let projected_repo_degrees = match kind {
+    PairKind::UserRepo => Vec::new(),
+    PairKind::RepoRepo => projected_degrees(ItemType::Repo, train),
+  };
+
+  let bar = get_bar(Some(candidates.pairs.len() as u64), 1000);
+  let scores: Vec<[f64; 4]> = candidates
+    .pairs
+    .par_iter()
+    .progress_with(bar)
+    .map(|&[l, r]| {
+      let projected = |idx| -> Set<usize> {
+        two_hop_neighbors(ItemType::Repo, idx, train)
+          .into_keys()
+          .collect()
+      };
+      match kind {
+        PairKind::UserRepo => set_scores(
+          &neighbors(train, ItemType::User, l).collect(),
+          &projected(r),
+          |idx| degree(train, ItemType::Repo, idx),
+        ),
+        PairKind::RepoRepo => set_scores(&projected(l), &projected(r), |idx| {
+          projected_repo_degrees[idx]
+        }),
+      }
+    })
+    .collect();
+
+  (0..TOPOLOGY_SCORERS.len())
+    .map(|i| scores.iter().map(|s| s[i]).collect())
+    .collect()
+}
+
+/// Scores from a connection strength on the repo projection of `train`.
+///
+/// A user and a repo are scored by the total strength between the repo and
+/// the repos of the user. Two repos are scored by the weighted common
+/// neighbors: the total over common projected neighbors of the mean strength
+/// to each repo.
+#[must_use]
+pub fn strength_scores<T: ConnectionStrength>(
+  kind: PairKind,
+  train: &Dataset,
+  candidates: &Candidates,
+  connection_strength: &T,
+) -> Vec<f64> {
+  let strength = |contrib_idxs: &[[usize; 2]]| {
+    connection_strength
+      .strength(&StrengthContext::new(ItemType::Repo, contrib_idxs, train))
+      .to_float()
+  };
+
+  let bar = get_bar(Some(candidates.pairs.len() as u64), 1000);
+  candidates
+    .pairs
+    .par_iter()
+    .progress_with(bar)
+    .map(|&[l, r]| match kind {
+      PairKind::UserRepo => {
+        let projected = two_hop_neighbors(ItemType::Repo, r, train);
+        neighbors(train, ItemType::User, l)
+          .filter_map(|repo| projected.get(&repo))
+          .map(|contrib_idxs| strength(contrib_idxs))
+          .sum()
+      }
+      PairKind::RepoRepo => {
+        let l_projected = two_hop_neighbors(ItemType::Repo, l, train);
+        let r_projected = two_hop_neighbors(ItemType::Repo, r, train);
+        l_projected
+          .iter()
+          .filter_map(|(end, l_contrib_idxs)| {
+            r_projected.get(end).map(|r_contrib_idxs| {
+              (strength(l_contrib_idxs) + strength(r_contrib_idxs)) / 2.
+            })
+          })
+          .sum()
+      }
+    })
+    .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+  /// Probability a random positive is scored above a random negative (ties
+  /// count half).
+  pub auc: f64,
+  /// Fraction of positives in the `k` highest scored candidates.
+  pub precision_at_k: f64,
+  pub average_precision: f64,
+}
+
+/// Ties in the ranking for precision are broken by candidate order.
+#[must_use]
+pub fn metrics(scores: &[f64], labels: &[bool], k: usize) -> Metrics {
+  assert_eq!(scores.len(), labels.len());
+
+  let mut order: Vec<usize> = (0..scores.len()).collect();
+  order.sort_by(|&l, &r| {
+    scores[r]
+      .partial_cmp(&scores[l])
+      .unwrap_or(Ordering::Equal)
+      .then(l.cmp(&r))
+  });
+
+  let num_positive = labels.iter().filter(|&&l| l).count();
+  let num_negative = labels.len() - num_positive;
+
+  // Mann Whitney U with average ranks for ties (ranks are ascending)
+  let mut positive_rank_total = 0.;
+  let mut start = 0;
+  while start < order.len() {
+    let mut end = start + 1;
+    while end < order.len() && scores[order[end]] == scores[order[start]] {
+      end += 1;
+    }
+    let rank = (order.len() - end + 1 + order.len() - start) as f64 / 2.;
+    positive_rank_total +=
+      rank * order[start..end].iter().filter(|&&i| labels[i]).count() as f64;
+    start = end;
+  }
+  let auc = (positive_rank_total
+    - (num_positive * (num_positive + 1)) as f64 / 2.)
+    / (num_positive * num_negative).max(1) as f64;
+
+  let mut hits = 0;
+  let mut precision_total = 0.;
+  let mut precision_at_k = 0.;
+  for (rank, &i) in order.iter().enumerate() {
+    if labels[i] {
+      hits += 1;
+      precision_total += hits as f64 / (rank + 1) as f64;
+    }
+    if rank + 1 == k {
+      precision_at_k = hits as f64 / k as f64;
+    }
+  }
+  if k > order.len() {
+    precision_at_k = hits as f64 / k as f64;
+  }
+
+  Metrics {
+    auc,
+    precision_at_k,
+    average_precision: precision_total / num_positive.max(1) as f64,
+  }
+}
+
+#[derive(Serialize)]
+pub struct LinkPredictionCsvEntry {
+  pub pair_kind: &'static str,
+  pub scorer: String,
+  pub num_positive: usize,
+  pub num_negative: usize,
+  pub auc: f64,
+  pub k: usize,
+  pub precision_at_k: f64,
+  pub average_precision: f64,
+}
+
+impl LinkPredictionCsvEntry {
+  #[must_use]
+  pub fn new(
+    kind: PairKind,
+    scorer: String,
+    candidates: &Candidates,
+    scores: &[f64],
+    k: usize,
+  ) -> Self {
+    let Metrics {
+      auc,
+      precision_at_k,
+      average_precision,
+    } = metrics(scores, &candidates.labels, k);
+    let num_positive = candidates.labels.iter().filter(|&&l| l).count();
+
+    Self {
+      pair_kind: kind.as_str(),
+      scorer,
+      num_positive,
+      num_negative: candidates.labels.len() - num_positive,
+      auc,
+      k,
+      precision_at_k,
+      average_precision,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{connection_strength::NumCommonNodes, dataset};
+  use proptest::prelude::*;
+
+  #[test]
+  fn known_metrics() {
+    let labels = [true, true, false, false];
+
+    let perfect = metrics(&[4., 3., 2., 1.], &labels, 2);
+    assert_eq!(
+      perfect,
+      Metrics {
+        auc: 1.,
+        precision_at_k: 1.,
+        average_precision: 1.,
+      }
+    );
+
+    let reversed = metrics(&[1., 2., 3., 4.], &labels, 2);
+    assert_eq!(reversed.auc, 0.);
+    assert_eq!(reversed.precision_at_k, 0.);
+    assert!(
+      (reversed.average_precision - (1. / 3. + 2. / 4.) / 2.).abs() < 1e-12
+    );
+
+    let ties = metrics(&[1., 1., 1., 1.], &labels, 2);
+    assert_eq!(ties.auc, 0.5);
+
+    let mixed = metrics(&[4., 2., 3., 1.], &labels, 2);
+    assert_eq!(mixed.auc, 0.75);
+    assert_eq!(mixed.precision_at_k, 0.5);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_candidates(
+        dataset in dataset::strategy(
+          1usize..30,
+          1usize..30,
+          1usize..=3,
+          1usize..200,
+        ),
+        seed in 0u64..1000,
+      ) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let holdout = random_holdout(&dataset, 0.2, &mut rng);
+        prop_assert_eq!(
+          holdout.train.contributions().len() + holdout.test.len(),
+          dataset.contributions().len()
+        );
+
+        for &kind in &[PairKind::UserRepo, PairKind::RepoRepo] {
+          let candidates =
+            candidates(kind, &dataset, &holdout, 100, 2, &mut rng);
+          for (&[l, r], &label) in
+            candidates.pairs.iter().zip(&candidates.labels)
+          {
+            match kind {
+              PairKind::UserRepo => {
+                prop_assert!(!is_edge(&holdout.train, l, r));
+                prop_assert_eq!(is_edge(&dataset, l, r), label);
+              }
+              PairKind::RepoRepo => {
+                prop_assert!(!share_neighbor(&holdout.train, ItemType::Repo, [l, r]));
+                prop_assert_eq!(share_neighbor(&dataset, ItemType::Repo, [l, r]), label);
+              }
+            }
+          }
+
+          let topology = topology_scores(kind, &holdout.train, &candidates);
+          let common = strength_scores(
+            kind,
+            &holdout.train,
+            &candidates,
+            &NumCommonNodes,
+          );
+          for scores in topology.iter().chain(Some(&common)) {
+            prop_assert_eq!(scores.len(), candidates.pairs.len());
+            prop_assert!(scores.iter().all(|s| s.is_finite() && *s >= 0.));
+          }
+        }
+      }
+  }
+}
@@ -0,0 +1,186 @@
+//! Machine readable record of a run: the options, the dataset, seeds, sizes,
+//! timings and every scalar result. Entries are keyed by the output directory
+//! of the analysis they belong to (relative to the root output directory,
+//! so they include parameters like min_contribution_0) and then by name.
+
+use crate::dataset::{Dataset, Lens};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+  collections::BTreeMap,
+  fs::{self, File},
+  io::BufWriter,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+type Section = BTreeMap<String, Map<String, Value>>;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+  #[serde(skip)]
+  root: PathBuf,
+  pub started_unix_secs: u64,
+  pub options: Value,
+  /// `Dataset::fingerprint` in hex
+  pub dataset_fingerprint: Option<String>,
+  pub seeds: Section,
+  pub sizes: Section,
+  /// seconds
+  pub timings: Section,
+  pub results: Section,
+}
+
+impl Manifest {
+  /// Start a manifest for a run writing its output to `root`.
+  pub fn new(root: &Path, options: &impl Serialize) -> Result<Self> {
+    Ok(Self {
+      root: root.to_owned(),
+      started_unix_secs: SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs(),
+      options: serde_json::to_value(options)?,
+      dataset_fingerprint: None,
+      seeds: Default::default(),
+      sizes: Default::default(),
+      timings: Default::default(),
+      results: Default::default(),
+    })
+  }
+
+  fn key(&self, dir: &Path) -> String {
+    let relative = dir.strip_prefix(&self.root).unwrap_or(dir);
+    if relative.as_os_str().is_empty() {
+      ".".to_owned()
+    } else {
+      relative.to_string_lossy().into_owned()
+    }
+  }
+
+  fn section(
+    &mut self,
+    section: fn(&mut Self) -> &mut Section,
+    dir: &Path,
+  ) -> &mut Map<String, Value> {
+    let key = self.key(dir);
+    section(self).entry(key).or_default()
+  }
+
+  /// Record the fingerprint of the loaded dataset and its size (under ".").
+  pub fn set_dataset(&mut self, dataset: &Dataset) {
+    self.dataset_fingerprint = Some(format!("{:016x}", dataset.fingerprint()));
+    let root = self.root.clone();
+    self.add_dataset_sizes(&root, dataset);
+  }
+
+  /// Record the numbers of users, repos and contributions.
+  pub fn add_dataset_sizes(&mut self, dir: &Path, dataset: &Dataset) {
+    let lens = dataset.lens();
+    self.add_size(dir, "users", lens.user);
+    self.add_size(dir, "repos", lens.repo);
+    self.add_size(dir, "contributions", dataset.contributions().len());
+  }
+
+  pub fn add_seed(&mut self, dir: &Path, name: &str, seed: u64) {
+    self
+      .section(|m| &mut m.seeds, dir)
+      .insert(name.to_owned(), seed.into());
+  }
+
+  pub fn add_size(&mut self, dir: &Path, name: &str, size: usize) {
+    self
+      .section(|m| &mut m.sizes, dir)
+      .insert(name.to_owned(), size.into());
+  }
+
+  pub fn add_timing(&mut self, dir: &Path, name: &str, elapsed: Duration) {
+    self
+      .section(|m| &mut m.timings, dir)
+      .insert(name.to_owned(), elapsed.as_secs_f64().into());
+  }
+
+  pub fn add_result(
+    &mut self,
+    dir: &Path,
+    name: &str,
+    value: impl Serialize,
+  ) -> Result<()> {
+    let value = serde_json::to_value(value)?;
+    self
+      .section(|m| &mut m.results, dir)
+      .insert(name.to_owned(), value);
+
+    Ok(())
+  }
+
+  /// Record every field of `results` (which must serialize to a map).
+  pub fn add_results(
+    &mut self,
+    dir: &Path,
+    results: &impl Serialize,
+  ) -> Result<()> {
+    let fields = match serde_json::to_value(results)? {
+      Value::Object(fields) => fields,
+      other => return Err(anyhow!("results aren't a map: {}", other)),
+    };
+    self.section(|m| &mut m.results, dir).extend(fields);
+
+    Ok(())
+  }
+
+  /// Write manifest.json to the root output directory (replacing any earlier
+  /// save).
+  pub fn save(&self) -> Result<()> {
+    fs::create_dir_all(&self.root)?;
+    let writer = BufWriter::new(File::create(self.root.join("manifest.json"))?);
+    serde_json::to_writer_pretty(writer, self)?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[derive(Serialize)]
+  struct Stats {
+    mean: f64,
+    count: usize,
+  }
+
+  #[test]
+  fn keys_and_results() -> Result<()> {
+    let root = Path::new("output_data");
+    let mut manifest = Manifest::new(root, &vec!["--degrees"])?;
+    let dir = root.join("actual_graph").join("min_contribution_0");
+
+    manifest.add_seed(&dir, "betweenness", 7);
+    manifest.add_result(&dir, "pseudo_diameter", 12)?;
+    manifest.add_results(
+      &dir,
+      &Stats {
+        mean: 0.5,
+        count: 3,
+      },
+    )?;
+    assert!(manifest.add_results(&dir, &1.).is_err());
+
+    let key = Path::new("actual_graph")
+      .join("min_contribution_0")
+      .to_string_lossy()
+      .into_owned();
+    let results = &manifest.results[&key];
+    assert_eq!(results["pseudo_diameter"], 12);
+    assert_eq!(results["mean"], 0.5);
+    assert_eq!(results["count"], 3);
+    assert_eq!(manifest.seeds[&key]["betweenness"], 7);
+    assert_eq!(manifest.options, serde_json::json!(["--degrees"]));
+
+    let json = serde_json::to_value(&manifest)?;
+    assert!(json.get("root").is_none());
+
+    Ok(())
+  }
+}
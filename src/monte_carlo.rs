@@ -0,0 +1,467 @@
+//! Empirical null for connection strengths: the strength of every projected
+//! pair is measured on many sampled networks with the same degrees to get its
+//! null mean and variance.
+
+use crate::{
+  connection_strength::{
+    ConnectionStrength, ConnectionStrengthValue, ExpectationAccelerator,
+    StrengthContext,
+  },
+  curveball::{self, CurveballOptions},
+  dataset::{Dataset, Lens},
+  hubs::HubFilter,
+  output_data::csv_writer,
+  progress_bar::get_bar,
+  projected_graph::{later_two_hop_neighbors, ProjectionOptions},
+  ItemType,
+};
+use anyhow::Result;
+use indicatif::{ParallelProgressIterator, ProgressIterator};
+use ordered_float::NotNan;
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, path::Path};
+
+/// Running mean and variance (Welford's algorithm).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Moments {
+  pub count: usize,
+  pub mean: f64,
+  m2: f64,
+}
+
+impl Moments {
+  pub fn add(&mut self, value: f64) {
+    self.count += 1;
+    let delta = value - self.mean;
+    self.mean += delta / self.count as f64;
+    self.m2 += delta * (value - self.mean);
+  }
+
+  /// Sample variance.
+  #[must_use]
+  pub fn variance(&self) -> f64 {
+    if self.count < 2 {
+      0.
+    } else {
+      self.m2 / (self.count - 1) as f64
+    }
+  }
+
+  #[must_use]
+  pub fn std_dev(&self) -> f64 {
+    self.variance().sqrt()
+  }
+}
+
+/// Null strength of one pair.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PairMoments {
+  /// Over all samples (0 when the pair isn't connected).
+  pub all: Moments,
+  /// Over the samples where the pair is connected.
+  pub connected: Moments,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MonteCarloOptions {
+  pub num_samples: usize,
+  pub curveball: CurveballOptions,
+  pub seed: u64,
+}
+
+/// Null strength moments for every pair a projection of one item type
+/// evaluates in the dataset the null was built from.
+pub struct MonteCarloNull<T: ConnectionStrength> {
+  inner: T,
+  item_type: ItemType,
+  options: MonteCarloOptions,
+  /// for each item, the larger items it's connected to (sorted)
+  pairs: Vec<Vec<(usize, PairMoments)>>,
+}
+
+fn connected_pairs(
+  item_type: ItemType,
+  dataset: &Dataset,
+  hubs: &HubFilter,
+) -> Vec<Vec<(usize, PairMoments)>> {
+  let num_items = dataset.lens()[item_type];
+  let bar = get_bar(Some(num_items as u64), 10_000);
+
+  (0..num_items)
+    .into_par_iter()
+    .progress_with(bar)
+    .map(|start_idx| {
+      let mut ends: Vec<_> =
+        later_two_hop_neighbors(item_type, start_idx, dataset, hubs)
+          .into_keys()
+          .map(|end_idx| (end_idx, PairMoments::default()))
+          .collect();
+      ends.sort_unstable_by_key(|&(end_idx, _)| end_idx);
+      ends
+    })
+    .collect()
+}
+
+impl<T: ConnectionStrength> MonteCarloNull<T> {
+  /// Null for projecting `item_type` with `projection`: only the pairs the
+  /// projection evaluates are kept (a top k projection still evaluates every
+  /// pair). Null networks are sampled from one curveball chain and the hub
+  /// policy is applied to each of them.
+  #[must_use]
+  pub fn new(
+    inner: T,
+    item_type: ItemType,
+    dataset: &Dataset,
+    projection: &ProjectionOptions,
+    options: &MonteCarloOptions,
+  ) -> Self {
+    let hubs = projection.hubs.as_ref();
+    let mut pairs = connected_pairs(
+      item_type,
+      dataset,
+      &HubFilter::new(item_type, hubs, dataset),
+    );
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut chain = curveball::Chain::new(dataset, &options.curveball);
+    let bar = get_bar(Some(options.num_samples as u64), 1);
+    for _ in (0..options.num_samples).progress_with(bar) {
+      let sample = chain.sample(&mut rng);
+      let sample_hubs = HubFilter::new(item_type, hubs, &sample);
+      pairs
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(start_idx, ends)| {
+          if ends.is_empty() {
+            return;
+          }
+          let edge_map = later_two_hop_neighbors(
+            item_type,
+            start_idx,
+            &sample,
+            &sample_hubs,
+          );
+          for (end_idx, moments) in ends {
+            if let Some(contrib_idxs) = edge_map.get(end_idx) {
+              let strength = inner
+                .strength(&StrengthContext::new(
+                  item_type,
+                  contrib_idxs,
+                  &sample,
+                ))
+                .to_float();
+              moments.all.add(strength);
+              moments.connected.add(strength);
+            } else {
+              moments.all.add(0.);
+            }
+          }
+        })
+    }
+
+    Self {
+      inner,
+      item_type,
+      options: *options,
+      pairs,
+    }
+  }
+
+  #[must_use]
+  pub fn inner(&self) -> T {
+    self.inner
+  }
+
+  #[must_use]
+  pub fn item_type(&self) -> ItemType {
+    self.item_type
+  }
+
+  #[must_use]
+  pub fn num_samples(&self) -> usize {
+    self.options.num_samples
+  }
+
+  /// Panics if the pair isn't evaluated by the projection the null was
+  /// built for.
+  #[must_use]
+  pub fn moments(&self, idxs: [usize; 2]) -> PairMoments {
+    let [start_idx, end_idx] = [idxs[0].min(idxs[1]), idxs[0].max(idxs[1])];
+    let ends = &self.pairs[start_idx];
+    let pos = ends
+      .binary_search_by_key(&end_idx, |&(end_idx, _)| end_idx)
+      .expect("pair isn't projected in the dataset the null is for");
+    ends[pos].1
+  }
+
+  /// Every pair the projection evaluates with its moments.
+  pub fn iter_pairs(
+    &self,
+  ) -> impl Iterator<Item = ([usize; 2], PairMoments)> + '_ {
+    self.pairs.iter().enumerate().flat_map(|(start_idx, ends)| {
+      ends
+        .iter()
+        .map(move |&(end_idx, moments)| ([start_idx, end_idx], moments))
+    })
+  }
+}
+
+/// z-score of the inner strength against a `MonteCarloNull`. The observed
+/// strength is counted as one extra null sample so pairs which are never
+/// connected in the null get a finite score (at most about the square root
+/// of the number of samples).
+#[derive(Clone, Copy)]
+pub struct MonteCarloZ<'a, T: ConnectionStrength> {
+  pub null: &'a MonteCarloNull<T>,
+}
+
+impl<'a, T: ConnectionStrength> fmt::Debug for MonteCarloZ<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "monte_carlo_z_{:?}", &self.null.inner)
+  }
+}
+
+impl<'a, T: ConnectionStrength> ConnectionStrength for MonteCarloZ<'a, T> {
+  type Value = NotNan<f64>;
+
+  fn describe(&self) -> String {
+    let options = &self.null.options;
+    format!(
+      "{:?} with {} samples (seed {}, {:?})",
+      self, options.num_samples, options.seed, options.curveball
+    )
+  }
+
+  fn strength(&self, context: &StrengthContext<'_>) -> Self::Value {
+    let strength = self.null.inner.strength(context).to_float();
+
+    assert_eq!(context.item_type, self.null.item_type);
+    let mut moments = self.null.moments(context.item_idxs()).all;
+    moments.add(strength);
+
+    let std_dev = moments.std_dev();
+    let z = if std_dev > 0. {
+      (strength - moments.mean) / std_dev
+    } else {
+      0.
+    };
+
+    NotNan::new(z).unwrap()
+  }
+
+  fn operation(_nums: [usize; 2]) -> Self::Value {
+    unreachable!();
+  }
+}
+
+#[derive(Serialize)]
+pub struct CalibrationCsvEntry {
+  /// smaller degree of the pair is in [2^bin, 2^(bin + 1))
+  pub low_degree_bin: u32,
+  /// larger degree of the pair is in [2^bin, 2^(bin + 1))
+  pub high_degree_bin: u32,
+  pub count: usize,
+  pub mean_expectation: f64,
+  pub mean_null_mean: f64,
+  pub mean_null_std_dev: f64,
+  /// mean null strength over the samples where the pair is connected
+  pub mean_null_connected_mean: f64,
+  pub mean_null_connected_fraction: f64,
+  /// mean of |expectation - null connected mean| / null connected mean
+  pub mean_relative_error: f64,
+}
+
+#[derive(Default)]
+struct CalibrationBin {
+  count: usize,
+  expectation: f64,
+  null_mean: f64,
+  null_std_dev: f64,
+  null_connected_mean: f64,
+  null_connected_fraction: f64,
+  relative_error: f64,
+  relative_error_count: usize,
+}
+
+/// Compare the analytic expectation against the null for the pairs of `null`
+/// binned by the log2 degrees of the pair. The analytic
+/// expectation is conditional on the pair being connected, so it's compared
+/// against the null mean over the samples where the pair is connected.
+pub fn save_calibration<T: ConnectionStrength>(
+  csv_path: &Path,
+  null: &MonteCarloNull<T>,
+  accelerator: &ExpectationAccelerator<T>,
+  dataset: &Dataset,
+) -> Result<()> {
+  let item_type = null.item_type;
+  let mut bins: BTreeMap<(u32, u32), CalibrationBin> = BTreeMap::new();
+  let degree = |idx: usize| dataset.contribution_idxs()[item_type][idx].len();
+
+  for (idxs, moments) in null.iter_pairs() {
+    let [l, r] = idxs.map(|idx| degree(idx).max(1).ilog2());
+    let bin = bins.entry((l.min(r), l.max(r))).or_default();
+
+    let expectation = accelerator.expectation(idxs);
+    bin.count += 1;
+    bin.expectation += expectation;
+    bin.null_mean += moments.all.mean;
+    bin.null_std_dev += moments.all.std_dev();
+    bin.null_connected_mean += moments.connected.mean;
+    bin.null_connected_fraction +=
+      moments.connected.count as f64 / moments.all.count.max(1) as f64;
+    if moments.connected.count > 0 && moments.connected.mean > 0. {
+      bin.relative_error +=
+        (expectation - moments.connected.mean).abs() / moments.connected.mean;
+      bin.relative_error_count += 1;
+    }
+  }
+
+  let mut writer = csv_writer(csv_path)?;
+
+  for ((low_degree_bin, high_degree_bin), bin) in bins {
+    let count = bin.count as f64;
+    writer.serialize(CalibrationCsvEntry {
+      low_degree_bin,
+      high_degree_bin,
+      count: bin.count,
+      mean_expectation: bin.expectation / count,
+      mean_null_mean: bin.null_mean / count,
+      mean_null_std_dev: bin.null_std_dev / count,
+      mean_null_connected_mean: bin.null_connected_mean / count,
+      mean_null_connected_fraction: bin.null_connected_fraction / count,
+      mean_relative_error: bin.relative_error
+        / bin.relative_error_count.max(1) as f64,
+    })?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::NumCommonNodes,
+    dataset,
+    hubs::{HubOptions, HubPolicy},
+    projected_graph::{two_hop_neighbors, ProjectedGraph},
+    saved_projection::ProjectedGraphHeader,
+  };
+  use proptest::prelude::*;
+
+  #[test]
+  fn welford() {
+    let values = [1., 4., 2., 8., 5.];
+    let mut moments = Moments::default();
+    for &v in &values {
+      moments.add(v);
+    }
+    let mean = values.iter().sum::<f64>() / 5.;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 4.;
+    assert_eq!(moments.count, 5);
+    assert!((moments.mean - mean).abs() < 1e-12);
+    assert!((moments.variance() - var).abs() < 1e-12);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_null_moments(
+        dataset in dataset::small_strategy(),
+        max_degree in 1usize..5,
+      ) {
+        let options = MonteCarloOptions {
+          num_samples: 4,
+          curveball: Default::default(),
+          seed: 0,
+        };
+        let hubs = ProjectionOptions {
+          hubs: Some(HubOptions {
+            policy: HubPolicy::Skip,
+            max_degree,
+            seed: 0,
+          }),
+          ..Default::default()
+        };
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let null = MonteCarloNull::new(
+            NumCommonNodes,
+            item_type,
+            &dataset,
+            &Default::default(),
+            &options,
+          );
+          let z = MonteCarloZ { null: &null };
+          for start_idx in 0..dataset.lens()[item_type] {
+            for (end_idx, contrib_idxs) in
+              two_hop_neighbors(item_type, start_idx, &dataset)
+            {
+              let moments = null.moments([start_idx, end_idx]);
+              prop_assert_eq!(moments.all.count, 4);
+              prop_assert!(moments.connected.count <= 4);
+              if moments.connected.count > 0 {
+                prop_assert!(moments.connected.mean >= 1.);
+              }
+
+              let context =
+                StrengthContext::new(item_type, &contrib_idxs, &dataset);
+              let observed = contrib_idxs.len() as f64;
+              let value = z.strength(&context).into_inner();
+              prop_assert!(value.is_finite());
+              prop_assert_eq!(value > 0., observed > moments.all.mean);
+            }
+          }
+
+          // only the pairs projected through the kept hubs are sampled
+          let hub_null =
+            MonteCarloNull::new(NumCommonNodes, item_type, &dataset, &hubs, &options);
+          let projected = ProjectedGraph::from_dataset_with_options(
+            item_type,
+            &MonteCarloZ { null: &hub_null },
+            &NotNan::new(f64::MIN).unwrap(),
+            &hubs,
+            &dataset,
+          );
+          let mut pairs: Vec<_> =
+            projected.edges().iter().map(|e| e.node_idxs).collect();
+          pairs.sort_unstable();
+          let null_pairs: Vec<_> =
+            hub_null.iter_pairs().map(|(idxs, _)| idxs).collect();
+          prop_assert_eq!(pairs, null_pairs);
+        }
+
+        // a projection saved with other null options can't be reused
+        let null = MonteCarloNull::new(
+          NumCommonNodes,
+          ItemType::User,
+          &dataset,
+          &Default::default(),
+          &options,
+        );
+        let other_null = MonteCarloNull::new(
+          NumCommonNodes,
+          ItemType::User,
+          &dataset,
+          &Default::default(),
+          &MonteCarloOptions { seed: 1, ..options },
+        );
+        let header = ProjectedGraphHeader::new(
+          ItemType::User,
+          &MonteCarloZ { null: &null },
+          &Default::default(),
+          &Default::default(),
+          &dataset,
+        );
+        let other_header = ProjectedGraphHeader::new(
+          ItemType::User,
+          &MonteCarloZ { null: &other_null },
+          &Default::default(),
+          &Default::default(),
+          &dataset,
+        );
+        prop_assert!(header.check_reusable(&header).is_ok());
+        prop_assert!(header.check_reusable(&other_header).is_err());
+      }
+  }
+}
@@ -0,0 +1,435 @@
+//! Random bipartite networks with the same users and repos as a dataset
+//! which keep some of its structure, for comparing the dataset against.
+
+use crate::{
+  configuration_model,
+  curveball::{self, CurveballOptions},
+  dataset::{Contribution, Dataset, DatasetNameID, Lens},
+  output_data::csv_reader,
+  ItemType, UserRepoPair,
+};
+use anyhow::{anyhow, Result};
+use fnv::{FnvHashMap as Map, FnvHashSet as Set};
+use rand::{distributions::WeightedIndex, prelude::*};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
+
+pub trait NullModel: Send + Sync {
+  /// Sample a network with the same numbers of users and repos.
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset;
+}
+
+/// Numbers of contributions for models which don't keep them are drawn
+/// from all the numbers of contributions in the dataset.
+fn sample_num(dataset: &Dataset, rng: &mut (impl Rng + ?Sized)) -> usize {
+  dataset
+    .contributions()
+    .choose(rng)
+    .map_or(1, |contrib| contrib.num)
+}
+
+fn degrees(item_type: ItemType, dataset: &Dataset) -> Vec<usize> {
+  dataset.contribution_idxs()[item_type]
+    .iter()
+    .map(|idxs| idxs.len())
+    .collect()
+}
+
+fn strengths(item_type: ItemType, dataset: &Dataset) -> Vec<usize> {
+  dataset.contribution_idxs()[item_type]
+    .iter()
+    .map(|idxs| idxs.iter().map(|&i| dataset.contributions()[i].num).sum())
+    .collect()
+}
+
+/// Aggregate (user, repo) pairs into contributions with sampled numbers.
+fn from_pairs(
+  dataset: &Dataset,
+  pairs: impl IntoIterator<Item = (usize, usize)>,
+  rng: &mut (impl Rng + ?Sized),
+) -> Dataset {
+  let contributions = pairs
+    .into_iter()
+    .map(|(user, repo)| Contribution {
+      idx: UserRepoPair { user, repo },
+      num: sample_num(dataset, rng),
+    })
+    .collect();
+
+  Dataset::new(dataset.lens(), contributions)
+}
+
+/// The binned configuration model (`configuration_model::gen_graph`).
+pub struct ConfigurationModel;
+
+impl NullModel for ConfigurationModel {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    configuration_model::gen_graph(dataset, rng)
+  }
+}
+
+/// The curveball chain (`curveball::gen_graph`): each network is burnt in
+/// from the dataset by a new chain.
+pub struct Curveball(pub CurveballOptions);
+
+impl NullModel for Curveball {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    curveball::gen_graph(dataset, &self.0, rng)
+  }
+}
+
+/// Uniformly random edges, exactly as many as the dataset has.
+pub struct ErdosRenyi;
+
+impl NullModel for ErdosRenyi {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    let lens = dataset.lens();
+    let num_edges = dataset.contributions().len();
+    assert!(num_edges <= lens.user * lens.repo);
+
+    // rejection is fine since real networks are sparse
+    let mut edges = Set::default();
+    let mut pairs = Vec::with_capacity(num_edges);
+    while pairs.len() < num_edges {
+      let pair = (rng.gen_range(0..lens.user), rng.gen_range(0..lens.repo));
+      if edges.insert(pair) {
+        pairs.push(pair);
+      }
+    }
+
+    from_pairs(dataset, pairs, rng)
+  }
+}
+
+/// Each (user, repo) edge is independent with probability proportional to
+/// the product of their degrees (capped at 1), so degrees match in
+/// expectation.
+pub struct ChungLu;
+
+impl NullModel for ChungLu {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    let user_degrees = degrees(ItemType::User, dataset);
+    let mut repos: Vec<(usize, usize)> = degrees(ItemType::Repo, dataset)
+      .into_iter()
+      .enumerate()
+      .map(|(repo, degree)| (degree, repo))
+      .collect();
+    repos.sort_unstable_by(|l, r| r.cmp(l));
+    let total = dataset.contributions().len() as f64;
+
+    let seed: u64 = rng.gen();
+    // skipping over repos which are unlikely to be picked (Miller and
+    // Hagberg), so this is linear in the number of edges
+    let pairs: Vec<_> = user_degrees
+      .par_iter()
+      .enumerate()
+      .flat_map_iter(|(user, &degree)| {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(user as u64));
+        let prob = |j: usize| -> f64 {
+          (degree as f64 * repos[j].0 as f64 / total).min(1.)
+        };
+        let mut out = Vec::new();
+        let mut j = 0;
+        let mut p = if repos.is_empty() { 0. } else { prob(0) };
+        while j < repos.len() && p > 0. {
+          if p < 1. {
+            let r: f64 = rng.gen();
+            j += ((1. - r).ln() / (1. - p).ln()).floor() as usize;
+          }
+          if j < repos.len() {
+            let q = prob(j);
+            if rng.gen::<f64>() < q / p {
+              out.push((user, repos[j].1));
+            }
+            p = q;
+            j += 1;
+          }
+        }
+        out
+      })
+      .collect();
+
+    from_pairs(dataset, pairs, rng)
+  }
+}
+
+/// Weighted configuration model: every contribution event of a user is
+/// matched with a uniformly random event of a repo, so the total number of
+/// contributions of each user and repo is kept exactly (degrees aren't).
+/// Memory is linear in the total number of events.
+pub struct StrengthPreserving;
+
+impl NullModel for StrengthPreserving {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    let mut repo_events: Vec<usize> = strengths(ItemType::Repo, dataset)
+      .into_iter()
+      .enumerate()
+      .flat_map(|(repo, strength)| std::iter::repeat_n(repo, strength))
+      .collect();
+    repo_events.shuffle(rng);
+
+    let mut contributions = Vec::new();
+    let mut start = 0;
+    for (user, strength) in
+      strengths(ItemType::User, dataset).into_iter().enumerate()
+    {
+      let mut counts: Map<usize, usize> = Map::default();
+      for &repo in &repo_events[start..start + strength] {
+        *counts.entry(repo).or_default() += 1;
+      }
+      start += strength;
+
+      let mut counts: Vec<_> = counts.into_iter().collect();
+      counts.sort_unstable();
+      contributions.extend(counts.into_iter().map(|(repo, num)| {
+        Contribution {
+          idx: UserRepoPair { user, repo },
+          num,
+        }
+      }));
+    }
+
+    Dataset::new(dataset.lens(), contributions)
+  }
+}
+
+/// Degree corrected bipartite stochastic block model: the number of edges
+/// between each user community and repo community is kept and the ends of
+/// each edge are picked proportionally to degree within the communities.
+/// Repeated edges are merged, so a few edges are lost.
+pub struct DcSbm {
+  /// community of each user and repo (user and repo communities are
+  /// separate)
+  pub communities: UserRepoPair<Vec<usize>>,
+}
+
+#[derive(Deserialize)]
+pub struct CommunityCsvEntry {
+  /// "user" or "repo"
+  pub item_type: String,
+  pub name: String,
+  pub community: usize,
+}
+
+impl DcSbm {
+  /// Items missing from the csv are put together in one extra community
+  /// (per type).
+  pub fn load_communities<D: DatasetNameID>(
+    csv_path: &Path,
+    dataset_info: &D,
+  ) -> Result<UserRepoPair<Vec<usize>>> {
+    let names = UserRepoPair::<()>::default().map_with(|_, item_type| {
+      (0..dataset_info.lens()[item_type])
+        .map(|idx| (dataset_info.get_name(item_type, idx), idx))
+        .collect::<Map<_, _>>()
+    });
+    let mut communities = dataset_info.lens().map(|len| vec![None; len]);
+
+    for entry in csv_reader(csv_path)?.deserialize() {
+      let entry: CommunityCsvEntry = entry?;
+      let item_type = match entry.item_type.as_str() {
+        "user" => ItemType::User,
+        "repo" => ItemType::Repo,
+        other => return Err(anyhow!("unexpected item type {}", other)),
+      };
+      let idx = names[item_type].get(&entry.name).ok_or_else(|| {
+        anyhow!("{} {} not found", item_type.as_str(), entry.name)
+      })?;
+      communities[item_type][*idx] = Some(entry.community);
+    }
+
+    Ok(communities.map(|communities| {
+      let missing = communities.iter().flatten().max().map_or(0, |&c| c + 1);
+      communities
+        .into_iter()
+        .map(|c| c.unwrap_or(missing))
+        .collect()
+    }))
+  }
+}
+
+impl NullModel for DcSbm {
+  fn gen_graph(&self, dataset: &Dataset, rng: &mut dyn RngCore) -> Dataset {
+    let community_of =
+      |item_type: ItemType, idx: usize| self.communities[item_type][idx];
+
+    let mut block_edges: Map<(usize, usize), usize> = Map::default();
+    for contrib in dataset.contributions() {
+      *block_edges
+        .entry((
+          community_of(ItemType::User, contrib.idx.user),
+          community_of(ItemType::Repo, contrib.idx.repo),
+        ))
+        .or_default() += 1;
+    }
+    let mut block_edges: Vec<_> = block_edges.into_iter().collect();
+    block_edges.sort_unstable();
+
+    // members of each community with their degrees
+    let members = UserRepoPair::<()>::default().map_with(|_, item_type| {
+      let mut members: Map<usize, (Vec<usize>, Vec<usize>)> = Map::default();
+      for (idx, degree) in degrees(item_type, dataset).into_iter().enumerate() {
+        let (idxs, degrees) =
+          members.entry(community_of(item_type, idx)).or_default();
+        idxs.push(idx);
+        degrees.push(degree);
+      }
+      members
+    });
+
+    let mut edges = Set::default();
+    let mut pairs = Vec::new();
+    for ((user_community, repo_community), count) in block_edges {
+      let pick = |item_type: ItemType, community| {
+        let (idxs, degrees) = &members[item_type][&community];
+        // every community with an edge has an item with positive degree
+        (idxs, WeightedIndex::new(degrees).unwrap())
+      };
+      let (users, user_dist) = pick(ItemType::User, user_community);
+      let (repos, repo_dist) = pick(ItemType::Repo, repo_community);
+      for _ in 0..count {
+        let pair = (users[user_dist.sample(rng)], repos[repo_dist.sample(rng)]);
+        if edges.insert(pair) {
+          pairs.push(pair);
+        }
+      }
+    }
+
+    from_pairs(dataset, pairs, rng)
+  }
+}
+
+/// Which null model to use (for command line arguments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum NullModelKind {
+  ConfigurationModel,
+  Curveball,
+  ErdosRenyi,
+  ChungLu,
+  StrengthPreserving,
+  DcSbm,
+}
+
+impl NullModelKind {
+  #[must_use]
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::ConfigurationModel => "configuration_model",
+      Self::Curveball => "curveball",
+      Self::ErdosRenyi => "erdos_renyi",
+      Self::ChungLu => "chung_lu",
+      Self::StrengthPreserving => "strength_preserving",
+      Self::DcSbm => "dc_sbm",
+    }
+  }
+}
+
+impl FromStr for NullModelKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let out = match s {
+      "configuration-model" => Self::ConfigurationModel,
+      "curveball" => Self::Curveball,
+      "erdos-renyi" => Self::ErdosRenyi,
+      "chung-lu" => Self::ChungLu,
+      "strength-preserving" => Self::StrengthPreserving,
+      "dc-sbm" => Self::DcSbm,
+      _ => return Err(format!("Unrecognized null model: {}", s)),
+    };
+
+    Ok(out)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dataset;
+  use proptest::prelude::*;
+
+  fn check_simple(sample: &Dataset, dataset: &Dataset) {
+    assert_eq!(sample.lens(), dataset.lens());
+    let mut pairs: Vec<_> = sample
+      .contributions()
+      .iter()
+      .map(|c| (c.idx.user, c.idx.repo))
+      .collect();
+    let len = pairs.len();
+    pairs.sort_unstable();
+    pairs.dedup();
+    assert_eq!(pairs.len(), len);
+    assert!(sample.contributions().iter().all(|c| c.num > 0));
+  }
+
+  #[test]
+  fn chung_lu_complete() {
+    let mut contributions = Vec::new();
+    for user in 0..3 {
+      for repo in 0..3 {
+        contributions.push(Contribution {
+          idx: UserRepoPair { user, repo },
+          num: 1,
+        });
+      }
+    }
+    let dataset =
+      Dataset::new(UserRepoPair { user: 3, repo: 3 }, contributions);
+    // every edge has probability 1
+    let sample = ChungLu.gen_graph(&dataset, &mut StdRng::seed_from_u64(0));
+    assert_eq!(sample.contributions().len(), 9);
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_null_models(
+        dataset in dataset::small_strategy(),
+        num_communities in 1usize..4,
+        seed in 0u64..1000,
+      ) {
+        let rng = &mut StdRng::seed_from_u64(seed);
+
+        let sample = ErdosRenyi.gen_graph(&dataset, rng);
+        check_simple(&sample, &dataset);
+        prop_assert_eq!(
+          sample.contributions().len(),
+          dataset.contributions().len()
+        );
+
+        check_simple(&ChungLu.gen_graph(&dataset, rng), &dataset);
+
+        let sample = StrengthPreserving.gen_graph(&dataset, rng);
+        check_simple(&sample, &dataset);
+        for item_type in [ItemType::User, ItemType::Repo] {
+          prop_assert_eq!(
+            strengths(item_type, &sample),
+            strengths(item_type, &dataset)
+          );
+        }
+
+        let communities = dataset
+          .lens()
+          .map(|len| (0..len).map(|i| i % num_communities).collect());
+        let model = DcSbm { communities };
+        let sample = model.gen_graph(&dataset, rng);
+        check_simple(&sample, &dataset);
+        let block_counts = |dataset: &Dataset| {
+          let mut counts: Map<(usize, usize), usize> = Map::default();
+          for c in dataset.contributions() {
+            *counts
+              .entry((
+                c.idx.user % num_communities,
+                c.idx.repo % num_communities,
+              ))
+              .or_default() += 1;
+          }
+          counts
+        };
+        let expected = block_counts(&dataset);
+        for (block, count) in block_counts(&sample) {
+          prop_assert!(count <= expected[&block]);
+        }
+      }
+  }
+}
@@ -1,6 +1,11 @@
 //! TODO: consider removing this module
 use anyhow::Result;
 use std::{fs::File, path::Path};
+#[cfg(test)]
+use std::{
+  path::PathBuf,
+  sync::atomic::{AtomicUsize, Ordering},
+};
 
 pub fn csv_writer(path: &Path) -> Result<csv::Writer<File>> {
   let file = File::create(path)?;
@@ -13,3 +18,20 @@ pub fn csv_reader(path: &Path) -> Result<csv::Reader<File>> {
   let out = csv::Reader::from_reader(file);
   Ok(out)
 }
+
+/// An empty directory for one test case, unique to this process and call so
+/// tests running in parallel don't share files.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+  static NEXT_CASE: AtomicUsize = AtomicUsize::new(0);
+  let case = NEXT_CASE.fetch_add(1, Ordering::Relaxed);
+  let dir = std::env::temp_dir().join(format!(
+    "github_net_test_{}_{}_{}",
+    name,
+    std::process::id(),
+    case
+  ));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  dir
+}
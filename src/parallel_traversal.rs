@@ -0,0 +1,360 @@
+use crate::{
+  dataset::{Dataset, Lens},
+  traversal::{ComponentAccess, ComponentDists, Node, TraversalWorkspace},
+  ItemType, UserRepoPair,
+};
+use rayon::prelude::*;
+use std::{
+  iter,
+  sync::atomic::{AtomicU32, Ordering},
+};
+
+const UNVISITED: u32 = u32::MAX;
+
+/// Reusable state for level synchronous, direction optimizing (top-down /
+/// bottom-up switching) breadth first search.
+///
+/// Each level is expanded in parallel. Top-down steps claim unvisited
+/// neighbors of the frontier, bottom-up steps have every unvisited node on the
+/// other side look for a parent in the frontier. Bottom-up is used when the
+/// frontier has more edges than `1 / alpha` of the unexplored edges and
+/// top-down is used again once the frontier has fewer than `1 / beta` of the
+/// nodes on its side (Beamer et al. 2012).
+pub struct ParallelTraversalWorkspace {
+  dists: UserRepoPair<Vec<AtomicU32>>,
+  component: ComponentDists,
+  alpha: usize,
+  beta: usize,
+}
+
+impl ParallelTraversalWorkspace {
+  #[must_use]
+  pub fn new(lens: UserRepoPair<usize>) -> Self {
+    Self {
+      dists: lens.map(|l| (0..l).map(|_| AtomicU32::new(UNVISITED)).collect()),
+      component: Default::default(),
+      alpha: 15,
+      beta: 18,
+    }
+  }
+
+  /// Override the direction switching heuristic (defaults are 15 and 18).
+  #[must_use]
+  pub fn with_heuristic(mut self, alpha: usize, beta: usize) -> Self {
+    self.alpha = alpha;
+    self.beta = beta;
+    self
+  }
+
+  /// Same semantics as `traverse_dist` starting from a single node: the
+  /// callback is called once for every node reached other than the start
+  /// with its distance. Levels are visited in order, but the calls within a
+  /// level are made in parallel. The returned component is only valid until
+  /// the next traversal.
+  pub fn traverse(
+    &mut self,
+    start: Node,
+    dataset: &Dataset,
+    limit: Option<usize>,
+    callback: impl Fn(Node, usize) + Sync,
+  ) -> &ComponentDists {
+    debug_assert_eq!(self.dists.as_ref().map(Vec::len), dataset.lens());
+
+    self.reset();
+
+    let dists = &self.dists;
+    let component = &mut self.component;
+    let limit = limit.unwrap_or(usize::MAX);
+    let degree =
+      |node: Node| dataset.contribution_idxs()[node.item_type][node.idx].len();
+
+    dists[start.item_type][start.idx].store(0, Ordering::Relaxed);
+    component[start.item_type].add_items(0, iter::once(start.idx));
+
+    // edges (counted from each side) which haven't been reached yet
+    let total_edges = dataset.contributions().len();
+    let mut unexplored_edges = UserRepoPair::same(total_edges);
+    unexplored_edges[start.item_type] -= degree(start);
+
+    let mut frontier = vec![start.idx];
+    let mut item_type = start.item_type;
+    let mut dist = 0;
+    let mut bottom_up = false;
+
+    while !frontier.is_empty() && dist < limit {
+      let other_type = item_type.other();
+      let next_dist = dist + 1;
+      let frontier_edges: usize = frontier
+        .par_iter()
+        .map(|&idx| degree(Node { item_type, idx }))
+        .sum();
+
+      bottom_up = if bottom_up {
+        frontier.len().saturating_mul(self.beta) >= dists[item_type].len()
+      } else {
+        frontier_edges.saturating_mul(self.alpha) > unexplored_edges[other_type]
+      };
+
+      let next_frontier: Vec<usize> = if bottom_up {
+        bottom_up_step(item_type, dist, dists, dataset)
+      } else {
+        top_down_step(item_type, next_dist, &frontier, dists, dataset)
+      };
+
+      unexplored_edges[other_type] -= next_frontier
+        .par_iter()
+        .map(|&idx| {
+          degree(Node {
+            item_type: other_type,
+            idx,
+          })
+        })
+        .sum::<usize>();
+
+      next_frontier.par_iter().for_each(|&idx| {
+        callback(
+          Node {
+            item_type: other_type,
+            idx,
+          },
+          next_dist,
+        )
+      });
+
+      component[other_type].add_items(next_dist, next_frontier.iter().cloned());
+
+      frontier = next_frontier;
+      item_type = other_type;
+      dist = next_dist;
+    }
+
+    &self.component
+  }
+
+  /// Only touches the nodes from the last traversal.
+  fn reset(&mut self) {
+    let dists = &self.dists;
+    for (item_type, idxs) in self.component.as_ref().iter_with() {
+      idxs.idxs().par_iter().for_each(|&idx| {
+        dists[item_type][idx].store(UNVISITED, Ordering::Relaxed)
+      });
+    }
+    for idxs in self.component.as_mut() {
+      idxs.clear();
+    }
+  }
+}
+
+fn to_dist(dist: usize) -> u32 {
+  assert!(dist < UNVISITED as usize);
+  dist as u32
+}
+
+fn top_down_step(
+  item_type: ItemType,
+  next_dist: usize,
+  frontier: &[usize],
+  dists: &UserRepoPair<Vec<AtomicU32>>,
+  dataset: &Dataset,
+) -> Vec<usize> {
+  let other_type = item_type.other();
+  let next_dist = to_dist(next_dist);
+  frontier
+    .par_iter()
+    .flat_map_iter(|&idx| {
+      dataset.contribution_idxs()[item_type][idx]
+        .iter()
+        .map(move |&i| dataset.contributions()[i].idx[other_type])
+        .filter(move |&other_idx| {
+          dists[other_type][other_idx]
+            .compare_exchange(
+              UNVISITED,
+              next_dist,
+              Ordering::Relaxed,
+              Ordering::Relaxed,
+            )
+            .is_ok()
+        })
+    })
+    .collect()
+}
+
+fn bottom_up_step(
+  item_type: ItemType,
+  dist: usize,
+  dists: &UserRepoPair<Vec<AtomicU32>>,
+  dataset: &Dataset,
+) -> Vec<usize> {
+  let other_type = item_type.other();
+  let frontier_dist = to_dist(dist);
+  // each node is only written by the thread checking it, so no compare
+  // exchange is needed
+  (0..dataset.lens()[other_type])
+    .into_par_iter()
+    .filter(|&other_idx| {
+      let other_dist = &dists[other_type][other_idx];
+      if other_dist.load(Ordering::Relaxed) != UNVISITED {
+        return false;
+      }
+      let found = dataset.contribution_idxs()[other_type][other_idx]
+        .iter()
+        .map(|&i| dataset.contributions()[i].idx[item_type])
+        .any(|idx| {
+          dists[item_type][idx].load(Ordering::Relaxed) == frontier_dist
+        });
+      if found {
+        other_dist.store(frontier_dist + 1, Ordering::Relaxed);
+      }
+      found
+    })
+    .collect()
+}
+
+/// Either a sequential or a parallel traversal workspace.
+pub enum AnyTraversalWorkspace {
+  Sequential(TraversalWorkspace),
+  Parallel(ParallelTraversalWorkspace),
+}
+
+impl AnyTraversalWorkspace {
+  #[must_use]
+  pub fn new(lens: UserRepoPair<usize>, parallel: bool) -> Self {
+    if parallel {
+      Self::Parallel(ParallelTraversalWorkspace::new(lens))
+    } else {
+      Self::Sequential(TraversalWorkspace::new(lens))
+    }
+  }
+
+  pub fn traverse(
+    &mut self,
+    start: Node,
+    dataset: &Dataset,
+    limit: Option<usize>,
+    callback: impl Fn(Node, usize) + Sync,
+  ) -> &ComponentDists {
+    match self {
+      Self::Sequential(workspace) => {
+        workspace.traverse(start, dataset, limit, callback)
+      }
+      Self::Parallel(workspace) => {
+        workspace.traverse(start, dataset, limit, callback)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dataset;
+  use crate::traversal::test::{
+    fully_connected_dataset, single_repo_dataset, single_user_dataset,
+    small_disconnected_dataset, two_dense_components_dataset,
+    two_dense_components_several_disconnected_dataset,
+  };
+  use proptest::prelude::*;
+  use std::sync::Mutex;
+
+  fn sorted_pairs(
+    component: &ComponentDists,
+  ) -> UserRepoPair<Vec<(usize, usize)>> {
+    component.as_ref().map(|idxs| {
+      let mut pairs: Vec<_> = idxs
+        .idxs()
+        .iter()
+        .cloned()
+        .zip(idxs.dists().iter().cloned())
+        .collect();
+      pairs.sort_unstable();
+      pairs
+    })
+  }
+
+  fn gen_test(dataset: &Dataset) -> Result<(), TestCaseError> {
+    let heuristics =
+      [(15, 18), (0, 0), (usize::MAX, usize::MAX), (usize::MAX, 0)];
+    let mut sequential = TraversalWorkspace::new(dataset.lens());
+    let mut parallel: Vec<_> = heuristics
+      .iter()
+      .map(|&(alpha, beta)| {
+        ParallelTraversalWorkspace::new(dataset.lens())
+          .with_heuristic(alpha, beta)
+      })
+      .collect();
+
+    for (item_type, len) in dataset.lens().iter_with() {
+      for idx in 0..len {
+        let start = Node { item_type, idx };
+        for &limit in &[None, Some(1), Some(2)] {
+          let mut expected_calls = Vec::new();
+          let expected = sorted_pairs(sequential.traverse(
+            start,
+            dataset,
+            limit,
+            |node, dist| {
+              expected_calls.push((
+                node.item_type == ItemType::User,
+                node.idx,
+                dist,
+              ))
+            },
+          ));
+          expected_calls.sort_unstable();
+
+          for workspace in &mut parallel {
+            let calls = Mutex::new(Vec::new());
+            let actual = sorted_pairs(workspace.traverse(
+              start,
+              dataset,
+              limit,
+              |node, dist| {
+                calls.lock().unwrap().push((
+                  node.item_type == ItemType::User,
+                  node.idx,
+                  dist,
+                ))
+              },
+            ));
+            let mut calls = calls.into_inner().unwrap();
+            calls.sort_unstable();
+
+            prop_assert_eq!(&actual, &expected);
+            prop_assert_eq!(&calls, &expected_calls);
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn matches_sequential() {
+    for dataset in &[
+      single_user_dataset(),
+      single_repo_dataset(),
+      small_disconnected_dataset(3),
+      fully_connected_dataset(1),
+      fully_connected_dataset(8),
+      two_dense_components_dataset(),
+      two_dense_components_several_disconnected_dataset(),
+    ] {
+      gen_test(dataset).unwrap();
+    }
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_matches_sequential(
+        dataset in dataset::strategy(
+          1usize..30,
+          1usize..30,
+          1usize..=2,
+          1usize..100,
+        ),
+      ) {
+        gen_test(&dataset)?;
+      }
+  }
+}
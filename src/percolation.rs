@@ -0,0 +1,170 @@
+//! How the components of a projected graph change as the minimum connection
+//! strength is lowered (a percolation curve), for choosing a threshold.
+
+use crate::{
+  connection_strength::{ConnectionStrength, ConnectionStrengthValue},
+  flat_graph::FlatGraph,
+  output_data::csv_writer,
+  progress_bar::get_bar,
+  projected_graph::ProjectedGraph,
+  union_find::UnionFind,
+};
+use anyhow::Result;
+use indicatif::ProgressIterator;
+use serde::Serialize;
+use std::path::Path;
+
+/// The projected graph with only the edges of at least `min_strength`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct PercolationCsvEntry {
+  pub min_strength: f64,
+  pub num_edges: usize,
+  /// items with at least one edge
+  pub num_connected_items: usize,
+  /// components with at least one edge
+  pub num_components: usize,
+  pub giant_component_size: usize,
+  /// over all items (including ones with no edges)
+  pub mean_degree: f64,
+}
+
+/// Percolation curve at (up to) `num_points` thresholds chosen so the number
+/// of edges grows in roughly even steps. Thresholds are strengths of edges,
+/// so the last point has every edge. Edges are added strongest first to a
+/// union find, so this is about as fast as one pass over the edges.
+#[must_use]
+pub fn percolation_sweep<T: ConnectionStrength>(
+  projected_graph: &ProjectedGraph<T>,
+  num_points: usize,
+) -> Vec<PercolationCsvEntry> {
+  let edges = projected_graph.edges();
+  let num_items = projected_graph.num_nodes();
+
+  let mut union_find = UnionFind::new(num_items);
+  let mut degrees = vec![0; num_items];
+  let mut num_connected_items = 0;
+  let mut num_unions = 0;
+  let mut giant_component_size = 0;
+  let mut num_added = 0;
+
+  let mut out: Vec<PercolationCsvEntry> = Vec::with_capacity(num_points);
+
+  let bar = get_bar(Some(num_points as u64), 1);
+  for point in (1..=num_points).progress_with(bar) {
+    let rank = (point * edges.len()).div_ceil(num_points);
+    if rank == 0 {
+      continue;
+    }
+    let min_strength = &edges[rank - 1].strength;
+    let num_edges = projected_graph.num_edges_at_least(min_strength);
+    if num_edges == num_added {
+      // ties with the last point
+      continue;
+    }
+
+    for edge in &edges[num_added..num_edges] {
+      for &idx in &edge.node_idxs {
+        if degrees[idx] == 0 {
+          num_connected_items += 1;
+        }
+        degrees[idx] += 1;
+      }
+      let [a, b] = edge.node_idxs;
+      if let Some(root) = union_find.union(a, b) {
+        num_unions += 1;
+        giant_component_size = giant_component_size.max(union_find.size(root));
+      }
+    }
+    num_added = num_edges;
+
+    out.push(PercolationCsvEntry {
+      min_strength: min_strength.clone().to_float(),
+      num_edges,
+      num_connected_items,
+      num_components: num_connected_items - num_unions,
+      giant_component_size,
+      mean_degree: 2. * num_edges as f64 / num_items.max(1) as f64,
+    });
+  }
+
+  out
+}
+
+pub fn save_percolation(
+  csv_path: &Path,
+  points: &[PercolationCsvEntry],
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+  for point in points {
+    writer.serialize(point)?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::TotalNumEvents, dataset, dataset::Lens,
+    traversal::ProjectedTraversalWorkspace, ItemType,
+  };
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_matches_filtered(
+        dataset in dataset::small_strategy(),
+        num_points in 1usize..10,
+      ) {
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let projected = ProjectedGraph::from_dataset(
+            item_type,
+            &TotalNumEvents,
+            &Default::default(),
+            &dataset,
+          );
+          let points = percolation_sweep(&projected, num_points);
+          prop_assert!(points.len() <= num_points);
+          if let Some(last) = points.last() {
+            prop_assert_eq!(last.num_edges, projected.edges().len());
+          } else {
+            prop_assert!(projected.edges().is_empty());
+          }
+
+          let num_items = dataset.lens()[item_type];
+          let mut workspace = ProjectedTraversalWorkspace::new(num_items);
+          for point in &points {
+            let min_strength =
+              ConnectionStrengthValue::from_float(point.min_strength).unwrap();
+            let filtered = projected.filter_edges(&min_strength);
+            prop_assert_eq!(filtered.edges().len(), point.num_edges);
+            prop_assert!(filtered
+              .edges()
+              .iter()
+              .all(|e| e.strength >= min_strength));
+
+            let mut sizes = Vec::new();
+            let mut seen = vec![false; num_items];
+            for start in 0..num_items {
+              if seen[start] || filtered.degree(start) == 0 {
+                continue;
+              }
+              let component =
+                workspace.traverse(start, &filtered, None, |_, _| ());
+              for &idx in component.idxs() {
+                seen[idx] = true;
+              }
+              sizes.push(component.idxs().len());
+            }
+            prop_assert_eq!(sizes.len(), point.num_components);
+            prop_assert_eq!(sizes.iter().sum::<usize>(), point.num_connected_items);
+            prop_assert_eq!(
+              sizes.iter().copied().max().unwrap_or(0),
+              point.giant_component_size
+            );
+          }
+        }
+      }
+  }
+}
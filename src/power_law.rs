@@ -0,0 +1,714 @@
+//! Fitting heavy tailed distributions to discrete data following Clauset,
+//! Shalizi and Newman ("Power-law distributions in empirical data"): the
+//! power law exponent is the maximum likelihood estimate, `xmin` is chosen to
+//! minimize the Kolmogorov-Smirnov distance, the goodness of fit comes from a
+//! semi-parametric bootstrap and alternatives are compared with (Vuong)
+//! likelihood ratio tests on the same tail.
+//!
+//! Works on any positive integer values: degrees, numbers of contributions,
+//! integer strengths, component sizes, ... Values of 0 are left out (a power
+//! law starts at 1).
+
+use crate::{
+  output_data::csv_writer,
+  stats::{erfc, hurwitz_zeta, ln_normal_sf},
+};
+use anyhow::Result;
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::path::Path;
+
+const ALPHA_RANGE: (f64, f64) = (1.000_1, 20.);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FitOptions {
+  /// Don't try an `xmin` which leaves fewer values in the tail.
+  pub min_tail: usize,
+  /// Synthetic data sets for the goodness of fit p-value (0 to skip it).
+  pub num_bootstrap: usize,
+  /// p-values of likelihood ratios below this favor one of the
+  /// distributions.
+  pub significance: f64,
+}
+
+impl Default for FitOptions {
+  fn default() -> Self {
+    Self {
+      min_tail: 10,
+      num_bootstrap: 100,
+      significance: 0.1,
+    }
+  }
+}
+
+/// A distribution over the integers from some `xmin` up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TailDistribution {
+  PowerLaw { alpha: f64 },
+  LogNormal { mu: f64, sigma: f64 },
+  Exponential { lambda: f64 },
+  TruncatedPowerLaw { alpha: f64, lambda: f64 },
+}
+
+impl TailDistribution {
+  #[must_use]
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::PowerLaw { .. } => "power_law",
+      Self::LogNormal { .. } => "log_normal",
+      Self::Exponential { .. } => "exponential",
+      Self::TruncatedPowerLaw { .. } => "truncated_power_law",
+    }
+  }
+
+  /// The parameters as "name=value" pairs.
+  #[must_use]
+  pub fn params(&self) -> String {
+    match *self {
+      Self::PowerLaw { alpha } => format!("alpha={}", alpha),
+      Self::LogNormal { mu, sigma } => format!("mu={} sigma={}", mu, sigma),
+      Self::Exponential { lambda } => format!("lambda={}", lambda),
+      Self::TruncatedPowerLaw { alpha, lambda } => {
+        format!("alpha={} lambda={}", alpha, lambda)
+      }
+    }
+  }
+
+  /// Log probability of each of `values` (all at least `xmin`).
+  #[must_use]
+  pub fn ln_pmfs(&self, values: &[usize], xmin: usize) -> Vec<f64> {
+    let xmin_f = xmin as f64;
+    match *self {
+      Self::PowerLaw { alpha } => {
+        let ln_norm = hurwitz_zeta(alpha, xmin_f).ln();
+        values
+          .iter()
+          .map(|&x| -alpha * (x as f64).ln() - ln_norm)
+          .collect()
+      }
+      Self::LogNormal { mu, sigma } => {
+        // mass of [x - 1/2, x + 1/2) of the continuous log normal
+        let ln_sf = |x: f64| ln_normal_sf((x.ln() - mu) / sigma);
+        let ln_norm = ln_sf(xmin_f - 0.5);
+        values
+          .iter()
+          .map(|&x| {
+            let (low, high) = (ln_sf(x as f64 - 0.5), ln_sf(x as f64 + 0.5));
+            let ln_mass = low + (-(high - low).exp()).ln_1p();
+            ln_mass.max(f64::MIN) - ln_norm
+          })
+          .collect()
+      }
+      Self::Exponential { lambda } => {
+        let ln_norm = -(-(-lambda).exp()).ln_1p();
+        values
+          .iter()
+          .map(|&x| -lambda * (x - xmin) as f64 - ln_norm)
+          .collect()
+      }
+      Self::TruncatedPowerLaw { alpha, lambda } => {
+        let ln_norm = truncated_power_law_norm(alpha, lambda, xmin).ln();
+        values
+          .iter()
+          .map(|&x| {
+            let x = x as f64;
+            -alpha * x.ln() - lambda * (x - xmin_f) - ln_norm
+          })
+          .collect()
+      }
+    }
+  }
+
+  #[must_use]
+  pub fn ln_likelihood(&self, values: &[usize], xmin: usize) -> f64 {
+    self.ln_pmfs(values, xmin).iter().sum()
+  }
+}
+
+/// Sum of x^-alpha e^(-lambda (x - xmin)) over x >= xmin for lambda > 0.
+fn truncated_power_law_norm(alpha: f64, lambda: f64, xmin: usize) -> f64 {
+  const DIRECT_TERMS: usize = 1000;
+  const INTERVALS: usize = 2000;
+
+  let xmin_f = xmin as f64;
+  let term = |x: f64| (-alpha * x.ln() - lambda * (x - xmin_f)).exp();
+  let direct: f64 = (xmin..xmin + DIRECT_TERMS).map(|x| term(x as f64)).sum();
+
+  // the rest by the midpoint rule: the integral from a = end - 1/2 up,
+  // which with x = a e^u is a^(1 - alpha) e^(-lambda (a - xmin)) times the
+  // integral of g below
+  let a = (xmin + DIRECT_TERMS) as f64 - 0.5;
+  let ln_g = |u: f64| (1. - alpha) * u - lambda * a * u.exp_m1();
+  let mut end = 1.;
+  while !(ln_g(end) < -50. && (1. - alpha) - lambda * a * end.exp() < 0.)
+    && end < 1e4
+  {
+    end *= 2.;
+  }
+  let step = end / INTERVALS as f64;
+  let simpson: f64 = (0..=INTERVALS)
+    .map(|i| {
+      let weight = if i == 0 || i == INTERVALS {
+        1.
+      } else if i % 2 == 1 {
+        4.
+      } else {
+        2.
+      };
+      weight * ln_g(i as f64 * step).exp()
+    })
+    .sum::<f64>()
+    * step
+    / 3.;
+
+  direct + a.powf(1. - alpha) * (-lambda * (a - xmin_f)).exp() * simpson
+}
+
+/// Maximize a unimodal function on [low, high] (golden section search).
+fn golden_section_max(
+  f: impl Fn(f64) -> f64,
+  mut low: f64,
+  mut high: f64,
+) -> f64 {
+  let ratio = (5f64.sqrt() - 1.) / 2.;
+  let mut left = high - ratio * (high - low);
+  let mut right = low + ratio * (high - low);
+  let (mut f_left, mut f_right) = (f(left), f(right));
+  for _ in 0..100 {
+    if f_left < f_right {
+      low = left;
+      left = right;
+      f_left = f_right;
+      right = low + ratio * (high - low);
+      f_right = f(right);
+    } else {
+      high = right;
+      right = left;
+      f_right = f_left;
+      left = high - ratio * (high - low);
+      f_left = f(left);
+    }
+  }
+  (low + high) / 2.
+}
+
+/// Maximize `f` of two unconstrained parameters from `start` (Nelder-Mead).
+fn nelder_mead_max(f: impl Fn([f64; 2]) -> f64, start: [f64; 2]) -> [f64; 2] {
+  let value = |p: [f64; 2]| {
+    let v = f(p);
+    if v.is_nan() {
+      f64::NEG_INFINITY
+    } else {
+      v
+    }
+  };
+  let mut simplex: Vec<_> = [
+    start,
+    [start[0] + 0.5, start[1]],
+    [start[0], start[1] + 0.5],
+  ]
+  .iter()
+  .map(|&p| (p, value(p)))
+  .collect();
+  let lerp = |a: [f64; 2], b: [f64; 2], t: f64| {
+    [a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])]
+  };
+
+  for _ in 0..500 {
+    simplex.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    if (simplex[0].1 - simplex[2].1).abs() < 1e-10 {
+      break;
+    }
+    let centroid = lerp(simplex[0].0, simplex[1].0, 0.5);
+    let worst = simplex[2];
+    let reflected = lerp(worst.0, centroid, 2.);
+    let f_reflected = value(reflected);
+    if f_reflected > simplex[0].1 {
+      let expanded = lerp(worst.0, centroid, 3.);
+      let f_expanded = value(expanded);
+      simplex[2] = if f_expanded > f_reflected {
+        (expanded, f_expanded)
+      } else {
+        (reflected, f_reflected)
+      };
+    } else if f_reflected > simplex[1].1 {
+      simplex[2] = (reflected, f_reflected);
+    } else {
+      let contracted = lerp(worst.0, centroid, 0.5);
+      let f_contracted = value(contracted);
+      if f_contracted > worst.1 {
+        simplex[2] = (contracted, f_contracted);
+      } else {
+        let best = simplex[0].0;
+        for vertex in &mut simplex[1..] {
+          let shrunk = lerp(best, vertex.0, 0.5);
+          *vertex = (shrunk, value(shrunk));
+        }
+      }
+    }
+  }
+
+  simplex
+    .into_iter()
+    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+    .unwrap()
+    .0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerLawFit {
+  pub alpha: f64,
+  pub xmin: usize,
+  /// Kolmogorov-Smirnov distance between the tail and the fit
+  pub ks: f64,
+  pub num_tail: usize,
+  /// number of positive values
+  pub num_total: usize,
+}
+
+/// Power law exponent and KS distance for the tail `sorted[start..]`.
+/// `sum_ln` is the sum of the logs of the tail values.
+fn fit_tail(sorted: &[usize], start: usize, sum_ln: f64) -> (f64, f64) {
+  let tail = &sorted[start..];
+  let xmin = tail[0] as f64;
+  let n = tail.len() as f64;
+  let alpha = golden_section_max(
+    |alpha| -n * hurwitz_zeta(alpha, xmin).ln() - alpha * sum_ln,
+    ALPHA_RANGE.0,
+    ALPHA_RANGE.1,
+  );
+
+  let norm = hurwitz_zeta(alpha, xmin);
+  let mut ks: f64 = 0.;
+  let mut idx = 0;
+  while idx < tail.len() {
+    let x = tail[idx];
+    let end = idx + tail[idx..].partition_point(|&v| v == x);
+    // the model cdf jumps at x, so compare on both sides of it
+    let fit_below = 1. - hurwitz_zeta(alpha, x as f64) / norm;
+    let fit_at = 1. - hurwitz_zeta(alpha, x as f64 + 1.) / norm;
+    ks = ks
+      .max((idx as f64 / n - fit_below).abs())
+      .max((end as f64 / n - fit_at).abs());
+    idx = end;
+  }
+
+  (alpha, ks)
+}
+
+/// Fit a discrete power law with every possible `xmin` (leaving at least
+/// `min_tail` values) and keep the one with the smallest KS distance. None
+/// if there aren't enough positive values.
+#[must_use]
+pub fn fit_power_law(
+  values: &[usize],
+  options: &FitOptions,
+) -> Option<PowerLawFit> {
+  let mut sorted: Vec<_> = values.iter().copied().filter(|&x| x > 0).collect();
+  sorted.sort_unstable();
+  fit_power_law_sorted(&sorted, options)
+}
+
+fn fit_power_law_sorted(
+  sorted: &[usize],
+  options: &FitOptions,
+) -> Option<PowerLawFit> {
+  let min_tail = options.min_tail.max(2);
+  if sorted.len() < min_tail {
+    return None;
+  }
+
+  // sum of logs of sorted[i..]
+  let mut suffix_ln = vec![0.; sorted.len() + 1];
+  for i in (0..sorted.len()).rev() {
+    suffix_ln[i] = suffix_ln[i + 1] + (sorted[i] as f64).ln();
+  }
+  let starts: Vec<_> = (0..=sorted.len() - min_tail)
+    .filter(|&i| i == 0 || sorted[i] != sorted[i - 1])
+    .collect();
+
+  starts
+    .into_par_iter()
+    .map(|start| {
+      let (alpha, ks) = fit_tail(sorted, start, suffix_ln[start]);
+      PowerLawFit {
+        alpha,
+        xmin: sorted[start],
+        ks,
+        num_tail: sorted.len() - start,
+        num_total: sorted.len(),
+      }
+    })
+    .min_by(|a, b| a.ks.partial_cmp(&b.ks).unwrap().then(a.xmin.cmp(&b.xmin)))
+}
+
+/// Goodness of fit: the fraction of synthetic data sets whose own best fit
+/// is at least as far (in KS distance) as `fit` is from `values`. Synthetic
+/// values are drawn from the fitted power law with probability num_tail /
+/// num_total and otherwise from the observed values below `xmin`.
+#[must_use]
+pub fn bootstrap_p_value<R: Rng + ?Sized>(
+  values: &[usize],
+  fit: &PowerLawFit,
+  options: &FitOptions,
+  rng: &mut R,
+) -> f64 {
+  let below: Vec<_> = values
+    .iter()
+    .copied()
+    .filter(|&x| x > 0 && x < fit.xmin)
+    .collect();
+  let tail_prob = fit.num_tail as f64 / fit.num_total as f64;
+  let seed: u64 = rng.gen();
+
+  let num_as_far = (0..options.num_bootstrap)
+    .into_par_iter()
+    .filter(|&sample| {
+      let mut rng = StdRng::seed_from_u64(seed.wrapping_add(sample as u64));
+      let mut synthetic: Vec<_> = (0..fit.num_total)
+        .map(|_| {
+          if below.is_empty() || rng.gen::<f64>() < tail_prob {
+            // continuous approximation rounded to the nearest integer
+            let r: f64 = rng.gen();
+            let x = (fit.xmin as f64 - 0.5)
+              * (1. - r).powf(-1. / (fit.alpha - 1.))
+              + 0.5;
+            x as usize
+          } else {
+            *below.choose(&mut rng).unwrap()
+          }
+        })
+        .collect();
+      synthetic.sort_unstable();
+      fit_power_law_sorted(&synthetic, options)
+        .is_some_and(|synthetic_fit| synthetic_fit.ks >= fit.ks)
+    })
+    .count();
+
+  num_as_far as f64 / options.num_bootstrap as f64
+}
+
+/// Maximum likelihood fits of the alternatives to a tail.
+#[must_use]
+pub fn fit_alternatives(
+  tail: &[usize],
+  xmin: usize,
+  alpha: f64,
+) -> Vec<TailDistribution> {
+  let n = tail.len() as f64;
+
+  let mean_excess = tail.iter().map(|&x| (x - xmin) as f64).sum::<f64>() / n;
+  let exponential = TailDistribution::Exponential {
+    lambda: (1. / mean_excess.max(1e-12)).ln_1p(),
+  };
+
+  let lns: Vec<_> = tail.iter().map(|&x| (x as f64).ln()).collect();
+  let mean_ln = lns.iter().sum::<f64>() / n;
+  let var_ln = lns.iter().map(|l| (l - mean_ln).powi(2)).sum::<f64>() / n;
+  let [mu, ln_sigma] = nelder_mead_max(
+    |[mu, ln_sigma]| {
+      TailDistribution::LogNormal {
+        mu,
+        sigma: ln_sigma.exp(),
+      }
+      .ln_likelihood(tail, xmin)
+    },
+    [mean_ln, 0.5 * var_ln.max(1e-4).ln()],
+  );
+  let log_normal = TailDistribution::LogNormal {
+    mu,
+    sigma: ln_sigma.exp(),
+  };
+
+  let [alpha, ln_lambda] = nelder_mead_max(
+    |[alpha, ln_lambda]| {
+      TailDistribution::TruncatedPowerLaw {
+        alpha,
+        lambda: ln_lambda.exp(),
+      }
+      .ln_likelihood(tail, xmin)
+    },
+    [alpha, (0.1 / (mean_excess + 1.)).ln()],
+  );
+  let truncated_power_law = TailDistribution::TruncatedPowerLaw {
+    alpha,
+    lambda: ln_lambda.exp(),
+  };
+
+  vec![log_normal, exponential, truncated_power_law]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LikelihoodRatio {
+  /// log likelihood of the power law minus that of the alternative
+  pub ratio: f64,
+  /// ratio over its standard deviation
+  pub normalized: f64,
+  pub p_value: f64,
+}
+
+impl LikelihoodRatio {
+  /// Compare two fits of the same values by their log probabilities.
+  /// `nested` alternatives contain the power law as a special case (one
+  /// extra parameter) and use a chi squared test rather than Vuong's.
+  #[must_use]
+  pub fn new(
+    ln_pmfs: &[f64],
+    alternative_ln_pmfs: &[f64],
+    nested: bool,
+  ) -> Self {
+    let diffs: Vec<_> = ln_pmfs
+      .iter()
+      .zip(alternative_ln_pmfs)
+      .map(|(a, b)| a - b)
+      .collect();
+    let n = diffs.len() as f64;
+    let ratio = diffs.iter().sum::<f64>();
+    let mean = ratio / n;
+    let std_dev =
+      (diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let normalized = if std_dev > 0. {
+      ratio / (n.sqrt() * std_dev)
+    } else {
+      0.
+    };
+    let p_value = if nested {
+      // 2 |ratio| is chi squared with 1 degree of freedom
+      erfc(ratio.abs().sqrt())
+    } else {
+      erfc(normalized.abs() / 2f64.sqrt())
+    };
+
+    Self {
+      ratio,
+      normalized,
+      p_value,
+    }
+  }
+
+  /// Which distribution the test favors: "power_law", the alternative or
+  /// "neither".
+  #[must_use]
+  pub fn favored(
+    &self,
+    alternative: &TailDistribution,
+    significance: f64,
+  ) -> &'static str {
+    if self.p_value >= significance || self.ratio == 0. {
+      "neither"
+    } else if self.ratio > 0. {
+      "power_law"
+    } else {
+      alternative.as_str()
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistributionFit {
+  pub power_law: PowerLawFit,
+  /// None if no bootstrap was run
+  pub p_value: Option<f64>,
+  pub alternatives: Vec<(TailDistribution, LikelihoodRatio)>,
+}
+
+/// Fit a power law, test its goodness of fit and compare it with the log
+/// normal, exponential and truncated power law fits of the same tail.
+#[must_use]
+pub fn fit_distribution<R: Rng + ?Sized>(
+  values: &[usize],
+  options: &FitOptions,
+  rng: &mut R,
+) -> Option<DistributionFit> {
+  let power_law = fit_power_law(values, options)?;
+  let p_value = if options.num_bootstrap > 0 {
+    Some(bootstrap_p_value(values, &power_law, options, rng))
+  } else {
+    None
+  };
+
+  let tail: Vec<_> = values
+    .iter()
+    .copied()
+    .filter(|&x| x >= power_law.xmin && x > 0)
+    .collect();
+  let ln_pmfs = TailDistribution::PowerLaw {
+    alpha: power_law.alpha,
+  }
+  .ln_pmfs(&tail, power_law.xmin);
+  let alternatives = fit_alternatives(&tail, power_law.xmin, power_law.alpha)
+    .into_iter()
+    .map(|alternative| {
+      let nested =
+        matches!(alternative, TailDistribution::TruncatedPowerLaw { .. });
+      let ratio = LikelihoodRatio::new(
+        &ln_pmfs,
+        &alternative.ln_pmfs(&tail, power_law.xmin),
+        nested,
+      );
+      (alternative, ratio)
+    })
+    .collect();
+
+  Some(DistributionFit {
+    power_law,
+    p_value,
+    alternatives,
+  })
+}
+
+#[derive(Serialize)]
+pub struct DistributionFitCsvEntry<'a> {
+  pub distribution: &'a str,
+  pub num_total: usize,
+  pub xmin: usize,
+  pub num_tail: usize,
+  pub alpha: f64,
+  pub ks: f64,
+  pub p_value: Option<f64>,
+  pub alternative: &'static str,
+  pub alternative_params: String,
+  pub log_likelihood_ratio: f64,
+  pub normalized_ratio: f64,
+  pub ratio_p_value: f64,
+  pub favored: &'static str,
+}
+
+/// Save one row per alternative of each named fit.
+pub fn save_fits(
+  csv_path: &Path,
+  fits: &[(String, DistributionFit)],
+  significance: f64,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+  for (name, fit) in fits {
+    for (alternative, ratio) in &fit.alternatives {
+      writer.serialize(DistributionFitCsvEntry {
+        distribution: name,
+        num_total: fit.power_law.num_total,
+        xmin: fit.power_law.xmin,
+        num_tail: fit.power_law.num_tail,
+        alpha: fit.power_law.alpha,
+        ks: fit.power_law.ks,
+        p_value: fit.p_value,
+        alternative: alternative.as_str(),
+        alternative_params: alternative.params(),
+        log_likelihood_ratio: ratio.ratio,
+        normalized_ratio: ratio.normalized,
+        ratio_p_value: ratio.p_value,
+        favored: ratio.favored(alternative, significance),
+      })?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn sample_power_law(
+    alpha: f64,
+    xmin: usize,
+    n: usize,
+    rng: &mut StdRng,
+  ) -> Vec<usize> {
+    // exact inversion of the discrete cdf
+    let norm = hurwitz_zeta(alpha, xmin as f64);
+    (0..n)
+      .map(|_| {
+        let r: f64 = rng.gen();
+        let mut x = xmin;
+        let mut sf = 1.;
+        loop {
+          sf -= (x as f64).powf(-alpha) / norm;
+          if sf <= r || x > 1_000_000 {
+            return x;
+          }
+          x += 1;
+        }
+      })
+      .collect()
+  }
+
+  #[test]
+  fn normalized() {
+    let xmin = 3;
+    let values: Vec<_> = (xmin..xmin + 200_000).collect();
+    for distribution in [
+      TailDistribution::PowerLaw { alpha: 2.5 },
+      TailDistribution::LogNormal {
+        mu: 1.5,
+        sigma: 0.8,
+      },
+      TailDistribution::Exponential { lambda: 0.3 },
+      TailDistribution::TruncatedPowerLaw {
+        alpha: 1.5,
+        lambda: 0.01,
+      },
+      TailDistribution::TruncatedPowerLaw {
+        alpha: 0.5,
+        lambda: 0.5,
+      },
+    ] {
+      let total: f64 = distribution
+        .ln_pmfs(&values, xmin)
+        .iter()
+        .map(|l| l.exp())
+        .sum();
+      assert!((total - 1.).abs() < 1e-6, "{:?} {}", distribution, total);
+    }
+
+    // the rest past the directly summed terms
+    let direct: f64 = (10..1_000_000)
+      .map(|x| (x as f64).powf(-1.2) * (-1e-4 * (x - 10) as f64).exp())
+      .sum();
+    let norm = truncated_power_law_norm(1.2, 1e-4, 10);
+    assert!((direct - norm).abs() < 1e-6 * norm, "{} {}", direct, norm);
+  }
+
+  #[test]
+  fn power_law_data() {
+    let mut rng = StdRng::seed_from_u64(17);
+    // a power law tail above noise
+    let mut values = sample_power_law(2.5, 5, 3000, &mut rng);
+    values.extend((0..1000).map(|_| rng.gen_range(1..5)));
+
+    let options = FitOptions {
+      num_bootstrap: 20,
+      ..Default::default()
+    };
+    let fit = fit_distribution(&values, &options, &mut rng).unwrap();
+    assert!((fit.power_law.alpha - 2.5).abs() < 0.15, "{:?}", fit);
+    assert!((4..=7).contains(&fit.power_law.xmin), "{:?}", fit);
+    assert!(fit.p_value.unwrap() > 0.1, "{:?}", fit);
+
+    let (exponential, ratio) = fit.alternatives[1];
+    assert!(matches!(exponential, TailDistribution::Exponential { .. }));
+    assert_eq!(ratio.favored(&exponential, 0.1), "power_law");
+    let (truncated, ratio) = fit.alternatives[2];
+    assert_ne!(ratio.favored(&truncated, 0.1), "truncated_power_law");
+  }
+
+  #[test]
+  fn exponential_data() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let values: Vec<_> = (0..3000)
+      .map(|_| 1 + (-rng.gen::<f64>().ln() / 0.2) as usize)
+      .collect();
+
+    let options = FitOptions {
+      num_bootstrap: 0,
+      ..Default::default()
+    };
+    let fit = fit_distribution(&values, &options, &mut rng).unwrap();
+    assert_eq!(fit.p_value, None);
+    let (exponential, ratio) = fit.alternatives[1];
+    assert_eq!(ratio.favored(&exponential, 0.1), "exponential", "{:?}", fit);
+    if let TailDistribution::Exponential { lambda } = exponential {
+      assert!((lambda - 0.2).abs() < 0.05, "{}", lambda);
+    }
+  }
+}
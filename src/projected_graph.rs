@@ -1,14 +1,16 @@
 use crate::{
-  connection_strength::ConnectionStrength,
+  connection_strength::{ConnectionStrength, StrengthContext},
   dataset::{Contribution, Dataset, Lens},
   edge_vec::EdgeVec,
+  hubs::{HubFilter, HubOptions},
   progress_bar::get_bar,
   ItemType,
 };
 use fnv::FnvHashMap as Map;
 use indicatif::{ParallelProgressIterator, ProgressIterator};
 use rayon::prelude::*;
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BinaryHeap, sync::Mutex};
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub struct Edge<T: ConnectionStrength> {
@@ -18,54 +20,124 @@ pub struct Edge<T: ConnectionStrength> {
   pub strength: T::Value,
 }
 
+/// How a projected graph is built.
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct ProjectionOptions {
+  /// Keep only the `top_k` strongest edges of each item (an edge is kept if
+  /// it's among the strongest of either item, ties broken by index). This
+  /// bounds memory, but hubs still take time quadratic in their degree.
+  pub top_k: Option<usize>,
+  /// Limit the middle items projected through.
+  pub hubs: Option<HubOptions>,
+}
+
+/// Edges are sorted by strength (strongest first, ties by node indexes), so
+/// the edges at or above any minimum strength are a prefix.
 pub struct ProjectedGraph<T: ConnectionStrength> {
   edges_v: Vec<Edge<T>>,
   edge_idxs_v: EdgeVec<usize>,
 }
 
+/// Contribution pairs (start side first) through which `start_idx` reaches
+/// each item of the same type accepted by `keep_end`, only going through the
+/// contributions of hubs kept by `hubs`.
+fn two_hop_contribs(
+  item_type: ItemType,
+  start_idx: usize,
+  dataset: &Dataset,
+  hubs: &HubFilter,
+  keep_end: impl Fn(usize) -> bool,
+) -> Map<usize, Vec<[usize; 2]>> {
+  let contrib_idx_to_item_idx = |item_type: ItemType, contrib_idx| {
+    let contrib: Contribution = dataset.contributions()[contrib_idx];
+    contrib.idx[item_type]
+  };
+
+  let mut edge_map: Map<_, Vec<[usize; 2]>> = Map::default();
+
+  for &first_contrib_idx in &dataset.contribution_idxs()[item_type][start_idx] {
+    let middle_idx =
+      contrib_idx_to_item_idx(item_type.other(), first_contrib_idx);
+    let middle_contrib_idxs = match hubs.kept(middle_idx) {
+      Some(kept) => {
+        if kept.binary_search(&first_contrib_idx).is_err() {
+          continue;
+        }
+        kept
+      }
+      None => &dataset.contribution_idxs()[item_type.other()][middle_idx],
+    };
+    for (end_idx, second_contrib_idx) in middle_contrib_idxs
+      .iter()
+      .map(|&contrib_idx| {
+        (contrib_idx_to_item_idx(item_type, contrib_idx), contrib_idx)
+      })
+      .filter(|&(end_idx, _)| keep_end(end_idx))
+    {
+      edge_map
+        .entry(end_idx)
+        .or_insert_with(Default::default)
+        .push([first_contrib_idx, second_contrib_idx]);
+    }
+  }
+
+  edge_map
+}
+
+/// Every item of the same type sharing at least one item of the other type
+/// with `start_idx` along with the contribution pairs (start side first)
+/// connecting them.
+#[must_use]
+pub fn two_hop_neighbors(
+  item_type: ItemType,
+  start_idx: usize,
+  dataset: &Dataset,
+) -> Map<usize, Vec<[usize; 2]>> {
+  two_hop_contribs(
+    item_type,
+    start_idx,
+    dataset,
+    &HubFilter::default(),
+    |end_idx| end_idx != start_idx,
+  )
+}
+
+/// The items after `start_idx` connected to it through the middle items
+/// kept by `hubs`: the edges of `start_idx` a projection evaluates.
+#[must_use]
+pub fn later_two_hop_neighbors(
+  item_type: ItemType,
+  start_idx: usize,
+  dataset: &Dataset,
+  hubs: &HubFilter,
+) -> Map<usize, Vec<[usize; 2]>> {
+  two_hop_contribs(item_type, start_idx, dataset, hubs, |end_idx| {
+    end_idx > start_idx
+  })
+}
+
 // We use a "for each" type construct for efficiency - external iterators are
 // very slow if used naively in this context.
 pub fn transitive_edge_compute(
   item_type: ItemType,
   dataset: &Dataset,
+  hubs: &HubFilter,
   f: impl Fn(usize, Map<usize, Vec<[usize; 2]>>) + Send + Sync,
 ) {
   let num_items = dataset.lens()[item_type];
 
   let bar = get_bar(Some(num_items as u64), 10_000);
 
-  let contrib_idx_to_item_idx = |item_type: ItemType, contrib_idx| {
-    let contrib: Contribution = dataset.contributions()[contrib_idx];
-    contrib.idx[item_type]
-  };
-
   // constructing a new map each time is faster because the average case
   // has a small number of edges (also, its better for threading)
   (0..dataset.lens()[item_type])
     .into_par_iter()
     .progress_with(bar)
     .for_each(|start_idx| {
-      let mut edge_map: Map<_, Vec<[usize; 2]>> = Map::default();
-
-      for &first_contrib_idx in
-        &dataset.contribution_idxs()[item_type][start_idx]
-      {
-        let middle_idx =
-          contrib_idx_to_item_idx(item_type.other(), first_contrib_idx);
-        for (end_idx, second_contrib_idx) in dataset.contribution_idxs()
-          [item_type.other()][middle_idx]
-          .iter()
-          .map(|&contrib_idx| {
-            (contrib_idx_to_item_idx(item_type, contrib_idx), contrib_idx)
-          })
-          .filter(|&(end_idx, _)| end_idx > start_idx)
-        {
-          edge_map
-            .entry(end_idx)
-            .or_insert_with(Default::default)
-            .push([first_contrib_idx, second_contrib_idx]);
-        }
-      }
+      let edge_map =
+        later_two_hop_neighbors(item_type, start_idx, dataset, hubs);
 
       f(start_idx, edge_map);
     })
@@ -85,22 +157,72 @@ where
     &self.edge_idxs_v
   }
 
+  /// Number of edges with at least `min_strength` (the length of the prefix
+  /// of `edges()` which they make up).
+  #[must_use]
+  pub fn num_edges_at_least(&self, min_strength: &T::Value) -> usize {
+    self
+      .edges_v
+      .partition_point(|e| e.strength >= *min_strength)
+  }
+
+  /// Keep the edges with at least `min_strength` without rebuilding the edge
+  /// indexes.
   #[must_use]
-  pub fn filter_edges(
+  pub fn filter_edges(&self, min_strength: &T::Value) -> Self {
+    let num_edges = self.num_edges_at_least(min_strength);
+    let mut edge_idxs_v = self.edge_idxs_v.clone();
+    edge_idxs_v.retain(|&i| i < num_edges);
+
+    Self {
+      edges_v: self.edges_v[..num_edges].to_vec(),
+      edge_idxs_v,
+    }
+  }
+
+  /// Like `filter_edges`, but in place (cheaper for increasing thresholds).
+  pub fn truncate(&mut self, min_strength: &T::Value) {
+    let num_edges = self.num_edges_at_least(min_strength);
+    if num_edges == self.edges_v.len() {
+      return;
+    }
+    self.edges_v.truncate(num_edges);
+    self.edge_idxs_v.retain(|&i| i < num_edges);
+  }
+
+  /// Keep the edges for which `keep(edge_idx, edge)` is true.
+  #[must_use]
+  pub fn filter_edges_by(
     &self,
     num_items: usize,
-    min_strength: &T::Value,
+    keep: impl Fn(usize, &Edge<T>) -> bool,
   ) -> Self {
     let edges = self
       .edges()
       .iter()
-      .cloned()
-      .filter(|e| &e.strength >= min_strength)
+      .enumerate()
+      .filter(|&(i, e)| keep(i, e))
+      .map(|(_, e)| e.clone())
       .collect();
 
     Self::from_edges(num_items, edges)
   }
 
+  /// Sorts `edges_v` by strength first.
+  pub(crate) fn from_unsorted_edges(
+    num_items: usize,
+    mut edges_v: Vec<Edge<T>>,
+  ) -> Self {
+    edges_v.par_sort_unstable_by(|l, r| {
+      r.strength
+        .cmp(&l.strength)
+        .then_with(|| l.node_idxs.cmp(&r.node_idxs))
+    });
+
+    Self::from_edges(num_items, edges_v)
+  }
+
+  /// `edges_v` must already be sorted.
   fn from_edges(num_items: usize, edges_v: Vec<Edge<T>>) -> Self {
     let mut edge_idxs = vec![Vec::new(); num_items];
 
@@ -111,7 +233,6 @@ where
     {
       for &idx in &node_idxs {
         edge_idxs[idx].push(i);
-        edge_idxs[idx].push(i);
       }
     }
 
@@ -130,6 +251,36 @@ where
     min_strength: &T::Value,
     dataset: &Dataset,
   ) -> Self {
+    Self::from_dataset_with_options(
+      item_type,
+      connection_strength,
+      min_strength,
+      &Default::default(),
+      dataset,
+    )
+  }
+
+  #[must_use]
+  pub fn from_dataset_with_options(
+    item_type: ItemType,
+    connection_strength: &T,
+    min_strength: &T::Value,
+    options: &ProjectionOptions,
+    dataset: &Dataset,
+  ) -> Self {
+    let hubs = &HubFilter::new(item_type, options.hubs.as_ref(), dataset);
+
+    if let Some(k) = options.top_k {
+      return Self::from_dataset_top_k(
+        item_type,
+        connection_strength,
+        min_strength,
+        k,
+        hubs,
+        dataset,
+      );
+    }
+
     let edges = Mutex::new(Vec::new());
 
     let f = |start_idx, mut edge_map: Map<_, Vec<[usize; 2]>>| {
@@ -138,8 +289,11 @@ where
       let mut new_edges: Vec<_> = edge_map
         .drain()
         .filter_map(|(end_idx, contrib_idxs)| {
-          let strength =
-            connection_strength.strength(item_type, &contrib_idxs, dataset);
+          let strength = connection_strength.strength(&StrengthContext::new(
+            item_type,
+            &contrib_idxs,
+            dataset,
+          ));
           if strength >= *min_strength {
             let edge = Edge {
               node_idxs: [start_idx, end_idx],
@@ -155,8 +309,155 @@ where
       edges.lock().unwrap().append(&mut new_edges)
     };
 
-    transitive_edge_compute(item_type, dataset, f);
+    transitive_edge_compute(item_type, dataset, hubs, f);
+
+    Self::from_unsorted_edges(
+      dataset.lens()[item_type],
+      edges.into_inner().unwrap(),
+    )
+  }
 
-    Self::from_edges(dataset.lens()[item_type], edges.into_inner().unwrap())
+  fn from_dataset_top_k(
+    item_type: ItemType,
+    connection_strength: &T,
+    min_strength: &T::Value,
+    k: usize,
+    hubs: &HubFilter,
+    dataset: &Dataset,
+  ) -> Self {
+    let num_items = dataset.lens()[item_type];
+
+    let bar = get_bar(Some(num_items as u64), 10_000);
+
+    let mut edges: Vec<_> = (0..num_items)
+      .into_par_iter()
+      .progress_with(bar)
+      .flat_map_iter(|start_idx| {
+        // min heap of the strongest so far
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (end_idx, contrib_idxs) in
+          two_hop_contribs(item_type, start_idx, dataset, hubs, |end_idx| {
+            end_idx != start_idx
+          })
+        {
+          let strength = connection_strength.strength(&StrengthContext::new(
+            item_type,
+            &contrib_idxs,
+            dataset,
+          ));
+          if strength < *min_strength {
+            continue;
+          }
+          heap.push(Reverse((strength, Reverse(end_idx))));
+          if heap.len() > k {
+            heap.pop();
+          }
+        }
+
+        heap
+          .into_iter()
+          .map(move |Reverse((strength, Reverse(end_idx)))| Edge {
+            node_idxs: [start_idx.min(end_idx), start_idx.max(end_idx)],
+            strength,
+          })
+      })
+      .collect();
+
+    // edges among the strongest of both items are found twice
+    edges.par_sort_unstable_by_key(|e| e.node_idxs);
+    edges.dedup_by_key(|e| e.node_idxs);
+
+    Self::from_unsorted_edges(num_items, edges)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::{MinNumEvents, NumCommonNodes},
+    dataset,
+    flat_graph::FlatGraph,
+  };
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_top_k(
+        dataset in dataset::small_strategy(),
+        k in 1usize..4,
+      ) {
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let full = ProjectedGraph::from_dataset(
+            item_type,
+            &MinNumEvents,
+            &Default::default(),
+            &dataset,
+          );
+          let top_k = ProjectedGraph::from_dataset_with_options(
+            item_type,
+            &MinNumEvents,
+            &Default::default(),
+            &ProjectionOptions {
+              top_k: Some(k),
+              ..Default::default()
+            },
+            &dataset,
+          );
+
+          let mut full_edges: Vec<_> =
+            full.edges().iter().map(|e| (e.node_idxs, e.strength)).collect();
+          full_edges.sort_unstable();
+          for e in top_k.edges() {
+            prop_assert!(full_edges
+              .binary_search(&(e.node_idxs, e.strength))
+              .is_ok());
+          }
+
+          for (idx, edge_idxs) in full.edge_idxs().iter().enumerate() {
+            let mut strongest: Vec<_> = edge_idxs
+              .iter()
+              .map(|&i| {
+                let e = &full.edges()[i];
+                let other = e.node_idxs[0] + e.node_idxs[1] - idx;
+                (Reverse(e.strength), other)
+              })
+              .collect();
+            strongest.sort_unstable();
+            for &(_, other) in strongest.iter().take(k) {
+              let node_idxs = [idx.min(other), idx.max(other)];
+              prop_assert!(top_k.edges().iter().any(|e| e.node_idxs == node_idxs));
+            }
+          }
+
+          if k >= dataset.lens()[item_type] {
+            prop_assert_eq!(top_k.edges().len(), full.edges().len());
+          }
+        }
+      }
+
+      #[test]
+      fn proptest_degree(
+        dataset in dataset::small_strategy(),
+      ) {
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let graph = ProjectedGraph::from_dataset(
+            item_type,
+            &NumCommonNodes,
+            &1,
+            &dataset,
+          );
+
+          let mut expected = vec![0; dataset.lens()[item_type]];
+          for edge in graph.edges() {
+            for &idx in &edge.node_idxs {
+              expected[idx] += 1;
+            }
+          }
+          for (idx, &degree) in expected.iter().enumerate() {
+            prop_assert_eq!(graph.degree(idx), degree);
+          }
+        }
+      }
   }
 }
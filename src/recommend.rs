@@ -0,0 +1,366 @@
+use crate::{
+  dataset::{Dataset, DatasetNameID, Lens},
+  link_analysis::{l1_diff, pull, strengths},
+  output_data::csv_writer,
+  traversal::Node,
+  ItemType, UserRepoPair,
+};
+use anyhow::Result;
+use fnv::{FnvHashMap as Map, FnvHashSet as Set};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{cmp::Ordering, collections::VecDeque, path::Path};
+
+#[derive(Clone, Copy, Debug)]
+pub enum PprMethod {
+  /// Power iteration over the whole dataset until the L1 change is below
+  /// `tolerance`.
+  PowerIteration {
+    tolerance: f64,
+    max_iterations: usize,
+  },
+  /// Forward push (Andersen, Chung and Lang 2006) which only touches nodes
+  /// near the seeds. Every score is within `epsilon` times the number of
+  /// contributions of the node of the exact score.
+  ForwardPush { epsilon: f64 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PprOptions {
+  /// Probability of jumping back to the seeds at each step.
+  pub restart: f64,
+  pub method: PprMethod,
+}
+
+/// Personalized PageRank (random walk with restart to a uniformly chosen
+/// seed) where each step follows an edge with probability proportional to
+/// the number of contributions. Only nonzero scores are returned.
+#[must_use]
+pub fn personalized_pagerank(
+  dataset: &Dataset,
+  seeds: &[Node],
+  options: PprOptions,
+) -> Map<Node, f64> {
+  match options.method {
+    PprMethod::PowerIteration {
+      tolerance,
+      max_iterations,
+    } => {
+      let scores = ppr_power_iteration(
+        dataset,
+        seeds,
+        options.restart,
+        tolerance,
+        max_iterations,
+      );
+      scores
+        .iter_with()
+        .flat_map(|(item_type, scores)| {
+          scores
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, score)| score > 0.)
+            .map(move |(idx, score)| (Node { item_type, idx }, score))
+        })
+        .collect()
+    }
+    PprMethod::ForwardPush { epsilon } => {
+      ppr_forward_push(dataset, seeds, options.restart, epsilon)
+    }
+  }
+}
+
+fn seed_weights(
+  seeds: &[Node],
+  lens: UserRepoPair<usize>,
+) -> UserRepoPair<Vec<f64>> {
+  let mut weights = lens.map(|l| vec![0.; l]);
+  for seed in seeds {
+    weights[seed.item_type][seed.idx] += 1. / seeds.len() as f64;
+  }
+  weights
+}
+
+/// Dense personalized PageRank. Items without contributions (which can only
+/// be reached as seeds) keep their mass.
+#[must_use]
+pub fn ppr_power_iteration(
+  dataset: &Dataset,
+  seeds: &[Node],
+  restart: f64,
+  tolerance: f64,
+  max_iterations: usize,
+) -> UserRepoPair<Vec<f64>> {
+  let strengths = strengths(dataset);
+  let seed_weights = seed_weights(seeds, dataset.lens());
+
+  let mut scores = seed_weights.clone();
+  for _ in 0..max_iterations {
+    let out = scores.as_ref().map_with(|scores, item_type| {
+      scores
+        .par_iter()
+        .zip(&strengths[item_type])
+        .map(
+          |(score, &strength)| {
+            if strength == 0. {
+              0.
+            } else {
+              score / strength
+            }
+          },
+        )
+        .collect::<Vec<_>>()
+    });
+
+    let new_scores = dataset.lens().map_with(|_, item_type| {
+      let mut pulled = pull(dataset, item_type, &out[item_type.other()]);
+      pulled
+        .par_iter_mut()
+        .zip(&seed_weights[item_type])
+        .zip(&strengths[item_type])
+        .zip(&scores[item_type])
+        .for_each(|(((v, seed_weight), &strength), score)| {
+          // items without contributions keep their mass
+          let walked = if strength == 0. { *score } else { *v };
+          *v = restart * seed_weight + (1. - restart) * walked
+        });
+      pulled
+    });
+
+    let diff: f64 = new_scores
+      .as_ref()
+      .iter_with()
+      .map(|(item_type, new)| l1_diff(new, &scores[item_type]))
+      .sum();
+    scores = new_scores;
+    if diff < tolerance {
+      break;
+    }
+  }
+
+  scores
+}
+
+/// Sparse personalized PageRank by pushing residual mass from nodes with
+/// residual at least `epsilon` times their number of contributions.
+#[must_use]
+pub fn ppr_forward_push(
+  dataset: &Dataset,
+  seeds: &[Node],
+  restart: f64,
+  epsilon: f64,
+) -> Map<Node, f64> {
+  let strength = |node: Node| -> f64 {
+    dataset.contribution_idxs()[node.item_type][node.idx]
+      .iter()
+      .map(|&i| dataset.contributions()[i].num as f64)
+      .sum()
+  };
+
+  let mut scores: Map<Node, f64> = Map::default();
+  let mut residuals: Map<Node, f64> = Map::default();
+  let mut queue = VecDeque::new();
+  for &seed in seeds {
+    *residuals.entry(seed).or_default() += 1. / seeds.len() as f64;
+  }
+  queue.extend(residuals.keys().cloned());
+
+  while let Some(node) = queue.pop_front() {
+    let node_strength = strength(node);
+    let residual = residuals.get(&node).cloned().unwrap_or(0.);
+    if residual < epsilon * node_strength || residual == 0. {
+      continue;
+    }
+    residuals.insert(node, 0.);
+
+    if node_strength == 0. {
+      *scores.entry(node).or_default() += residual;
+      continue;
+    }
+    *scores.entry(node).or_default() += restart * residual;
+
+    let other_type = node.item_type.other();
+    let per_contribution = (1. - restart) * residual / node_strength;
+    for &contrib_idx in &dataset.contribution_idxs()[node.item_type][node.idx] {
+      let contrib = dataset.contributions()[contrib_idx];
+      let other = Node {
+        item_type: other_type,
+        idx: contrib.idx[other_type],
+      };
+      let other_residual = residuals.entry(other).or_default();
+      let before = *other_residual;
+      *other_residual += per_contribution * contrib.num as f64;
+      let threshold = epsilon * strength(other);
+      // only queue when crossing the threshold (nodes already above it are
+      // already queued)
+      if before < threshold && *other_residual >= threshold {
+        queue.push_back(other);
+      }
+    }
+  }
+
+  scores
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Recommendation {
+  pub idx: usize,
+  pub score: f64,
+}
+
+/// The `num` repos with the highest personalized PageRank which aren't seeds
+/// and which seed users haven't contributed to.
+#[must_use]
+pub fn recommend_repos(
+  dataset: &Dataset,
+  seeds: &[Node],
+  options: PprOptions,
+  num: usize,
+) -> Vec<Recommendation> {
+  let excluded: Set<usize> = seeds
+    .iter()
+    .flat_map(|seed| match seed.item_type {
+      ItemType::Repo => vec![seed.idx],
+      ItemType::User => dataset.contribution_idxs()[ItemType::User][seed.idx]
+        .iter()
+        .map(|&i| dataset.contributions()[i].idx.repo)
+        .collect(),
+    })
+    .collect();
+
+  let mut out: Vec<_> = personalized_pagerank(dataset, seeds, options)
+    .into_iter()
+    .filter(|(node, _)| {
+      node.item_type == ItemType::Repo && !excluded.contains(&node.idx)
+    })
+    .map(|(node, score)| Recommendation {
+      idx: node.idx,
+      score,
+    })
+    .collect();
+  out.sort_unstable_by(|l, r| {
+    r.score
+      .partial_cmp(&l.score)
+      .unwrap_or(Ordering::Equal)
+      .then(l.idx.cmp(&r.idx))
+  });
+  out.truncate(num);
+  out
+}
+
+#[derive(Serialize)]
+pub struct RecommendationCsvEntry<ID> {
+  pub rank: usize,
+  pub github_id: ID,
+  pub name: String,
+  pub degree: usize,
+  pub score: f64,
+}
+
+pub fn save_recommendations<D: DatasetNameID>(
+  csv_path: &Path,
+  recommendations: &[Recommendation],
+  dataset: &Dataset,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+
+  for (rank, &Recommendation { idx, score }) in
+    recommendations.iter().enumerate()
+  {
+    writer.serialize(RecommendationCsvEntry {
+      rank,
+      github_id: dataset_info.repo_id(idx),
+      name: dataset_info.repo_name(idx),
+      degree: dataset.contribution_idxs()[ItemType::Repo][idx].len(),
+      score,
+    })?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dataset;
+  use crate::traversal::test::two_dense_components_dataset;
+  use proptest::prelude::*;
+
+  const RESTART: f64 = 0.15;
+
+  fn check_push_matches(
+    dataset: &Dataset,
+    seeds: &[Node],
+  ) -> Result<(), TestCaseError> {
+    let exact = ppr_power_iteration(dataset, seeds, RESTART, 1e-14, 10_000);
+    let epsilon = 1e-8;
+    let pushed = ppr_forward_push(dataset, seeds, RESTART, epsilon);
+
+    for (item_type, scores) in exact.as_ref().iter_with() {
+      for (idx, &score) in scores.iter().enumerate() {
+        let node = Node { item_type, idx };
+        let strength: usize = dataset.contribution_idxs()[item_type][idx]
+          .iter()
+          .map(|&i| dataset.contributions()[i].num)
+          .sum();
+        let approx = pushed.get(&node).cloned().unwrap_or(0.);
+        // scores are an underestimate
+        prop_assert!(approx <= score + 1e-9);
+        prop_assert!(score - approx <= epsilon * strength.max(1) as f64 + 1e-6);
+      }
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn stays_in_component() {
+    let dataset = two_dense_components_dataset();
+    let seeds = [Node {
+      item_type: ItemType::User,
+      idx: 0,
+    }];
+    for &method in &[
+      PprMethod::PowerIteration {
+        tolerance: 1e-12,
+        max_iterations: 1000,
+      },
+      PprMethod::ForwardPush { epsilon: 1e-9 },
+    ] {
+      let recommendations = recommend_repos(
+        &dataset,
+        &seeds,
+        PprOptions {
+          restart: RESTART,
+          method,
+        },
+        10,
+      );
+      // user 0 contributes to repos 0 and 1 of the component with repos 0..4
+      let idxs: Vec<_> = recommendations.iter().map(|r| r.idx).collect();
+      assert_eq!(idxs.len(), 2);
+      assert!(idxs.contains(&2) && idxs.contains(&3));
+    }
+    check_push_matches(&dataset, &seeds).unwrap();
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_push_matches_power_iteration(
+        dataset in dataset::strategy(
+          1usize..20,
+          1usize..20,
+          1usize..=5,
+          1usize..80,
+        ),
+        seed in 0usize..20,
+      ) {
+        let seeds = [Node {
+          item_type: ItemType::Repo,
+          idx: seed % dataset.lens().repo,
+        }];
+        check_push_matches(&dataset, &seeds)?;
+      }
+  }
+}
@@ -1,16 +1,15 @@
 use crate::{
-  connection_strength::ConnectionStrength,
-  dataset::DatasetNameID,
-  item_name_to_save_name,
-  progress_bar::get_bar,
-  projected_graph::ProjectedGraph,
-  traversal::{projected_make_component_dists, projected_traverse_dist},
+  connection_strength::ConnectionStrength, dataset::DatasetNameID,
+  item_name_to_save_name, progress_bar::get_bar,
+  projected_graph::ProjectedGraph, traversal::ProjectedTraversalWorkspace,
   ItemType,
 };
 use anyhow::Result;
 use fnv::FnvHashMap as Map;
 use std::{borrow::Cow, fs::File, io::BufWriter, path::Path};
 
+/// `workspace` should be sized for the `item_type` and can be reused across
+/// calls.
 pub fn save_subgraph<T: ConnectionStrength>(
   output_dir: &Path,
   start: usize,
@@ -18,20 +17,12 @@ pub fn save_subgraph<T: ConnectionStrength>(
   projected_graph: &ProjectedGraph<T>,
   item_type: ItemType,
   dataset_info: &impl DatasetNameID,
+  workspace: &mut ProjectedTraversalWorkspace,
 ) -> Result<()> {
-  let mut visited = vec![false; dataset_info.lens()[item_type]];
-  visited[start] = true;
-  let mut component = projected_make_component_dists(start);
-
   let bar = get_bar(None, 1000);
 
-  projected_traverse_dist(
-    &mut component,
-    &mut visited,
-    projected_graph,
-    Some(limit),
-    |_, _| bar.inc(1),
-  );
+  let component =
+    workspace.traverse(start, projected_graph, Some(limit), |_, _| bar.inc(1));
 
   let name = dataset_info.get_name(item_type, start);
 
@@ -0,0 +1,287 @@
+//! Saving projected graphs to disk so later runs (subgraphs, communities,
+//! threshold sweeps) can skip the projection.
+//!
+//! The binary format is a magic string, the length of a json header, the
+//! header, the number of items and edges and then every edge as two node
+//! indexes and a strength (all little endian 64 bit).
+
+use crate::{
+  connection_strength::{ConnectionStrength, ConnectionStrengthValue},
+  dataset::{Dataset, DatasetNameID},
+  flat_graph::FlatGraph,
+  output_data::csv_writer,
+  progress_bar::get_bar,
+  projected_graph::{Edge, ProjectedGraph, ProjectionOptions},
+  ItemType,
+};
+use anyhow::{anyhow, Result};
+use indicatif::ProgressIterator;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs::File,
+  io::{prelude::*, BufReader, BufWriter},
+  path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"GHNPROJ\0";
+const FORMAT_VERSION: u32 = 1;
+
+/// How a saved projected graph was built.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectedGraphHeader {
+  pub version: u32,
+  pub item_type: ItemType,
+  /// `ConnectionStrength::describe` of the connection strength
+  pub connection_strength: String,
+  /// edges below this weren't kept
+  pub min_strength: f64,
+  pub projection: ProjectionOptions,
+  /// `Dataset::fingerprint` of the dataset projected
+  pub dataset_fingerprint: u64,
+}
+
+impl ProjectedGraphHeader {
+  #[must_use]
+  pub fn new<T: ConnectionStrength>(
+    item_type: ItemType,
+    connection_strength: &T,
+    min_strength: &T::Value,
+    projection: &ProjectionOptions,
+    dataset: &Dataset,
+  ) -> Self {
+    Self {
+      version: FORMAT_VERSION,
+      item_type,
+      connection_strength: connection_strength.describe(),
+      min_strength: min_strength.clone().to_float(),
+      projection: *projection,
+      dataset_fingerprint: dataset.fingerprint(),
+    }
+  }
+
+  /// Errors unless a graph saved with this header can be filtered to get the
+  /// graph `expected` describes: everything must match except the saved
+  /// minimum strength, which may be lower.
+  pub fn check_reusable(&self, expected: &Self) -> Result<()> {
+    if self.dataset_fingerprint != expected.dataset_fingerprint {
+      return Err(anyhow!("saved projected graph is for a different dataset"));
+    }
+    if self.item_type != expected.item_type
+      || self.connection_strength != expected.connection_strength
+    {
+      return Err(anyhow!(
+        "saved projected graph is for {} {} not {} {}",
+        self.item_type.as_str(),
+        self.connection_strength,
+        expected.item_type.as_str(),
+        expected.connection_strength
+      ));
+    }
+    if self.projection != expected.projection {
+      return Err(anyhow!(
+        "saved projected graph used projection options {:?} not {:?}",
+        self.projection,
+        expected.projection
+      ));
+    }
+    if self.min_strength > expected.min_strength {
+      return Err(anyhow!(
+        "saved projected graph has min strength {} which is above {}",
+        self.min_strength,
+        expected.min_strength
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+  let mut bytes = [0; 8];
+  reader.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn save_projected_graph<T: ConnectionStrength>(
+  path: &Path,
+  projected_graph: &ProjectedGraph<T>,
+  header: &ProjectedGraphHeader,
+) -> Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  let header = serde_json::to_vec(header)?;
+  writer.write_all(MAGIC)?;
+  writer.write_all(&(header.len() as u64).to_le_bytes())?;
+  writer.write_all(&header)?;
+
+  let edges = projected_graph.edges();
+  writer.write_all(&(projected_graph.num_nodes() as u64).to_le_bytes())?;
+  writer.write_all(&(edges.len() as u64).to_le_bytes())?;
+
+  let bar = get_bar(Some(edges.len() as u64), 100_000);
+  for edge in edges.iter().progress_with(bar) {
+    for &idx in &edge.node_idxs {
+      writer.write_all(&(idx as u64).to_le_bytes())?;
+    }
+    writer.write_all(&edge.strength.clone().to_float().to_le_bytes())?;
+  }
+
+  writer.flush()?;
+
+  Ok(())
+}
+
+/// Only reads the header of a saved projected graph.
+pub fn load_projected_graph_header(
+  path: &Path,
+) -> Result<ProjectedGraphHeader> {
+  read_header(&mut BufReader::new(File::open(path)?))
+}
+
+fn read_header(reader: &mut impl Read) -> Result<ProjectedGraphHeader> {
+  let mut magic = [0; 8];
+  reader.read_exact(&mut magic)?;
+  if &magic != MAGIC {
+    return Err(anyhow!("not a saved projected graph"));
+  }
+
+  let mut header = vec![0; read_u64(reader)? as usize];
+  reader.read_exact(&mut header)?;
+  let header: ProjectedGraphHeader = serde_json::from_slice(&header)?;
+  if header.version != FORMAT_VERSION {
+    return Err(anyhow!(
+      "saved projected graph has format version {} (expected {})",
+      header.version,
+      FORMAT_VERSION
+    ));
+  }
+
+  Ok(header)
+}
+
+/// The strengths are converted back from `f64`, so `T` must be the
+/// connection strength (or have the same value type as the one) the graph
+/// was saved with.
+pub fn load_projected_graph<T: ConnectionStrength>(
+  path: &Path,
+) -> Result<(ProjectedGraphHeader, ProjectedGraph<T>)> {
+  let mut reader = BufReader::new(File::open(path)?);
+
+  let header = read_header(&mut reader)?;
+  let num_items = read_u64(&mut reader)? as usize;
+  let num_edges = read_u64(&mut reader)? as usize;
+
+  let bar = get_bar(Some(num_edges as u64), 100_000);
+  let edges = (0..num_edges)
+    .progress_with(bar)
+    .map(|_| {
+      let node_idxs = [read_u64(&mut reader)?, read_u64(&mut reader)?]
+        .map(|idx| idx as usize);
+      if node_idxs.iter().any(|&idx| idx >= num_items) {
+        return Err(anyhow!("edge {:?} out of range", node_idxs));
+      }
+      let strength =
+        T::Value::from_float(f64::from_bits(read_u64(&mut reader)?))?;
+
+      Ok(Edge {
+        node_idxs,
+        strength,
+      })
+    })
+    .collect::<Result<_>>()?;
+
+  Ok((
+    header,
+    ProjectedGraph::from_unsorted_edges(num_items, edges),
+  ))
+}
+
+#[derive(Serialize)]
+pub struct ProjectedEdgeCsvEntry<ID> {
+  pub github_id_a: ID,
+  pub name_a: String,
+  pub github_id_b: ID,
+  pub name_b: String,
+  pub strength: f64,
+}
+
+/// Save every edge with the names of its items.
+pub fn save_projected_edges<T: ConnectionStrength, D: DatasetNameID>(
+  csv_path: &Path,
+  projected_graph: &ProjectedGraph<T>,
+  item_type: ItemType,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+
+  let bar = get_bar(Some(projected_graph.edges().len() as u64), 100_000);
+  for edge in projected_graph.edges().iter().progress_with(bar) {
+    let [a, b] = edge.node_idxs;
+    writer.serialize(ProjectedEdgeCsvEntry {
+      github_id_a: dataset_info.get_id(item_type, a),
+      name_a: dataset_info.get_name(item_type, a),
+      github_id_b: dataset_info.get_id(item_type, b),
+      name_b: dataset_info.get_name(item_type, b),
+      strength: edge.strength.clone().to_float(),
+    })?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::GeometricMeanEvents, dataset, output_data::test_dir,
+  };
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_roundtrip(
+        dataset in dataset::small_strategy(),
+      ) {
+        let path = test_dir("projected").join("projected_graph.bin");
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let min_strength = Default::default();
+          let projected = ProjectedGraph::from_dataset(
+            item_type,
+            &GeometricMeanEvents,
+            &min_strength,
+            &dataset,
+          );
+          let header = ProjectedGraphHeader::new(
+            item_type,
+            &GeometricMeanEvents,
+            &min_strength,
+            &Default::default(),
+            &dataset,
+          );
+          save_projected_graph(&path, &projected, &header).unwrap();
+
+          let (loaded_header, loaded) =
+            load_projected_graph::<GeometricMeanEvents>(&path).unwrap();
+          prop_assert_eq!(&loaded_header, &header);
+          prop_assert_eq!(load_projected_graph_header(&path).unwrap(), header.clone());
+          prop_assert!(loaded_header.check_reusable(&header).is_ok());
+          prop_assert_eq!(loaded.edges(), projected.edges());
+          prop_assert_eq!(loaded.num_nodes(), projected.num_nodes());
+          for (l, r) in loaded.edge_idxs().iter().zip(projected.edge_idxs().iter()) {
+            prop_assert_eq!(l, r);
+          }
+
+          let other = ProjectedGraphHeader {
+            dataset_fingerprint: header.dataset_fingerprint.wrapping_add(1),
+            ..header.clone()
+          };
+          prop_assert!(loaded_header.check_reusable(&other).is_err());
+          let lower = ProjectedGraphHeader {
+            min_strength: -1.,
+            ..header.clone()
+          };
+          prop_assert!(loaded_header.check_reusable(&lower).is_err());
+        }
+      }
+  }
+}
@@ -0,0 +1,437 @@
+//! Out of core projection: edges are streamed from `transitive_edge_compute`
+//! into sorted shards on disk, which are then merged into a compressed sparse
+//! row (CSR) adjacency on disk. Only the edge buffer, the row offsets and
+//! whatever is loaded from the CSR need to fit in memory.
+//!
+//! Shards and `neighbors.bin` are records of a row, a column (shards only)
+//! and a strength, all little endian 64 bit. Every edge is stored in both
+//! directions.
+
+use crate::{
+  connection_strength::{
+    ConnectionStrength, ConnectionStrengthValue, StrengthContext,
+  },
+  dataset::{Dataset, Lens},
+  hubs::HubFilter,
+  progress_bar::get_bar,
+  projected_graph::{
+    transitive_edge_compute, Edge, ProjectedGraph, ProjectionOptions,
+  },
+  saved_projection::ProjectedGraphHeader,
+  ItemType,
+};
+use anyhow::{anyhow, Result};
+use fnv::FnvHashMap as Map;
+use rayon::prelude::*;
+use std::{
+  cmp::Reverse,
+  collections::BinaryHeap,
+  fs::{self, File},
+  io::{self, prelude::*, BufReader, BufWriter, SeekFrom},
+  mem,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardOptions {
+  pub shard_dir: PathBuf,
+  /// Edges (each direction counts) buffered in memory before a shard is
+  /// written.
+  pub max_shard_edges: usize,
+}
+
+const RECORD_SIZE: u64 = 16;
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+  let mut bytes = [0; 8];
+  reader.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_shard_entry(
+  reader: &mut impl Read,
+) -> Result<Option<(usize, usize, f64)>> {
+  let row = match read_u64(reader) {
+    Ok(row) => row as usize,
+    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  let col = read_u64(reader)? as usize;
+  let strength = f64::from_bits(read_u64(reader)?);
+
+  Ok(Some((row, col, strength)))
+}
+
+fn write_shard(
+  path: &Path,
+  mut entries: Vec<(usize, usize, f64)>,
+) -> Result<()> {
+  entries.par_sort_unstable_by_key(|&(row, col, _)| (row, col));
+
+  let mut writer = BufWriter::new(File::create(path)?);
+  for (row, col, strength) in entries {
+    writer.write_all(&(row as u64).to_le_bytes())?;
+    writer.write_all(&(col as u64).to_le_bytes())?;
+    writer.write_all(&strength.to_le_bytes())?;
+  }
+  writer.flush()?;
+
+  Ok(())
+}
+
+/// Project `item_type` into sorted shards in `options.shard_dir` (returned
+/// in order). Top k projections aren't supported (they already bound
+/// memory).
+pub fn project_to_shards<T: ConnectionStrength>(
+  item_type: ItemType,
+  connection_strength: &T,
+  min_strength: &T::Value,
+  projection: &ProjectionOptions,
+  dataset: &Dataset,
+  options: &ShardOptions,
+) -> Result<Vec<PathBuf>> {
+  if projection.top_k.is_some() {
+    return Err(anyhow!("top k projections can't be sharded"));
+  }
+  fs::create_dir_all(&options.shard_dir)?;
+
+  let hubs = &HubFilter::new(item_type, projection.hubs.as_ref(), dataset);
+  let buffer = Mutex::new(Vec::new());
+  let shards = Mutex::new(Vec::new());
+  let next_shard = AtomicUsize::new(0);
+  let error = Mutex::new(None);
+
+  let flush = |entries: Vec<_>| {
+    let idx = next_shard.fetch_add(1, Ordering::Relaxed);
+    let path = options.shard_dir.join(format!("shard_{}.bin", idx));
+    match write_shard(&path, entries) {
+      Ok(()) => shards.lock().unwrap().push((idx, path)),
+      Err(err) => *error.lock().unwrap() = Some(err),
+    }
+  };
+
+  let f = |start_idx, mut edge_map: Map<_, Vec<[usize; 2]>>| {
+    let mut new_entries = Vec::new();
+    for (end_idx, contrib_idxs) in edge_map.drain() {
+      let strength = connection_strength.strength(&StrengthContext::new(
+        item_type,
+        &contrib_idxs,
+        dataset,
+      ));
+      if strength >= *min_strength {
+        let strength = strength.to_float();
+        new_entries.push((start_idx, end_idx, strength));
+        new_entries.push((end_idx, start_idx, strength));
+      }
+    }
+
+    let full = {
+      let mut buffer = buffer.lock().unwrap();
+      buffer.append(&mut new_entries);
+      if buffer.len() >= options.max_shard_edges {
+        Some(mem::take(&mut *buffer))
+      } else {
+        None
+      }
+    };
+    // written without holding the lock so other threads keep projecting
+    if let Some(entries) = full {
+      flush(entries);
+    }
+  };
+
+  transitive_edge_compute(item_type, dataset, hubs, f);
+
+  let remaining = buffer.into_inner().unwrap();
+  if !remaining.is_empty() {
+    flush(remaining);
+  }
+  if let Some(err) = error.into_inner().unwrap() {
+    return Err(err);
+  }
+
+  let mut shards = shards.into_inner().unwrap();
+  shards.sort_unstable();
+
+  Ok(shards.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Projected graph adjacency on disk. The row offsets are kept in memory.
+pub struct DiskCsr {
+  dir: PathBuf,
+  header: ProjectedGraphHeader,
+  /// `offsets[i]..offsets[i + 1]` are the records of row `i`
+  offsets: Vec<u64>,
+}
+
+fn csr_paths(dir: &Path) -> [PathBuf; 3] {
+  ["header.json", "offsets.bin", "neighbors.bin"].map(|name| dir.join(name))
+}
+
+impl DiskCsr {
+  /// k-way merge of sorted shards into a CSR in `dir`.
+  pub fn merge_shards(
+    shards: &[PathBuf],
+    dir: &Path,
+    num_items: usize,
+    header: &ProjectedGraphHeader,
+  ) -> Result<Self> {
+    fs::create_dir_all(dir)?;
+    let [header_path, offsets_path, neighbors_path] = csr_paths(dir);
+
+    let mut readers = shards
+      .iter()
+      .map(|path| Ok(BufReader::new(File::open(path)?)))
+      .collect::<Result<Vec<_>>>()?;
+    let mut strengths = vec![0.; shards.len()];
+    let mut heap = BinaryHeap::with_capacity(shards.len());
+    for (i, reader) in readers.iter_mut().enumerate() {
+      if let Some((row, col, strength)) = read_shard_entry(reader)? {
+        strengths[i] = strength;
+        heap.push(Reverse((row, col, i)));
+      }
+    }
+
+    let mut degrees = vec![0u64; num_items];
+    let mut writer = BufWriter::new(File::create(&neighbors_path)?);
+    let bar = get_bar(None, 1_000_000);
+    while let Some(Reverse((row, col, i))) = heap.pop() {
+      if row >= num_items || col >= num_items {
+        return Err(anyhow!("shard entry {} {} out of range", row, col));
+      }
+      degrees[row] += 1;
+      writer.write_all(&(col as u64).to_le_bytes())?;
+      writer.write_all(&strengths[i].to_le_bytes())?;
+      bar.inc(1);
+
+      if let Some((row, col, strength)) = read_shard_entry(&mut readers[i])? {
+        strengths[i] = strength;
+        heap.push(Reverse((row, col, i)));
+      }
+    }
+    writer.flush()?;
+    bar.finish();
+
+    let offsets: Vec<u64> = Some(0)
+      .into_iter()
+      .chain(degrees.iter().scan(0, |total, &degree| {
+        *total += degree;
+        Some(*total)
+      }))
+      .collect();
+    let mut writer = BufWriter::new(File::create(&offsets_path)?);
+    for offset in &offsets {
+      writer.write_all(&offset.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    fs::write(header_path, serde_json::to_vec(header)?)?;
+
+    Ok(Self {
+      dir: dir.to_owned(),
+      header: header.clone(),
+      offsets,
+    })
+  }
+
+  pub fn open(dir: &Path) -> Result<Self> {
+    let [header_path, offsets_path, _] = csr_paths(dir);
+    let header = serde_json::from_slice(&fs::read(header_path)?)?;
+
+    let mut reader = BufReader::new(File::open(offsets_path)?);
+    let mut offsets = Vec::new();
+    loop {
+      match read_u64(&mut reader) {
+        Ok(offset) => offsets.push(offset),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+        Err(err) => return Err(err.into()),
+      }
+    }
+    if offsets.is_empty() {
+      return Err(anyhow!("empty csr offsets"));
+    }
+
+    Ok(Self {
+      dir: dir.to_owned(),
+      header,
+      offsets,
+    })
+  }
+
+  #[must_use]
+  pub fn header(&self) -> &ProjectedGraphHeader {
+    &self.header
+  }
+
+  #[must_use]
+  pub fn num_items(&self) -> usize {
+    self.offsets.len() - 1
+  }
+
+  /// Number of (undirected) edges.
+  #[must_use]
+  pub fn num_edges(&self) -> usize {
+    *self.offsets.last().unwrap() as usize / 2
+  }
+
+  #[must_use]
+  pub fn degree(&self, idx: usize) -> usize {
+    (self.offsets[idx + 1] - self.offsets[idx]) as usize
+  }
+
+  /// Neighbors of `idx` (sorted) with the strength of each edge.
+  pub fn neighbors(&self, idx: usize) -> Result<Vec<(usize, f64)>> {
+    let [_, _, neighbors_path] = csr_paths(&self.dir);
+    let mut file = File::open(neighbors_path)?;
+    file.seek(SeekFrom::Start(self.offsets[idx] * RECORD_SIZE))?;
+    let mut reader = BufReader::new(file);
+
+    (0..self.degree(idx))
+      .map(|_| {
+        let col = read_u64(&mut reader)? as usize;
+        let strength = f64::from_bits(read_u64(&mut reader)?);
+        Ok((col, strength))
+      })
+      .collect()
+  }
+
+  /// Load the edges with at least `min_strength` in one pass over the CSR.
+  pub fn load_filtered<T: ConnectionStrength>(
+    &self,
+    min_strength: &T::Value,
+  ) -> Result<ProjectedGraph<T>> {
+    let [_, _, neighbors_path] = csr_paths(&self.dir);
+    let mut reader = BufReader::new(File::open(neighbors_path)?);
+    let min_strength = min_strength.clone().to_float();
+
+    let mut edges = Vec::new();
+    let bar = get_bar(Some(self.num_items() as u64), 10_000);
+    for row in 0..self.num_items() {
+      for _ in 0..self.degree(row) {
+        let col = read_u64(&mut reader)? as usize;
+        let strength = f64::from_bits(read_u64(&mut reader)?);
+        // each edge is stored in both directions
+        if row < col && strength >= min_strength {
+          edges.push(Edge {
+            node_idxs: [row, col],
+            strength: T::Value::from_float(strength)?,
+          });
+        }
+      }
+      bar.inc(1);
+    }
+    bar.finish();
+
+    Ok(ProjectedGraph::from_unsorted_edges(self.num_items(), edges))
+  }
+}
+
+/// Project into shards, merge them into a CSR in `csr_dir` and remove the
+/// shards.
+pub fn project_to_disk<T: ConnectionStrength>(
+  item_type: ItemType,
+  connection_strength: &T,
+  min_strength: &T::Value,
+  projection: &ProjectionOptions,
+  dataset: &Dataset,
+  options: &ShardOptions,
+  csr_dir: &Path,
+) -> Result<DiskCsr> {
+  let shards = project_to_shards(
+    item_type,
+    connection_strength,
+    min_strength,
+    projection,
+    dataset,
+    options,
+  )?;
+  println!("merging {} shards", shards.len());
+
+  let csr = DiskCsr::merge_shards(
+    &shards,
+    csr_dir,
+    dataset.lens()[item_type],
+    &ProjectedGraphHeader::new(
+      item_type,
+      connection_strength,
+      min_strength,
+      projection,
+      dataset,
+    ),
+  )?;
+  for shard in shards {
+    fs::remove_file(shard)?;
+  }
+
+  Ok(csr)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::GeometricMeanEvents, dataset, flat_graph::FlatGraph,
+    output_data::test_dir,
+  };
+  use proptest::prelude::*;
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_matches_in_memory(
+        dataset in dataset::small_strategy(),
+        max_shard_edges in 1usize..20,
+        min_strength in 0f64..3.,
+      ) {
+        let min_strength =
+          ConnectionStrengthValue::from_float(min_strength).unwrap();
+        for item_type in [ItemType::User, ItemType::Repo] {
+          let dir = test_dir("sharded");
+          let options = ShardOptions {
+            shard_dir: dir.join("shards"),
+            max_shard_edges,
+          };
+          let csr = project_to_disk(
+            item_type,
+            &GeometricMeanEvents,
+            &Default::default(),
+            &Default::default(),
+            &dataset,
+            &options,
+            &dir.join("csr"),
+          )
+          .unwrap();
+          let projected = ProjectedGraph::from_dataset(
+            item_type,
+            &GeometricMeanEvents,
+            &Default::default(),
+            &dataset,
+          );
+          prop_assert_eq!(csr.num_edges(), projected.edges().len());
+
+          let csr = DiskCsr::open(&dir.join("csr")).unwrap();
+          prop_assert_eq!(csr.num_items(), projected.num_nodes());
+          for idx in 0..csr.num_items() {
+            let mut expected: Vec<_> = projected.edge_idxs()[idx]
+              .iter()
+              .map(|&i| {
+                let e = &projected.edges()[i];
+                (e.node_idxs[0] + e.node_idxs[1] - idx, e.strength.into_inner())
+              })
+              .collect();
+            expected.sort_by(|l, r| l.partial_cmp(r).unwrap());
+            prop_assert_eq!(csr.neighbors(idx).unwrap(), expected);
+          }
+
+          let loaded: ProjectedGraph<GeometricMeanEvents> =
+            csr.load_filtered(&min_strength).unwrap();
+          let filtered = projected.filter_edges(&min_strength);
+          prop_assert_eq!(loaded.edges(), filtered.edges());
+          fs::remove_dir_all(dir).unwrap();
+        }
+      }
+  }
+}
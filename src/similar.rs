@@ -0,0 +1,302 @@
+use crate::{
+  connection_strength::{
+    ConnectionStrength, ConnectionStrengthValue, ExpectationAccelerator,
+    StrengthContext,
+  },
+  dataset::{Dataset, DatasetNameID},
+  output_data::csv_writer,
+  projected_graph::two_hop_neighbors,
+  ItemType,
+};
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+use std::{cmp::Reverse, path::Path};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimilarItem {
+  pub idx: usize,
+  /// Strength used for ranking.
+  pub strength: f64,
+  /// Strength under the connection strength the expectation is for.
+  pub raw_strength: f64,
+  pub expectation: f64,
+  /// Shared items of the other type with the number of contributions from
+  /// the query item and from this item (largest minimum first).
+  pub shared: Vec<(usize, [usize; 2])>,
+}
+
+/// The `k` items of the same type most strongly connected to `idx` under
+/// `connection_strength` (ties are broken by index). Only the 2 hop
+/// neighborhood of `idx` is visited. `raw` is the connection strength
+/// `accelerator` is for (the inner strength for normalized strengths).
+#[must_use]
+pub fn top_k_similar<T: ConnectionStrength, V: ConnectionStrength>(
+  item_type: ItemType,
+  idx: usize,
+  k: usize,
+  connection_strength: &T,
+  raw: &V,
+  accelerator: &ExpectationAccelerator<V>,
+  dataset: &Dataset,
+) -> Vec<SimilarItem> {
+  let mut found: Vec<_> = two_hop_neighbors(item_type, idx, dataset)
+    .into_iter()
+    .map(|(end_idx, contrib_idxs)| {
+      let strength = connection_strength.strength(&StrengthContext::new(
+        item_type,
+        &contrib_idxs,
+        dataset,
+      ));
+      (end_idx, contrib_idxs, strength)
+    })
+    .collect();
+
+  let cmp = |(l_idx, _, l): &(usize, _, T::Value),
+             (r_idx, _, r): &(usize, _, T::Value)| {
+    r.cmp(l).then(l_idx.cmp(r_idx))
+  };
+  if k < found.len() {
+    found.select_nth_unstable_by(k, cmp);
+    found.truncate(k);
+  }
+  found.sort_unstable_by(cmp);
+
+  found
+    .into_iter()
+    .map(|(end_idx, contrib_idxs, strength)| {
+      let raw_strength =
+        raw.strength(&StrengthContext::new(item_type, &contrib_idxs, dataset));
+      let shared = contrib_idxs
+        .iter()
+        .map(|pair| {
+          let first = dataset.contributions()[pair[0]];
+          let second = dataset.contributions()[pair[1]];
+          (first.idx[item_type.other()], [first.num, second.num])
+        })
+        .sorted_by_key(|&(other_idx, nums)| {
+          (Reverse(nums[0].min(nums[1])), other_idx)
+        })
+        .collect();
+
+      SimilarItem {
+        idx: end_idx,
+        strength: strength.to_float(),
+        raw_strength: raw_strength.to_float(),
+        expectation: accelerator.expectation([idx, end_idx]),
+        shared,
+      }
+    })
+    .collect()
+}
+
+#[derive(Serialize)]
+pub struct SimilarCsvEntry<ID> {
+  pub rank: usize,
+  pub github_id: ID,
+  pub name: String,
+  pub degree: usize,
+  pub strength: f64,
+  pub raw_strength: f64,
+  pub expectation: f64,
+  pub num_shared: usize,
+  /// at most `num_shared` entries of "name:query_num:num" separated by ';'
+  pub shared: String,
+}
+
+pub fn save_similar<D: DatasetNameID>(
+  csv_path: &Path,
+  item_type: ItemType,
+  similar: &[SimilarItem],
+  num_shared: usize,
+  dataset: &Dataset,
+  dataset_info: &D,
+) -> Result<()> {
+  let mut writer = csv_writer(csv_path)?;
+
+  for (rank, item) in similar.iter().enumerate() {
+    writer.serialize(SimilarCsvEntry {
+      rank,
+      github_id: dataset_info.get_id(item_type, item.idx),
+      name: dataset_info.get_name(item_type, item.idx),
+      degree: dataset.contribution_idxs()[item_type][item.idx].len(),
+      strength: item.strength,
+      raw_strength: item.raw_strength,
+      expectation: item.expectation,
+      num_shared: item.shared.len(),
+      shared: item
+        .shared
+        .iter()
+        .take(num_shared)
+        .map(|&(other_idx, [query_num, num])| {
+          format!(
+            "{}:{}:{}",
+            dataset_info.get_name(item_type.other(), other_idx),
+            query_num,
+            num
+          )
+        })
+        .join(";"),
+    })?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    connection_strength::{MinNumEvents, Normalized, NumCommonNodes, ZScore},
+    dataset::{self, Lens},
+    projected_graph::ProjectedGraph,
+    UserRepoPair,
+  };
+  use proptest::prelude::*;
+
+  fn check_matches_projected<T: ConnectionStrength + Default>(
+    dataset: &Dataset,
+  ) -> Result<(), TestCaseError> {
+    let strength = T::default();
+    for (item_type, len) in dataset.lens().iter_with() {
+      let accelerator = ExpectationAccelerator::<T>::new(item_type, dataset);
+      let projected = ProjectedGraph::from_dataset(
+        item_type,
+        &strength,
+        &Default::default(),
+        dataset,
+      );
+      for idx in 0..len {
+        let mut expected: Vec<_> = projected
+          .edges()
+          .iter()
+          .filter(|e| e.node_idxs.contains(&idx))
+          .map(|e| {
+            let [a, b] = e.node_idxs;
+            (if a == idx { b } else { a }, e.strength.clone().to_float())
+          })
+          .collect();
+        expected
+          .sort_by(|l, r| r.1.partial_cmp(&l.1).unwrap().then(l.0.cmp(&r.0)));
+
+        let actual: Vec<_> = top_k_similar(
+          item_type,
+          idx,
+          usize::MAX,
+          &strength,
+          &strength,
+          &accelerator,
+          dataset,
+        )
+        .into_iter()
+        .map(|item| (item.idx, item.strength))
+        .collect();
+        prop_assert_eq!(&actual, &expected);
+
+        let top = top_k_similar(
+          item_type,
+          idx,
+          2,
+          &strength,
+          &strength,
+          &accelerator,
+          dataset,
+        );
+        prop_assert_eq!(top.len(), expected.len().min(2));
+        for (item, &(end_idx, _)) in top.iter().zip(&expected) {
+          prop_assert_eq!(item.idx, end_idx);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Normalized strengths rank by the normalized value and report the
+  /// expectation of the inner strength.
+  fn check_normalized<T: ConnectionStrength + Default>(
+    dataset: &Dataset,
+  ) -> Result<(), TestCaseError> {
+    let inner = T::default();
+    let accelerators = &UserRepoPair::<()>::default()
+      .map_with(|_, item_type| ExpectationAccelerator::new(item_type, dataset));
+    let normalized = Normalized {
+      inner,
+      accelerators,
+    };
+    let z_score = ZScore {
+      inner,
+      accelerators,
+    };
+
+    for (item_type, len) in dataset.lens().iter_with() {
+      let accelerator = &accelerators[item_type];
+      for idx in 0..len {
+        let num_neighbors = two_hop_neighbors(item_type, idx, dataset).len();
+        let by_normalized = top_k_similar(
+          item_type,
+          idx,
+          usize::MAX,
+          &normalized,
+          &inner,
+          accelerator,
+          dataset,
+        );
+        let by_z_score = top_k_similar(
+          item_type,
+          idx,
+          usize::MAX,
+          &z_score,
+          &inner,
+          accelerator,
+          dataset,
+        );
+
+        for (similar, is_z_score) in
+          [(by_normalized, false), (by_z_score, true)]
+        {
+          prop_assert_eq!(similar.len(), num_neighbors);
+          for pair in similar.windows(2) {
+            prop_assert!(
+              pair[0].strength > pair[1].strength
+                || (pair[0].strength == pair[1].strength
+                  && pair[0].idx < pair[1].idx)
+            );
+          }
+          for item in &similar {
+            let (mean, var) = accelerator.moments([idx, item.idx]);
+            prop_assert_eq!(item.expectation, mean);
+            let expected = if !is_z_score {
+              item.raw_strength / mean
+            } else if var > 0. {
+              (item.raw_strength - mean) / var.sqrt()
+            } else {
+              0.
+            };
+            prop_assert_eq!(item.strength, expected);
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  proptest::proptest! {
+      #[test]
+      fn proptest_matches_projected(
+        dataset in dataset::small_strategy(),
+      ) {
+        check_matches_projected::<NumCommonNodes>(&dataset)?;
+        check_matches_projected::<MinNumEvents>(&dataset)?;
+      }
+
+      #[test]
+      fn proptest_normalized(
+        dataset in dataset::small_strategy(),
+      ) {
+        check_normalized::<NumCommonNodes>(&dataset)?;
+        check_normalized::<MinNumEvents>(&dataset)?;
+      }
+  }
+}
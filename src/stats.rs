@@ -0,0 +1,351 @@
+//! Special functions and distribution tails for significance tests.
+
+use std::f64::consts::PI;
+
+const LANCZOS_G: f64 = 7.;
+const LANCZOS_COEFFS: [f64; 9] = [
+  0.999_999_999_999_809_9,
+  676.520_368_121_885_1,
+  -1_259.139_216_722_402_8,
+  771.323_428_777_653_1,
+  -176.615_029_162_140_6,
+  12.507_343_278_686_905,
+  -0.138_571_095_265_720_12,
+  9.984_369_578_019_572e-6,
+  1.505_632_735_149_311_6e-7,
+];
+
+/// Natural log of the gamma function for positive `x` (Lanczos
+/// approximation, accurate to about 15 digits).
+#[must_use]
+pub fn ln_gamma(x: f64) -> f64 {
+  if x < 0.5 {
+    // reflection
+    (PI / (PI * x).sin()).ln() - ln_gamma(1. - x)
+  } else {
+    let x = x - 1.;
+    let t = x + LANCZOS_G + 0.5;
+    let series = LANCZOS_COEFFS[1..]
+      .iter()
+      .enumerate()
+      .fold(LANCZOS_COEFFS[0], |acc, (i, &c)| {
+        acc + c / (x + (i + 1) as f64)
+      });
+    0.5 * (2. * PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+  }
+}
+
+#[must_use]
+pub fn ln_beta(a: f64, b: f64) -> f64 {
+  ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b)
+}
+
+#[must_use]
+pub fn ln_choose(n: f64, k: f64) -> f64 {
+  ln_gamma(n + 1.) - ln_gamma(k + 1.) - ln_gamma(n - k + 1.)
+}
+
+/// `ln(erfc(x))` for `x >= 0`, without underflowing for large `x`.
+fn ln_erfc_tail(x: f64) -> f64 {
+  let t = 1. / (1. + 0.5 * x);
+  let poly = -x * x - 1.265_512_23
+    + t
+      * (1.000_023_68
+        + t
+          * (0.374_091_96
+            + t
+              * (0.096_784_18
+                + t
+                  * (-0.186_288_06
+                    + t
+                      * (0.278_868_07
+                        + t
+                          * (-1.135_203_98
+                            + t
+                              * (1.488_515_87
+                                + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+  t.ln() + poly
+}
+
+/// Complementary error function (fractional error below 1.2e-7).
+#[must_use]
+pub fn erfc(x: f64) -> f64 {
+  let out = ln_erfc_tail(x.abs()).exp();
+  if x >= 0. {
+    out
+  } else {
+    2. - out
+  }
+}
+
+/// Probability a standard normal is above `z`.
+#[must_use]
+pub fn normal_sf(z: f64) -> f64 {
+  0.5 * erfc(z / 2f64.sqrt())
+}
+
+/// Natural log of `normal_sf` which stays accurate far into the upper tail.
+#[must_use]
+pub fn ln_normal_sf(z: f64) -> f64 {
+  let x = z / 2f64.sqrt();
+  if x < 0. {
+    return (0.5 * erfc(x)).ln();
+  }
+  0.5f64.ln() + ln_erfc_tail(x)
+}
+
+/// Bernoulli numbers B_2, B_4, ..., B_14.
+const BERNOULLI: [f64; 7] = [
+  1. / 6.,
+  -1. / 30.,
+  1. / 42.,
+  -1. / 30.,
+  5. / 66.,
+  -691. / 2730.,
+  7. / 6.,
+];
+
+/// Hurwitz zeta function: the sum of (q + k)^-s over k >= 0, for s > 1 and
+/// q > 0 (Euler-Maclaurin summation).
+#[must_use]
+pub fn hurwitz_zeta(s: f64, q: f64) -> f64 {
+  const DIRECT_TERMS: usize = 10;
+
+  let direct: f64 = (0..DIRECT_TERMS).map(|k| (q + k as f64).powf(-s)).sum();
+  let a = q + DIRECT_TERMS as f64;
+  let mut out = direct + a.powf(1. - s) / (s - 1.) + 0.5 * a.powf(-s);
+
+  // term j is B_2j / (2j)! * s (s + 1) ... (s + 2j - 2) * a^(-s - 2j + 1)
+  let mut factor = s * a.powf(-s - 1.);
+  let mut factorial = 2.;
+  for (j, &bernoulli) in BERNOULLI.iter().enumerate() {
+    out += bernoulli / factorial * factor;
+    let next = 2. * (j + 1) as f64;
+    factor *= (s + next - 1.) * (s + next) / (a * a);
+    factorial *= (next + 1.) * (next + 2.);
+  }
+
+  out
+}
+
+/// log(exp(a) + exp(b))
+#[must_use]
+pub fn ln_add_exp(a: f64, b: f64) -> f64 {
+  if a == f64::NEG_INFINITY {
+    return b;
+  }
+  let (high, low) = if a > b { (a, b) } else { (b, a) };
+  high + (low - high).exp().ln_1p()
+}
+
+/// Sums terms of a discrete distribution in log space starting from `start`
+/// and moving by `step` (1 or -1) until the terms are negligible or `end` is
+/// passed. `ln_ratio(x)` is log(pmf(x + step) / pmf(x)).
+fn ln_tail_sum(
+  ln_start: f64,
+  start: f64,
+  end: f64,
+  step: f64,
+  ln_ratio: impl Fn(f64) -> f64,
+) -> f64 {
+  let mut total = ln_start;
+  let mut term = ln_start;
+  let mut x = start;
+  while (end - x) * step >= 1. {
+    term += ln_ratio(x);
+    x += step;
+    total = ln_add_exp(total, term);
+    // terms only decrease away from the mode, so stop once they can't
+    // matter
+    if term < total - 40. && ln_ratio(x) < 0. {
+      break;
+    }
+  }
+  total
+}
+
+/// Probability a binomial with `n` trials of probability `p` is at least
+/// `k`.
+#[must_use]
+pub fn binomial_sf(k: f64, n: f64, p: f64) -> f64 {
+  if k <= 0. {
+    return 1.;
+  }
+  if k > n || p <= 0. {
+    return 0.;
+  }
+  if p >= 1. {
+    return 1.;
+  }
+  let ln_pmf = |x: f64| ln_choose(n, x) + x * p.ln() + (n - x) * (-p).ln_1p();
+  let ln_odds = p.ln() - (-p).ln_1p();
+  let ln_up = |x: f64| ((n - x) / (x + 1.)).ln() + ln_odds;
+  if k >= n * p {
+    ln_tail_sum(ln_pmf(k), k, n, 1., ln_up).exp().min(1.)
+  } else {
+    let ln_down = |x: f64| -ln_up(x - 1.);
+    let lower = ln_tail_sum(ln_pmf(k - 1.), k - 1., 0., -1., ln_down).exp();
+    (1. - lower).max(0.)
+  }
+}
+
+/// Probability a beta binomial with `n` trials and shape `alpha`, `beta` is
+/// at least `k`.
+#[must_use]
+pub fn beta_binomial_sf(k: f64, n: f64, alpha: f64, beta: f64) -> f64 {
+  if k <= 0. {
+    return 1.;
+  }
+  if k > n {
+    return 0.;
+  }
+  let ln_pmf = |x: f64| {
+    ln_choose(n, x) + ln_beta(x + alpha, n - x + beta) - ln_beta(alpha, beta)
+  };
+  let ln_up = |x: f64| {
+    ((n - x) / (x + 1.)).ln() + ((x + alpha) / (n - x - 1. + beta)).ln()
+  };
+  let mean = n * alpha / (alpha + beta);
+  if k >= mean {
+    ln_tail_sum(ln_pmf(k), k, n, 1., ln_up).exp().min(1.)
+  } else {
+    // sum the (smaller) lower tail instead
+    let ln_down = |x: f64| -ln_up(x - 1.);
+    let lower = ln_tail_sum(ln_pmf(k - 1.), k - 1., 0., -1., ln_down).exp();
+    (1. - lower).max(0.)
+  }
+}
+
+/// Natural log of the probability a hypergeometric variable is at least `k`
+/// when drawing `draws` items from `population` items of which `successes`
+/// are successes. Stays accurate when the probability underflows a f64.
+#[must_use]
+pub fn ln_hypergeometric_sf(
+  k: f64,
+  population: f64,
+  successes: f64,
+  draws: f64,
+) -> f64 {
+  let min = (draws + successes - population).max(0.);
+  let max = successes.min(draws);
+  if k <= min {
+    return 0.;
+  }
+  if k > max {
+    return f64::NEG_INFINITY;
+  }
+  let ln_pmf = |x: f64| {
+    ln_choose(successes, x) + ln_choose(population - successes, draws - x)
+      - ln_choose(population, draws)
+  };
+  let ln_up = |x: f64| {
+    ((successes - x) * (draws - x)).ln()
+      - ((x + 1.) * (population - successes - draws + x + 1.)).ln()
+  };
+  let mean = draws * successes / population;
+  if k >= mean {
+    ln_tail_sum(ln_pmf(k), k, max, 1., ln_up).min(0.)
+  } else {
+    let ln_down = |x: f64| -ln_up(x - 1.);
+    let lower = ln_tail_sum(ln_pmf(k - 1.), k - 1., min, -1., ln_down).exp();
+    (-lower.min(1.)).ln_1p()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn special_functions() {
+    assert!(ln_gamma(1.).abs() < 1e-12);
+    assert!((ln_gamma(5.) - 24f64.ln()).abs() < 1e-12);
+    assert!((ln_gamma(0.5) - PI.sqrt().ln()).abs() < 1e-12);
+    assert!((ln_gamma(100.) - 359.134_205_369_575_4).abs() < 1e-9);
+    assert!((erfc(0.) - 1.).abs() < 1e-7);
+    assert!((normal_sf(1.959_963_985) - 0.025).abs() < 1e-7);
+    assert!((normal_sf(-1.959_963_985) - 0.975).abs() < 1e-7);
+    assert!((ln_normal_sf(1.) - normal_sf(1.).ln()).abs() < 1e-7);
+    // sf(30) is about 4.906e-198
+    assert!((ln_normal_sf(30.) - 4.906_714e-198f64.ln()).abs() < 1e-5);
+
+    let zeta_2 = PI * PI / 6.;
+    assert!((hurwitz_zeta(2., 1.) - zeta_2).abs() < 1e-13);
+    assert!((hurwitz_zeta(2., 3.) - (zeta_2 - 1.25)).abs() < 1e-13);
+    assert!((hurwitz_zeta(3., 1.) - 1.202_056_903_159_594_2).abs() < 1e-13);
+    assert!(
+      (hurwitz_zeta(1.5, 0.5) - 2.612_375_348_685_488 * 1.828_427_124_746_19)
+        .abs()
+        < 1e-9
+    );
+  }
+
+  #[test]
+  fn beta_binomial() {
+    let (n, alpha, beta) = (20., 2.5, 4.);
+    let pmf = |x: f64| {
+      (ln_choose(n, x) + ln_beta(x + alpha, n - x + beta)
+        - ln_beta(alpha, beta))
+      .exp()
+    };
+    let total: f64 = (0..=20).map(|x| pmf(x as f64)).sum();
+    assert!((total - 1.).abs() < 1e-10);
+
+    for k in 0..=21 {
+      let expected: f64 = (k..=20).map(|x| pmf(x as f64)).sum();
+      let actual = beta_binomial_sf(k as f64, n, alpha, beta);
+      assert!(
+        (actual - expected).abs() < 1e-10,
+        "{} {} {}",
+        k,
+        actual,
+        expected
+      );
+    }
+  }
+
+  #[test]
+  fn binomial() {
+    for &(n, p) in &[(20., 0.3), (50., 0.01), (7., 0.9)] {
+      let pmf = |x: f64| {
+        (ln_choose(n, x) + x * f64::ln(p) + (n - x) * f64::ln(1. - p)).exp()
+      };
+      for k in 0..=(n as usize + 1) {
+        let expected: f64 = (k..=n as usize).map(|x| pmf(x as f64)).sum();
+        let actual = binomial_sf(k as f64, n, p);
+        assert!(
+          (actual - expected).abs() < 1e-10,
+          "{} {} {} {}",
+          n,
+          k,
+          actual,
+          expected
+        );
+      }
+    }
+    assert_eq!(binomial_sf(1., 10., 0.), 0.);
+    assert_eq!(binomial_sf(10., 10., 1.), 1.);
+  }
+
+  #[test]
+  fn hypergeometric() {
+    let (population, successes, draws) = (30., 12., 9.);
+    let pmf = |x: f64| {
+      (ln_choose(successes, x) + ln_choose(population - successes, draws - x)
+        - ln_choose(population, draws))
+      .exp()
+    };
+
+    for k in 0..=10 {
+      let expected: f64 = (k..=9).map(|x| pmf(x as f64)).sum();
+      let actual =
+        ln_hypergeometric_sf(k as f64, population, successes, draws).exp();
+      assert!((actual - expected).abs() < 1e-10, "{} {}", actual, expected);
+    }
+
+    // far past what a f64 probability can hold
+    let ln_p = ln_hypergeometric_sf(5000., 1e7, 5000., 5000.);
+    let expected = ln_choose(1e7, 5000.);
+    assert!((ln_p + expected).abs() < 1e-6 * expected, "{}", ln_p);
+  }
+}
@@ -4,7 +4,7 @@ use crate::{
   projected_graph::ProjectedGraph,
   ItemType, UserRepoPair,
 };
-use std::{hash::Hash, iter};
+use std::{hash::Hash, iter, sync::Mutex};
 
 /// construct using Node
 pub type Component = UserRepoPair<Vec<usize>>;
@@ -54,8 +54,33 @@ pub struct Node {
 }
 
 impl Node {
-  pub fn set_visited(self, visited: &mut Visited) {
-    visited.as_mut()[self.item_type][self.idx] = true;
+  pub fn set_visited(self, visited: &mut UserRepoPair<impl VisitedMark>) {
+    visited[self.item_type].visit(self.idx);
+  }
+
+  /// Index with users first and then repos.
+  #[must_use]
+  pub fn to_flat(self, lens: UserRepoPair<usize>) -> usize {
+    match self.item_type {
+      ItemType::User => self.idx,
+      ItemType::Repo => lens.user + self.idx,
+    }
+  }
+
+  /// Inverse of `to_flat`.
+  #[must_use]
+  pub fn from_flat(flat: usize, lens: UserRepoPair<usize>) -> Self {
+    if flat < lens.user {
+      Self {
+        item_type: ItemType::User,
+        idx: flat,
+      }
+    } else {
+      Self {
+        item_type: ItemType::Repo,
+        idx: flat - lens.user,
+      }
+    }
   }
 }
 
@@ -96,9 +121,64 @@ pub fn default_visited(dataset: &Dataset) -> Visited {
   dataset.lens().map(|l| vec![false; l])
 }
 
+pub trait VisitedMark {
+  #[must_use]
+  fn is_visited(&self, idx: usize) -> bool;
+
+  /// returns true if idx wasn't already visited
+  fn visit(&mut self, idx: usize) -> bool;
+}
+
+impl VisitedMark for Vec<bool> {
+  fn is_visited(&self, idx: usize) -> bool {
+    self[idx]
+  }
+
+  fn visit(&mut self, idx: usize) -> bool {
+    !std::mem::replace(&mut self[idx], true)
+  }
+}
+
+/// Visited marks which can be reset in O(1) by bumping the epoch, so repeated
+/// traversals only pay for the nodes they actually touch.
+#[derive(Clone, Debug)]
+pub struct EpochVisited {
+  stamps: Vec<u32>,
+  epoch: u32,
+}
+
+impl EpochVisited {
+  #[must_use]
+  pub fn new(len: usize) -> Self {
+    Self {
+      stamps: vec![0; len],
+      epoch: 1,
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.epoch = self.epoch.wrapping_add(1);
+    if self.epoch == 0 {
+      // wrapped, so old stamps could alias the new epoch
+      self.stamps.iter_mut().for_each(|s| *s = 0);
+      self.epoch = 1;
+    }
+  }
+}
+
+impl VisitedMark for EpochVisited {
+  fn is_visited(&self, idx: usize) -> bool {
+    self.stamps[idx] == self.epoch
+  }
+
+  fn visit(&mut self, idx: usize) -> bool {
+    std::mem::replace(&mut self.stamps[idx], self.epoch) != self.epoch
+  }
+}
+
 pub fn traverse(
   component: &mut Component,
-  visited: &mut UserRepoPair<Vec<bool>>,
+  visited: &mut UserRepoPair<impl VisitedMark>,
   dataset: &Dataset,
   limit: Option<usize>,
   callback: impl FnMut(Node, usize),
@@ -108,7 +188,7 @@ pub fn traverse(
 
 pub fn traverse_dist(
   component: &mut ComponentDists,
-  visited: &mut UserRepoPair<Vec<bool>>,
+  visited: &mut UserRepoPair<impl VisitedMark>,
   dataset: &Dataset,
   limit: Option<usize>,
   callback: impl FnMut(Node, usize),
@@ -123,7 +203,7 @@ pub fn traverse_dist(
 
 fn traverse_gen(
   component: &mut UserRepoPair<impl ComponentAccess>,
-  visited: &mut UserRepoPair<Vec<bool>>,
+  visited: &mut UserRepoPair<impl VisitedMark>,
   dataset: &Dataset,
   limit: Option<usize>,
   mut callback: impl FnMut(Node, usize),
@@ -138,7 +218,7 @@ fn traverse_gen(
       .idxs()
       .iter()
       .map(move |&i| (item_type, i)))
-    .all(|(item_type, i)| visited[item_type][i]));
+    .all(|(item_type, i)| visited[item_type].is_visited(i)));
 
   let mut start = UserRepoPair { user: 0, repo: 0 };
   let mut dist = 0;
@@ -170,9 +250,11 @@ fn traverse_gen(
   }
 }
 
-trait ComponentAccess: Hash + Eq {
+pub(crate) trait ComponentAccess: Hash + Eq {
   fn idxs(&self) -> &[usize];
   fn add_items(&mut self, dist: usize, idxs: impl IntoIterator<Item = usize>);
+  /// remove all items, but keep the allocation
+  fn clear(&mut self);
 }
 
 impl ComponentAccess for Vec<usize> {
@@ -180,6 +262,10 @@ impl ComponentAccess for Vec<usize> {
     self
   }
 
+  fn clear(&mut self) {
+    Vec::clear(self);
+  }
+
   fn add_items(&mut self, _dist: usize, idxs: impl IntoIterator<Item = usize>) {
     self.extend(idxs);
   }
@@ -194,13 +280,18 @@ impl ComponentAccess for IdxDist {
     self.idxs_v.extend(idxs);
     self.dists_v.resize(self.idxs_v.len(), dist);
   }
+
+  fn clear(&mut self) {
+    self.idxs_v.clear();
+    self.dists_v.clear();
+  }
 }
 
 fn traversal_step(
   item_type: ItemType,
   dist: usize,
   start: &mut UserRepoPair<usize>,
-  visited: &mut UserRepoPair<Vec<bool>>,
+  visited: &mut UserRepoPair<impl VisitedMark>,
   component: &mut UserRepoPair<impl ComponentAccess>,
   dataset: &Dataset,
   callback: &mut impl FnMut(Node, usize),
@@ -212,11 +303,7 @@ fn traversal_step(
       .iter()
       .filter_map(|&i| {
         let other_idx = dataset.contributions()[i].idx[item_type.other()];
-        let other_visited = &mut visited[item_type.other()][other_idx];
-        if *other_visited {
-          None
-        } else {
-          *other_visited = true;
+        if visited[item_type.other()].visit(other_idx) {
           callback(
             Node {
               item_type: item_type.other(),
@@ -225,6 +312,8 @@ fn traversal_step(
             dist,
           );
           Some(other_idx)
+        } else {
+          None
         }
       });
     other_idxs.add_items(dist, new_idxs);
@@ -234,7 +323,7 @@ fn traversal_step(
 
 pub fn projected_traverse<T: ConnectionStrength>(
   component: &mut Vec<usize>,
-  visited: &mut Vec<bool>,
+  visited: &mut impl VisitedMark,
   projected_graph: &ProjectedGraph<T>,
   limit: Option<usize>,
   callback: impl FnMut(usize, usize),
@@ -244,7 +333,7 @@ pub fn projected_traverse<T: ConnectionStrength>(
 
 pub fn projected_traverse_dist<T: ConnectionStrength>(
   component: &mut IdxDist,
-  visited: &mut Vec<bool>,
+  visited: &mut impl VisitedMark,
   projected_graph: &ProjectedGraph<T>,
   limit: Option<usize>,
   callback: impl FnMut(usize, usize),
@@ -256,7 +345,7 @@ pub fn projected_traverse_dist<T: ConnectionStrength>(
 
 fn projected_traverse_gen<T: ConnectionStrength>(
   component: &mut impl ComponentAccess,
-  visited: &mut Vec<bool>,
+  visited: &mut impl VisitedMark,
   projected_graph: &ProjectedGraph<T>,
   limit: Option<usize>,
   mut callback: impl FnMut(usize, usize),
@@ -264,7 +353,7 @@ fn projected_traverse_gen<T: ConnectionStrength>(
   // one item
   assert!(component.idxs().len() <= 1);
   // all items are visited
-  assert!(component.idxs().iter().all(|&i| visited[i]));
+  assert!(component.idxs().iter().all(|&i| visited.is_visited(i)));
 
   let mut start = 0;
   let mut dist = 0;
@@ -298,7 +387,7 @@ fn projected_traverse_gen<T: ConnectionStrength>(
 fn projected_traversal_step<T: ConnectionStrength>(
   dist: usize,
   start: &mut usize,
-  visited: &mut Vec<bool>,
+  visited: &mut impl VisitedMark,
   component: &mut impl ComponentAccess,
   projected_graph: &ProjectedGraph<T>,
   callback: &mut impl FnMut(usize, usize),
@@ -313,13 +402,11 @@ fn projected_traversal_step<T: ConnectionStrength>(
         .cloned()
         .find(|&other_idx| other_idx != idx)
         .unwrap();
-      let other_visited = &mut visited[other_idx];
-      if *other_visited {
-        None
-      } else {
-        *other_visited = true;
+      if visited.visit(other_idx) {
         callback(other_idx, dist);
         Some(other_idx)
+      } else {
+        None
       }
     });
     component.add_items(dist, new_idxs);
@@ -327,6 +414,135 @@ fn projected_traversal_step<T: ConnectionStrength>(
   *start = end;
 }
 
+/// Reusable state for repeated traversals over the same dataset.
+///
+/// Resetting between traversals is O(1) (see `EpochVisited`) and the
+/// component buffers keep their allocation.
+pub struct TraversalWorkspace {
+  visited: UserRepoPair<EpochVisited>,
+  component: ComponentDists,
+}
+
+impl TraversalWorkspace {
+  #[must_use]
+  pub fn new(lens: UserRepoPair<usize>) -> Self {
+    Self {
+      visited: lens.map(EpochVisited::new),
+      component: Default::default(),
+    }
+  }
+
+  /// Same as `traverse_dist` starting from a single node. The returned
+  /// component is only valid until the next traversal.
+  pub fn traverse(
+    &mut self,
+    start: Node,
+    dataset: &Dataset,
+    limit: Option<usize>,
+    callback: impl FnMut(Node, usize),
+  ) -> &ComponentDists {
+    debug_assert_eq!(
+      self.visited.as_ref().map(|v| v.stamps.len()),
+      dataset.lens()
+    );
+
+    for visited in self.visited.as_mut() {
+      visited.clear();
+    }
+    for idxs in self.component.as_mut() {
+      idxs.clear();
+    }
+
+    start.set_visited(&mut self.visited);
+    self.component[start.item_type].add_items(0, iter::once(start.idx));
+
+    traverse_dist(
+      &mut self.component,
+      &mut self.visited,
+      dataset,
+      limit,
+      callback,
+    );
+
+    &self.component
+  }
+}
+
+/// Workspaces shared between rayon threads. At most one workspace is alive
+/// per thread running a traversal, so the allocation happens once per thread
+/// instead of once per traversal.
+pub struct WorkspacePool<W, F> {
+  make: F,
+  free: Mutex<Vec<W>>,
+}
+
+impl<W, F: Fn() -> W> WorkspacePool<W, F> {
+  #[must_use]
+  pub fn new(make: F) -> Self {
+    Self {
+      make,
+      free: Default::default(),
+    }
+  }
+
+  pub fn with<R>(&self, f: impl FnOnce(&mut W) -> R) -> R {
+    let workspace = self.free.lock().unwrap().pop();
+    let mut workspace = workspace.unwrap_or_else(&self.make);
+    let out = f(&mut workspace);
+    self.free.lock().unwrap().push(workspace);
+    out
+  }
+
+  /// Every workspace which was created (at most one per thread which used
+  /// the pool at the same time).
+  #[must_use]
+  pub fn into_workspaces(self) -> Vec<W> {
+    self.free.into_inner().unwrap()
+  }
+}
+
+/// `TraversalWorkspace` for a projected graph.
+pub struct ProjectedTraversalWorkspace {
+  visited: EpochVisited,
+  component: IdxDist,
+}
+
+impl ProjectedTraversalWorkspace {
+  #[must_use]
+  pub fn new(num_items: usize) -> Self {
+    Self {
+      visited: EpochVisited::new(num_items),
+      component: Default::default(),
+    }
+  }
+
+  /// Same as `projected_traverse_dist` starting from a single node. The
+  /// returned component is only valid until the next traversal.
+  pub fn traverse<T: ConnectionStrength>(
+    &mut self,
+    start: usize,
+    projected_graph: &ProjectedGraph<T>,
+    limit: Option<usize>,
+    callback: impl FnMut(usize, usize),
+  ) -> &IdxDist {
+    self.visited.clear();
+    self.component.clear();
+
+    self.visited.visit(start);
+    self.component.add_items(0, iter::once(start));
+
+    projected_traverse_dist(
+      &mut self.component,
+      &mut self.visited,
+      projected_graph,
+      limit,
+      callback,
+    );
+
+    &self.component
+  }
+}
+
 #[cfg(test)]
 pub(super) mod test {
   use super::*;
@@ -741,4 +957,46 @@ pub(super) mod test {
       },
     );
   }
+
+  #[test]
+  fn workspace_reuse() {
+    let datasets = vec![
+      single_user_dataset(),
+      single_repo_dataset(),
+      small_disconnected_dataset(3),
+      fully_connected_dataset(8),
+      two_dense_components_dataset(),
+      two_dense_components_several_disconnected_dataset(),
+    ];
+
+    for dataset in &datasets {
+      let mut workspace = TraversalWorkspace::new(dataset.lens());
+      // go through every start twice to make sure resets work
+      for _ in 0..2 {
+        for (item_type, len) in dataset.lens().iter_with() {
+          for idx in 0..len {
+            let start = Node { item_type, idx };
+            for &limit in &[None, Some(1), Some(3)] {
+              let mut expected = ComponentDists::from(start);
+              let mut visited = default_visited(dataset);
+              start.set_visited(&mut visited);
+              traverse_dist(
+                &mut expected,
+                &mut visited,
+                dataset,
+                limit,
+                |_, _| {},
+              );
+
+              let mut actual =
+                workspace.traverse(start, dataset, limit, |_, _| {}).clone();
+              sort_component(&mut actual);
+              sort_component(&mut expected);
+              assert_eq!(actual, expected);
+            }
+          }
+        }
+      }
+    }
+  }
 }
@@ -0,0 +1,143 @@
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lock free disjoint sets which can be unioned from many threads at once.
+///
+/// Roots are always linked from the higher index to the lower index, so the
+/// root of a set is its minimum element.
+pub struct ConcurrentUnionFind {
+  parents: Vec<AtomicUsize>,
+}
+
+impl ConcurrentUnionFind {
+  #[must_use]
+  pub fn new(len: usize) -> Self {
+    Self {
+      parents: (0..len).into_par_iter().map(AtomicUsize::new).collect(),
+    }
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.parents.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.parents.is_empty()
+  }
+
+  fn parent(&self, i: usize) -> usize {
+    self.parents[i].load(Ordering::Acquire)
+  }
+
+  /// Uses path halving.
+  #[must_use]
+  pub fn find(&self, mut i: usize) -> usize {
+    loop {
+      let parent = self.parent(i);
+      if parent == i {
+        return i;
+      }
+      let grandparent = self.parent(parent);
+      if grandparent != parent {
+        // failure is fine, someone else already shortened the path
+        let _ = self.parents[i].compare_exchange_weak(
+          parent,
+          grandparent,
+          Ordering::AcqRel,
+          Ordering::Acquire,
+        );
+      }
+      i = grandparent;
+    }
+  }
+
+  pub fn union(&self, a: usize, b: usize) {
+    let (mut a, mut b) = (a, b);
+    loop {
+      a = self.find(a);
+      b = self.find(b);
+      if a == b {
+        return;
+      }
+      let (low, high) = if a < b { (a, b) } else { (b, a) };
+      if self.parents[high]
+        .compare_exchange(high, low, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+      {
+        return;
+      }
+    }
+  }
+
+  /// Root of every element (in parallel). Should only be called once all
+  /// unions are done.
+  #[must_use]
+  pub fn roots(&self) -> Vec<usize> {
+    (0..self.len())
+      .into_par_iter()
+      .map(|i| self.find(i))
+      .collect()
+  }
+}
+
+/// Single threaded disjoint sets (union by size) which keep track of set
+/// sizes, for adding edges one at a time.
+#[derive(Clone, Debug)]
+pub struct UnionFind {
+  parents: Vec<usize>,
+  sizes: Vec<usize>,
+}
+
+impl UnionFind {
+  #[must_use]
+  pub fn new(len: usize) -> Self {
+    Self {
+      parents: (0..len).collect(),
+      sizes: vec![1; len],
+    }
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.parents.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.parents.is_empty()
+  }
+
+  /// Uses path halving.
+  pub fn find(&mut self, mut i: usize) -> usize {
+    while self.parents[i] != i {
+      self.parents[i] = self.parents[self.parents[i]];
+      i = self.parents[i];
+    }
+    i
+  }
+
+  /// Returns the root of the merged set if `a` and `b` were in different
+  /// sets.
+  pub fn union(&mut self, a: usize, b: usize) -> Option<usize> {
+    let (a, b) = (self.find(a), self.find(b));
+    if a == b {
+      return None;
+    }
+    let (small, large) = if self.sizes[a] < self.sizes[b] {
+      (a, b)
+    } else {
+      (b, a)
+    };
+    self.parents[small] = large;
+    self.sizes[large] += self.sizes[small];
+    Some(large)
+  }
+
+  /// Size of the set with root `root`.
+  #[must_use]
+  pub fn size(&self, root: usize) -> usize {
+    self.sizes[root]
+  }
+}